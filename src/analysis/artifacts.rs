@@ -0,0 +1,214 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+use crate::analysis::ast::source_ast;
+use crate::analysis::definitions::{
+    build_definition_index, extract_definitions_from_solc_json, DefinitionIndex, SourceFile,
+    SourceTable, DEFINITION_MAP,
+};
+use crate::project::remappings::parse_remappings;
+use crate::util::imports::{resolve_sources_recursive, source_unit_name, virtual_path};
+use crate::util::log::log_to_file;
+use crate::util::text::strip_bom;
+use crate::util::uri::{canonical_path, path_to_uri};
+
+/// Foundry's default artifact directory
+const FOUNDRY_OUT_DIR: &str = "out";
+
+/// Where Hardhat keeps the standard-json input and output of each compile
+const HARDHAT_BUILD_INFO_DIR: &str = "artifacts/build-info";
+
+/// Load the AST of `virtual_path` from its Foundry artifact in `out/<File>.sol/*.json`.
+/// Returns `None` if no artifact exists or the source is newer than the artifact.
+fn read_foundry_artifact_ast(project_root: &Path, virtual_path: &str) -> Option<Value> {
+    let source_path = project_root.join(virtual_path);
+    let file_name = source_path.file_name()?;
+    let artifact_dir = project_root.join(FOUNDRY_OUT_DIR).join(file_name);
+    let source_mtime = fs::metadata(&source_path).and_then(|m| m.modified()).ok()?;
+
+    for entry in fs::read_dir(&artifact_dir).ok()?.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+
+        let artifact_mtime = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let Ok(raw) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let Ok(artifact) = serde_json::from_str::<Value>(&raw) else {
+            continue;
+        };
+        let Some(ast) = source_ast(&artifact) else {
+            continue;
+        };
+        if ast.get("absolutePath").and_then(|v| v.as_str()) != Some(virtual_path) {
+            continue; // same file name, different directory
+        }
+
+        if source_mtime > artifact_mtime {
+            log_to_file(&format!("[artifacts] Stale artifact for {}", virtual_path));
+            return None;
+        }
+        return Some(ast.clone());
+    }
+
+    None
+}
+
+/// Solc source index of a source unit AST, taken from its `src` span
+fn ast_file_index(ast: &Value) -> Option<usize> {
+    ast.get("src")?.as_str()?.rsplit(':').next()?.parse().ok()
+}
+
+/// Populate `DEFINITION_MAP` for `source_path` and its imports from Foundry build artifacts.
+/// Returns `false` (leaving the map untouched) when the entry file's artifact is missing
+/// or stale, so the caller can fall back to compiling with solc.
+pub fn load_definitions_from_artifacts(
+    project_root: &Path,
+    source_path: &Path,
+    workspace_root: Option<&Path>,
+) -> bool {
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    let remappings = parse_remappings(project_root, workspace_root);
+    let sources = resolve_sources_recursive(project_root, source_path, &remappings, &mut visited);
+
+    let entry_virtual = source_unit_name(source_path, project_root, &remappings);
+    if !sources.contains_key(&entry_virtual) {
+        return false;
+    }
+
+    let mut defs_per_file: HashMap<String, DefinitionIndex> = HashMap::new();
+    for (virtual_path, content) in &sources {
+        let Some(ast) = read_foundry_artifact_ast(project_root, virtual_path) else {
+            if *virtual_path == entry_virtual {
+                return false;
+            }
+            continue;
+        };
+        let Some(file_index) = ast_file_index(&ast) else {
+            continue;
+        };
+
+        let Some(uri) = path_to_uri(&canonical_path(&project_root.join(virtual_path))) else {
+            continue;
+        };
+
+        let mut table = SourceTable::new();
+        table.insert(file_index, SourceFile { uri: uri.clone(), content: content.clone() });
+        defs_per_file.insert(uri, build_definition_index(&ast, &table));
+    }
+
+    log_to_file(&format!(
+        "[artifacts] Loaded {} definition indices from {}",
+        defs_per_file.len(),
+        FOUNDRY_OUT_DIR
+    ));
+
+    if let Ok(mut map) = DEFINITION_MAP.lock() {
+        map.extend(defs_per_file);
+    }
+    true
+}
+
+/// Hardhat source name of `source_path`: its path relative to the project
+/// root, or the package path for a file under `node_modules`
+fn hardhat_source_name(project_root: &Path, source_path: &Path) -> String {
+    let name = virtual_path(source_path, project_root);
+    match name.strip_prefix("node_modules/") {
+        Some(package_path) => package_path.to_string(),
+        None => name,
+    }
+}
+
+/// Physical file of a Hardhat source name, the reverse of [`hardhat_source_name`]
+fn hardhat_source_path(project_root: &Path, source_name: &str) -> PathBuf {
+    let local = project_root.join(source_name);
+    if local.exists() {
+        local
+    } else {
+        project_root.join("node_modules").join(source_name)
+    }
+}
+
+/// solc output of the newest build-info in `artifacts/build-info` that
+/// compiled `source_name`, and when it was written. Hardhat 2 keeps the output
+/// in `<id>.json` next to the input, Hardhat 3 in a separate `<id>.output.json`.
+fn read_hardhat_build_info(project_root: &Path, source_name: &str) -> Option<(Value, SystemTime)> {
+    let mut build_infos: Vec<(SystemTime, PathBuf)> =
+        fs::read_dir(project_root.join(HARDHAT_BUILD_INFO_DIR))
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("json"))
+            .filter_map(|path| Some((fs::metadata(&path).and_then(|m| m.modified()).ok()?, path)))
+            .collect();
+    // newest first
+    build_infos.sort_by_key(|(built, _)| Reverse(*built));
+
+    for (built, path) in build_infos {
+        let Ok(raw) = fs::read(&path) else {
+            continue;
+        };
+        let Ok(mut build_info) = serde_json::from_slice::<Value>(&raw) else {
+            continue;
+        };
+        let Some(output) = build_info.get_mut("output").map(Value::take) else {
+            continue;
+        };
+        if output["sources"].get(source_name).is_some() {
+            return Some((output, built));
+        }
+    }
+
+    None
+}
+
+/// Populate `DEFINITION_MAP` for `source_path` and every file compiled with it
+/// from a Hardhat build-info. Returns `false` (leaving the map untouched) when
+/// no build-info covers the file or one of its sources changed since, so the
+/// caller can fall back to compiling with solc.
+pub fn load_definitions_from_build_info(project_root: &Path, source_path: &Path) -> bool {
+    let source_name = hardhat_source_name(project_root, source_path);
+    let Some((output, built)) = read_hardhat_build_info(project_root, &source_name) else {
+        return false;
+    };
+
+    let mut table = SourceTable::new();
+    for (name, source) in output["sources"].as_object().into_iter().flatten() {
+        let Some(id) = source["id"].as_u64() else {
+            continue;
+        };
+        let path = canonical_path(&hardhat_source_path(project_root, name));
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified.is_some_and(|modified| modified > built) {
+            log_to_file(&format!("[artifacts] Stale build-info for {}", name));
+            return false;
+        }
+        let (Some(uri), Ok(content)) = (path_to_uri(&path), fs::read_to_string(&path)) else {
+            continue;
+        };
+        let content = strip_bom(&content).to_string();
+        table.insert(id as usize, SourceFile { uri, content });
+    }
+
+    let defs_per_file = extract_definitions_from_solc_json(&output, &table, project_root);
+    log_to_file(&format!(
+        "[artifacts] Loaded {} definition indices from {}",
+        defs_per_file.len(),
+        HARDHAT_BUILD_INFO_DIR
+    ));
+
+    if let Ok(mut map) = DEFINITION_MAP.lock() {
+        map.extend(defs_per_file);
+    }
+    true
+}
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::analysis::definitions::SourceTable;
+
+/// A compiled source unit: its AST and the exact text solc saw
+#[derive(Debug, Clone)]
+pub struct SourceUnit {
+    pub ast: Value,
+    /// The text solc was given, which for an open document is the editor's
+    /// buffer rather than the file on disk; AST offsets index into it
+    pub content: String,
+    /// solc source index of this file in the compile that produced `ast`
+    pub file_index: usize,
+}
+
+/// Global map: file URI → last compiled source unit
+pub static AST_MAP: Lazy<Mutex<HashMap<String, SourceUnit>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Keys a source's AST has been emitted under across solc versions, newest first
+const AST_KEYS: [&str; 3] = ["ast", "AST", "legacyAST"];
+
+/// The compact-format AST of one entry of solc's `sources` output. Older
+/// compilers may put it under `legacyAST`; an AST in the pre-0.4.12 legacy
+/// format (`name`/`children` instead of `nodeType`/`nodes`) is not usable.
+pub fn source_ast(file_data: &Value) -> Option<&Value> {
+    AST_KEYS
+        .iter()
+        .filter_map(|key| file_data.get(*key))
+        .find(|ast| ast.get("nodeType").is_some())
+}
+
+/// Collect the source units of a solc output, keyed by file URI
+pub fn extract_source_units(json: &Value, table: &SourceTable) -> HashMap<String, SourceUnit> {
+    let mut units = HashMap::new();
+
+    if let Some(sources) = json.get("sources").and_then(|v| v.as_object()) {
+        for file_data in sources.values() {
+            let Some(ast) = source_ast(file_data) else {
+                continue;
+            };
+            let Some(file_index) = file_data.get("id").and_then(|v| v.as_u64()) else {
+                continue;
+            };
+            let Some(file) = table.get(&(file_index as usize)) else {
+                continue;
+            };
+            units.insert(
+                file.uri.clone(),
+                SourceUnit {
+                    ast: ast.clone(),
+                    content: file.content.clone(),
+                    file_index: file_index as usize,
+                },
+            );
+        }
+    }
+
+    units
+}
+
+/// URIs of the files `uri` imports directly, among the compiled units
+pub fn imported_uris(units: &HashMap<String, SourceUnit>, uri: &str) -> HashSet<String> {
+    let Some(unit) = units.get(uri) else {
+        return HashSet::new();
+    };
+    let imported: HashSet<&str> = unit
+        .ast
+        .get("nodes")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|node| node.get("nodeType").and_then(|v| v.as_str()) == Some("ImportDirective"))
+        .filter_map(|node| node.get("absolutePath").and_then(|v| v.as_str()))
+        .collect();
+
+    units
+        .iter()
+        .filter(|(_, other)| {
+            other
+                .ast
+                .get("absolutePath")
+                .and_then(|v| v.as_str())
+                .is_some_and(|path| imported.contains(path))
+        })
+        .map(|(uri, _)| uri.clone())
+        .collect()
+}
+
+/// Parse a solc `src` span into `(start, end, file_index)` byte offsets
+pub fn parse_src_span(src: &str) -> Option<(usize, usize, usize)> {
+    let mut parts = src.split(':');
+    let start = parts.next()?.parse::<usize>().ok()?;
+    let length = parts.next()?.parse::<usize>().ok()?;
+    let file_index = parts.next()?.parse::<usize>().ok()?;
+    Some((start, start + length, file_index))
+}
+
+/// Spans `[start, end)` of every AST node in `file_index` that contains `offset`,
+/// ordered from the outermost node to the innermost one
+pub fn enclosing_spans(ast: &Value, file_index: usize, offset: usize) -> Vec<(usize, usize)> {
+    fn visit(node: &Value, file_index: usize, offset: usize, acc: &mut Vec<(usize, usize)>) {
+        if let Some(obj) = node.as_object() {
+            if let Some((start, end, file)) = obj
+                .get("src")
+                .and_then(|v| v.as_str())
+                .and_then(parse_src_span)
+                && file == file_index
+                && start <= offset
+                && offset <= end
+            {
+                acc.push((start, end));
+            }
+            for value in obj.values() {
+                visit(value, file_index, offset, acc);
+            }
+        } else if let Some(array) = node.as_array() {
+            for value in array {
+                visit(value, file_index, offset, acc);
+            }
+        }
+    }
+
+    let mut spans = Vec::new();
+    visit(ast, file_index, offset, &mut spans);
+
+    // outermost first; identical spans (e.g. an expression statement and its expression) collapse
+    spans.sort_by(|a, b| (b.1 - b.0).cmp(&(a.1 - a.0)).then(a.0.cmp(&b.0)));
+    spans.dedup();
+    spans
+}
+
+/// A function or modifier declared in a source unit, with byte spans
+#[derive(Debug, Clone)]
+pub struct FunctionNode {
+    /// Declared name, or `constructor`/`fallback`/`receive` for unnamed ones
+    pub name: String,
+    pub span: (usize, usize),
+    pub name_span: (usize, usize),
+}
+
+/// All function and modifier definitions belonging to this unit's own file
+pub fn function_nodes(unit: &SourceUnit) -> Vec<FunctionNode> {
+    fn visit(node: &Value, file_index: usize, acc: &mut Vec<FunctionNode>) {
+        if let Some(obj) = node.as_object() {
+            let node_type = obj.get("nodeType").and_then(|v| v.as_str());
+            if matches!(node_type, Some("FunctionDefinition") | Some("ModifierDefinition"))
+                && let Some((start, end, file)) =
+                    obj.get("src").and_then(|v| v.as_str()).and_then(parse_src_span)
+                && file == file_index
+            {
+                let name = match obj.get("name").and_then(|v| v.as_str()) {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => obj
+                        .get("kind")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("function")
+                        .to_string(),
+                };
+                let name_span = obj
+                    .get("nameLocation")
+                    .and_then(|v| v.as_str())
+                    .and_then(parse_src_span)
+                    .filter(|(_, _, f)| *f == file)
+                    .map(|(s, e, _)| (s, e))
+                    .unwrap_or((start, end));
+                acc.push(FunctionNode {
+                    name,
+                    span: (start, end),
+                    name_span,
+                });
+            }
+            for value in obj.values() {
+                visit(value, file_index, acc);
+            }
+        } else if let Some(array) = node.as_array() {
+            for value in array {
+                visit(value, file_index, acc);
+            }
+        }
+    }
+
+    let mut nodes = Vec::new();
+    visit(&unit.ast, unit.file_index, &mut nodes);
+    nodes
+}
+
+/// Innermost function or modifier whose body contains `offset`
+pub fn enclosing_function(unit: &SourceUnit, offset: usize) -> Option<FunctionNode> {
+    function_nodes(unit)
+        .into_iter()
+        .filter(|f| f.span.0 <= offset && offset <= f.span.1)
+        .min_by_key(|f| f.span.1 - f.span.0)
+}
+
+/// The declaration node in this unit's own file whose name starts at
+/// `name_start`, matching `nameLocation` (or `src` when solc omits it)
+pub fn declaration_at(unit: &SourceUnit, name_start: usize) -> Option<&Value> {
+    fn visit(node: &Value, file_index: usize, name_start: usize) -> Option<&Value> {
+        if let Some(obj) = node.as_object() {
+            let span = obj
+                .get("nameLocation")
+                .or_else(|| obj.get("src"))
+                .and_then(|v| v.as_str())
+                .and_then(parse_src_span);
+            let is_declaration = obj.get("nodeType").and_then(|v| v.as_str()).is_some_and(|t| {
+                t.ends_with("Definition") || t == "VariableDeclaration" || t == "EnumValue"
+            });
+            if is_declaration
+                && span.is_some_and(|(start, _, file)| start == name_start && file == file_index)
+            {
+                return Some(node);
+            }
+            obj.values().find_map(|value| visit(value, file_index, name_start))
+        } else if let Some(array) = node.as_array() {
+            array.iter().find_map(|value| visit(value, file_index, name_start))
+        } else {
+            None
+        }
+    }
+
+    visit(&unit.ast, unit.file_index, name_start)
+}
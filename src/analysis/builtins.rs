@@ -0,0 +1,74 @@
+/// A global variable, function or unit the language provides
+pub struct Builtin {
+    /// `msg.sender` or `keccak256`
+    pub name: &'static str,
+    pub signature: &'static str,
+    pub description: &'static str,
+}
+
+const fn builtin(name: &'static str, signature: &'static str, description: &'static str) -> Builtin {
+    Builtin { name, signature, description }
+}
+
+/// Globals, their members and units, as documented under "Units and Globally
+/// Available Variables" in the Solidity docs
+static BUILTINS: &[Builtin] = &[
+    builtin("msg", "msg", "Properties of the current call: `sender`, `value`, `data`, `sig`."),
+    builtin("msg.sender", "address msg.sender", "Sender of the current call (the immediate caller, which may be a contract)."),
+    builtin("msg.value", "uint256 msg.value", "Number of wei sent with the call."),
+    builtin("msg.data", "bytes calldata msg.data", "Complete calldata."),
+    builtin("msg.sig", "bytes4 msg.sig", "First four bytes of the calldata (the function selector)."),
+    builtin("block", "block", "Properties of the current block: `timestamp`, `number`, `chainid`, `basefee`, ..."),
+    builtin("block.timestamp", "uint256 block.timestamp", "Current block timestamp in seconds since the Unix epoch. Validators have some influence over it."),
+    builtin("block.number", "uint256 block.number", "Current block number."),
+    builtin("block.chainid", "uint256 block.chainid", "Current chain id."),
+    builtin("block.coinbase", "address payable block.coinbase", "Address of the current block's proposer."),
+    builtin("block.basefee", "uint256 block.basefee", "Current block's base fee (EIP-3198 and EIP-1559)."),
+    builtin("block.blobbasefee", "uint256 block.blobbasefee", "Current block's blob base fee (EIP-7516 and EIP-4844)."),
+    builtin("block.gaslimit", "uint256 block.gaslimit", "Current block's gas limit."),
+    builtin("block.prevrandao", "uint256 block.prevrandao", "Random number provided by the beacon chain (EIP-4399). Not safe as a source of randomness against validators."),
+    builtin("block.difficulty", "uint256 block.difficulty", "Deprecated alias of `block.prevrandao` since the Paris upgrade."),
+    builtin("tx", "tx", "Properties of the transaction: `origin`, `gasprice`."),
+    builtin("tx.origin", "address tx.origin", "Sender of the transaction (the externally owned account that started the call chain). Don't use it for authorization."),
+    builtin("tx.gasprice", "uint256 tx.gasprice", "Gas price of the transaction."),
+    builtin("gasleft", "function gasleft() returns (uint256)", "Remaining gas."),
+    builtin("blockhash", "function blockhash(uint256 blockNumber) returns (bytes32)", "Hash of one of the 256 most recent blocks; zero otherwise."),
+    builtin("blobhash", "function blobhash(uint256 index) returns (bytes32)", "Versioned hash of the `index`-th blob of the transaction (EIP-4844)."),
+    builtin("abi", "abi", "ABI encoding and decoding: `encode`, `encodePacked`, `encodeWithSelector`, `encodeWithSignature`, `encodeCall`, `decode`."),
+    builtin("abi.encode", "function abi.encode(...) returns (bytes memory)", "ABI-encodes the arguments."),
+    builtin("abi.encodePacked", "function abi.encodePacked(...) returns (bytes memory)", "Non-standard packed encoding of the arguments. Dynamic types next to each other can collide when hashed."),
+    builtin("abi.encodeWithSelector", "function abi.encodeWithSelector(bytes4 selector, ...) returns (bytes memory)", "ABI-encodes the arguments after the given function selector."),
+    builtin("abi.encodeWithSignature", "function abi.encodeWithSignature(string memory signature, ...) returns (bytes memory)", "Same as `abi.encodeWithSelector(bytes4(keccak256(bytes(signature))), ...)`."),
+    builtin("abi.encodeCall", "function abi.encodeCall(function functionPointer, (...)) returns (bytes memory)", "ABI-encodes a call to `functionPointer`, type-checking the arguments against it."),
+    builtin("abi.decode", "function abi.decode(bytes memory encodedData, (...)) returns (...)", "ABI-decodes the data into the types given in the second argument."),
+    builtin("keccak256", "function keccak256(bytes memory) returns (bytes32)", "Keccak-256 hash of the input."),
+    builtin("sha256", "function sha256(bytes memory) returns (bytes32)", "SHA-256 hash of the input."),
+    builtin("ripemd160", "function ripemd160(bytes memory) returns (bytes20)", "RIPEMD-160 hash of the input."),
+    builtin("ecrecover", "function ecrecover(bytes32 hash, uint8 v, bytes32 r, bytes32 s) returns (address)", "Address associated with the public key from the signature, or zero on error. Signatures are malleable; prefer a library that checks `s`."),
+    builtin("addmod", "function addmod(uint256 x, uint256 y, uint256 k) returns (uint256)", "`(x + y) % k` with the addition at arbitrary precision. Reverts if `k` is zero."),
+    builtin("mulmod", "function mulmod(uint256 x, uint256 y, uint256 k) returns (uint256)", "`(x * y) % k` with the multiplication at arbitrary precision. Reverts if `k` is zero."),
+    builtin("require", "function require(bool condition, string memory message)", "Reverts if `condition` is false, for invalid inputs or external conditions. Also accepts a custom error since 0.8.26."),
+    builtin("assert", "function assert(bool condition)", "Panics if `condition` is false, for internal errors that should never happen."),
+    builtin("revert", "function revert(string memory reason)", "Aborts execution and reverts state changes."),
+    builtin("selfdestruct", "function selfdestruct(address payable recipient)", "Sends all Ether to `recipient`. Since Cancun it only destroys the contract when called in the transaction that created it."),
+    builtin("this", "this", "The current contract, explicitly convertible to `address`."),
+    builtin("super", "super", "The contract one level higher in the inheritance hierarchy."),
+    builtin("wei", "wei", "Ether unit: `1 wei == 1`."),
+    builtin("gwei", "gwei", "Ether unit: `1 gwei == 1e9`."),
+    builtin("ether", "ether", "Ether unit: `1 ether == 1e18`."),
+    builtin("seconds", "seconds", "Time unit: `1 seconds == 1`."),
+    builtin("minutes", "minutes", "Time unit: `1 minutes == 60 seconds`."),
+    builtin("hours", "hours", "Time unit: `1 hours == 60 minutes`."),
+    builtin("days", "days", "Time unit: `1 days == 24 hours`."),
+    builtin("weeks", "weeks", "Time unit: `1 weeks == 7 days`."),
+];
+
+/// The builtin `name` refers to, where `base` is the identifier before the
+/// dot when `name` is a member access (`msg` for `msg.sender`)
+pub fn lookup(base: Option<&str>, name: &str) -> Option<&'static Builtin> {
+    let qualified = match base {
+        Some(base) => format!("{}.{}", base, name),
+        None => name.to_string(),
+    };
+    BUILTINS.iter().find(|b| b.name == qualified)
+}
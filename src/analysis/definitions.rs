@@ -1,20 +1,43 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Mutex;
 use std::path::Path;
 
-use lsp_types::{Location, Url};
+use lsp_types::{Location, Range, Url};
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::analysis::ast::source_ast;
+use crate::util::log::log_to_file;
 use crate::util::position::byte_offset_to_position;
+use crate::util::text::strip_bom;
+use crate::util::uri::{canonical_path, path_to_uri};
 use std::fs;
 
 /// Structure for a single definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Definition {
     pub name: String,
+    /// Span of the whole declaration
     pub location: Location,
+    /// Span of just the declared name, when solc reports `nameLocation`
+    pub name_range: Option<Range>,
     pub kind: String, // Contract, Function, Variable, Struct, etc.
+    /// Canonical name of the enclosing struct (e.g. `Vault.Position`), for
+    /// struct member declarations
+    pub container: Option<String>,
+    /// solc `typeDescriptions.typeString` of variable declarations
+    pub type_string: Option<String>,
+    /// For parameters and local variables, the span of the function or
+    /// modifier they are declared in; they are only visible inside it
+    #[serde(default)]
+    pub scope: Option<Range>,
+}
+
+/// Index key for a struct member, e.g. `Vault.Position.amount`. Structs are
+/// named canonically so same-named structs of different contracts don't mix.
+pub fn qualified_member_key(struct_name: &str, field: &str) -> String {
+    format!("{}.{}", struct_name, field)
 }
 
 /// Map from identifier name → list of definitions
@@ -24,17 +47,36 @@ pub type DefinitionIndex = HashMap<String, Vec<Definition>>;
 pub static DEFINITION_MAP: Lazy<Mutex<HashMap<String, DefinitionIndex>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// A source file as referenced by the file index of a solc `src` span
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub uri: String,
+    pub content: String,
+}
+
+/// Map from solc source index → source file
+pub type SourceTable = HashMap<usize, SourceFile>;
+
 /// Recursively walk AST and extract definitions into the index
-pub fn build_definition_index(ast: &Value, file_uri: &str) -> DefinitionIndex {
+pub fn build_definition_index(ast: &Value, sources: &SourceTable) -> DefinitionIndex {
     let mut index = DefinitionIndex::new();
-    visit_node(ast, file_uri, &mut index);
+    visit_node(ast, sources, None, None, &mut index);
     index
 }
 
-/// Visit AST node recursively
-fn visit_node(node: &Value, file_uri: &str, index: &mut DefinitionIndex) {
+/// Visit AST node recursively. `container` is the canonical name of the struct
+/// whose members are being visited, if any; `scope` the span of the
+/// function or modifier they are in.
+fn visit_node(
+    node: &Value,
+    sources: &SourceTable,
+    container: Option<&str>,
+    scope: Option<Range>,
+    index: &mut DefinitionIndex,
+) {
     if let Some(obj) = node.as_object() {
-        if let Some(node_type) = obj.get("nodeType").and_then(|v| v.as_str()) {
+        let node_type = obj.get("nodeType").and_then(|v| v.as_str());
+        if let Some(node_type) = node_type {
             match node_type {
                 "ContractDefinition"
                 | "InterfaceDefinition"
@@ -48,36 +90,81 @@ fn visit_node(node: &Value, file_uri: &str, index: &mut DefinitionIndex) {
                 | "EnumValue"
                 | "UserDefinedValueTypeDefinition"
                 | "VariableDeclaration" => {
-                    if let Some(name) = obj.get("name").and_then(|v| v.as_str()) {
-                        if let Some(src) = obj.get("src").and_then(|v| v.as_str()) {
-                            if let Some(location) = parse_solc_src(src, file_uri) {
-                                let def = Definition {
-                                    name: name.to_string(),
-                                    location,
-                                    kind: node_type.to_string(),
-                                };
-                                index.entry(name.to_string()).or_default().push(def);
-                            }
+                    if let Some(name) = obj.get("name").and_then(|v| v.as_str())
+                        && let Some(src) = obj.get("src").and_then(|v| v.as_str())
+                        && let Some(location) = parse_solc_src(src, sources)
+                    {
+                        let is_member = node_type == "VariableDeclaration" && container.is_some();
+                        let def = Definition {
+                            name: name.to_string(),
+                            name_range: obj
+                                .get("nameLocation")
+                                .and_then(|v| v.as_str())
+                                .and_then(|src| parse_solc_src(src, sources))
+                                .filter(|name| name.uri == location.uri)
+                                .map(|name| name.range),
+                            location,
+                            kind: node_type.to_string(),
+                            container: container.filter(|_| is_member).map(str::to_string),
+                            type_string: obj
+                                .get("typeDescriptions")
+                                .and_then(|t| t.get("typeString"))
+                                .and_then(|v| v.as_str())
+                                .map(str::to_string),
+                            scope: scope.filter(|_| node_type == "VariableDeclaration"),
+                        };
+                        if let Some(struct_name) = def.container.as_deref() {
+                            index
+                                .entry(qualified_member_key(struct_name, name))
+                                .or_default()
+                                .push(def.clone());
                         }
+                        index.entry(name.to_string()).or_default().push(def);
                     }
                 }
                 _ => {}
             }
         }
 
+        // struct members are visited with the struct as their container
+        let child_container = match node_type {
+            Some("StructDefinition") => obj
+                .get("canonicalName")
+                .or_else(|| obj.get("name"))
+                .and_then(|v| v.as_str()),
+            _ => None,
+        };
+
+        // parameters and locals are scoped to the function or modifier declaring them
+        let child_scope = match node_type {
+            Some("FunctionDefinition" | "ModifierDefinition") => obj
+                .get("src")
+                .and_then(|v| v.as_str())
+                .and_then(|src| parse_solc_src(src, sources))
+                .map(|location| location.range),
+            _ => scope,
+        };
+
         // recurse into all children
         for value in obj.values() {
-            visit_node(value, file_uri, index);
+            visit_node(value, sources, child_container, child_scope, index);
         }
     } else if let Some(array) = node.as_array() {
         for value in array {
-            visit_node(value, file_uri, index);
+            visit_node(value, sources, container, scope, index);
         }
     }
 }
 
-/// Parse solc-style `src`: "start:length:fileIndex" into LSP Location
-fn parse_solc_src(src: &str, file_uri: &str) -> Option<Location> {
+/// Canonical struct name from a solc type string, e.g. `Lib.Position` from
+/// `struct Lib.Position storage ref`
+pub fn struct_name_from_type_string(type_string: &str) -> Option<&str> {
+    type_string.strip_prefix("struct ")?.split_whitespace().next()
+}
+
+/// Parse solc-style `src`: "start:length:fileIndex" into LSP Location,
+/// resolving the file index against the compilation's source table
+fn parse_solc_src(src: &str, sources: &SourceTable) -> Option<Location> {
     let parts: Vec<&str> = src.split(':').collect();
     if parts.len() != 3 {
         return None;
@@ -85,40 +172,240 @@ fn parse_solc_src(src: &str, file_uri: &str) -> Option<Location> {
 
     let start = parts[0].parse::<usize>().ok()?;
     let length = parts[1].parse::<usize>().ok()?;
-    let path = file_uri.strip_prefix("file://")?;
-    let content = fs::read_to_string(path).ok()?;
+    let file_index = parts[2].parse::<usize>().ok()?;
+    let file = sources.get(&file_index)?;
 
-    let start_pos = byte_offset_to_position(&content, start);
-    let end_pos = byte_offset_to_position(&content, start + length);
+    let start_pos = byte_offset_to_position(&file.content, start);
+    let end_pos = byte_offset_to_position(&file.content, start + length);
 
     Some(Location {
-        uri: file_uri.parse().ok()?,
-        range: lsp_types::Range {
+        uri: file.uri.parse().ok()?,
+        range: Range {
             start: start_pos,
             end: end_pos,
         },
     })
 }
 
-/// Extract AST from `solc` JSON output and build per-file definition indices
-pub fn extract_definitions_from_solc_json(json: &Value, project_root: &Path) -> HashMap<String, DefinitionIndex> {
+/// Resolve a solc source unit name (relative to project root) to a file URI;
+/// `None` when the project root isn't absolute
+fn source_name_to_uri(project_root: &Path, file_name: &str) -> Option<String> {
+    path_to_uri(&canonical_path(&project_root.join(file_name)))
+}
+
+/// Build the source index → file table from the `sources[*].id` fields of solc
+/// output. `texts` are the sources solc was given, by unit name, so offsets
+/// are converted against the text it compiled (an unsaved buffer included);
+/// a name missing from them is read from disk.
+pub fn build_source_table(
+    json: &Value,
+    project_root: &Path,
+    texts: &BTreeMap<String, String>,
+) -> SourceTable {
+    let mut table = SourceTable::new();
+
+    if let Some(sources) = json.get("sources").and_then(|v| v.as_object()) {
+        for (file_name, file_data) in sources {
+            let Some(id) = file_data.get("id").and_then(|v| v.as_u64()) else {
+                continue;
+            };
+            let Some(uri) = source_name_to_uri(project_root, file_name) else {
+                continue;
+            };
+            let content = match texts.get(file_name) {
+                Some(text) => text.clone(),
+                // Decode via `Url` so percent-encoded paths (spaces etc.) open correctly
+                None => Url::parse(&uri)
+                    .ok()
+                    .and_then(|u| u.to_file_path().ok())
+                    .and_then(|path| fs::read_to_string(path).ok())
+                    .map(|content| strip_bom(&content).to_string())
+                    .unwrap_or_default(),
+            };
+            table.insert(id as usize, SourceFile { uri, content });
+        }
+    }
+
+    table
+}
+
+/// Extract AST from `solc` JSON output and build per-file definition indices,
+/// resolving `src` spans through the compile's source table
+pub fn extract_definitions_from_solc_json(
+    json: &Value,
+    table: &SourceTable,
+    project_root: &Path,
+) -> HashMap<String, DefinitionIndex> {
     let mut defs_per_file = HashMap::new();
 
     if let Some(sources) = json.get("sources").and_then(|v| v.as_object()) {
         for (file_name, file_data) in sources {
-            if let Some(ast) = file_data.get("ast") {
-                // Resolve relative to project root
-                let joined = project_root.join(file_name);
-                let abs_path = joined.canonicalize().unwrap_or(joined);
-                let uri = Url::from_file_path(&abs_path)
-                    .map(|u| u.to_string())
-                    .unwrap_or_else(|_| format!("file://{}", abs_path.to_string_lossy()));
-
-                let index = build_definition_index(ast, &uri);
-                defs_per_file.insert(uri, index);
-            }
+            let Some(ast) = source_ast(file_data) else {
+                // navigation silently breaks otherwise when solc changes its output shape
+                let keys: Vec<&String> = file_data.as_object().into_iter().flat_map(|o| o.keys()).collect();
+                log_to_file(&format!(
+                    "[ast] No usable AST for {} (keys: {:?}); goto-definition won't cover it",
+                    file_name, keys
+                ));
+                continue;
+            };
+            let Some(uri) = file_data
+                .get("id")
+                .and_then(|v| v.as_u64())
+                .and_then(|id| table.get(&(id as usize)))
+                .map(|file| file.uri.clone())
+                .or_else(|| source_name_to_uri(project_root, file_name))
+            else {
+                continue;
+            };
+
+            let index = build_definition_index(ast, table);
+            defs_per_file.insert(uri, index);
         }
     }
 
     defs_per_file
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn definition_in_path_with_space_resolves_and_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = canonical_path(dir.path()).join("my project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/Foo.sol"), "contract Foo {}\n").unwrap();
+
+        let output = json!({
+            "sources": {
+                "src/Foo.sol": {
+                    "id": 0,
+                    "ast": {
+                        "nodeType": "SourceUnit",
+                        "src": "0:16:0",
+                        "nodes": [{
+                            "nodeType": "ContractDefinition",
+                            "name": "Foo",
+                            "src": "0:15:0",
+                            "nameLocation": "9:3:0",
+                        }],
+                    },
+                },
+            },
+        });
+        let table = build_source_table(&output, &root, &BTreeMap::new());
+        assert_eq!(table[&0].content, "contract Foo {}\n");
+
+        let defs = extract_definitions_from_solc_json(&output, &table, &root);
+        let foo = &defs[&table[&0].uri]["Foo"][0];
+        assert!(foo.location.uri.as_str().contains("my%20project"));
+        assert_eq!(foo.location.uri.to_file_path().unwrap(), root.join("src/Foo.sol"));
+        assert_eq!(foo.name_range.unwrap().start, lsp_types::Position::new(0, 9));
+    }
+
+    #[test]
+    fn offsets_are_converted_against_the_compiled_text_not_the_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = canonical_path(dir.path());
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/Foo.sol"), "contract Foo {}\n").unwrap();
+
+        // the buffer gained two lines since it was saved; New.sol was never saved
+        let texts = BTreeMap::from([
+            ("src/Foo.sol".to_string(), "\n\ncontract Foo {}\n".to_string()),
+            ("src/New.sol".to_string(), "contract New {}\n".to_string()),
+        ]);
+        let contract = |name: &str, src: &str, name_src: &str| {
+            json!({ "nodeType": "ContractDefinition", "name": name, "src": src, "nameLocation": name_src })
+        };
+        let unit = |node: Value| json!({ "nodeType": "SourceUnit", "nodes": [node] });
+        let output = json!({
+            "sources": {
+                "src/Foo.sol": { "id": 0, "ast": unit(contract("Foo", "2:15:0", "11:3:0")) },
+                "src/New.sol": { "id": 1, "ast": unit(contract("New", "0:15:1", "9:3:1")) },
+            },
+        });
+        let table = build_source_table(&output, &root, &texts);
+        assert_eq!(table[&0].content, texts["src/Foo.sol"]);
+        assert_eq!(table[&1].content, texts["src/New.sol"]);
+
+        let defs = extract_definitions_from_solc_json(&output, &table, &root);
+        let foo = &defs[&table[&0].uri]["Foo"][0];
+        assert_eq!(foo.name_range.unwrap().start, lsp_types::Position::new(2, 9));
+        let new = &defs[&table[&1].uri]["New"][0];
+        assert_eq!(new.name_range.unwrap().end, lsp_types::Position::new(0, 12));
+    }
+
+    fn member(name: &str, src: &str) -> Value {
+        json!({
+            "nodeType": "VariableDeclaration",
+            "name": name,
+            "src": src,
+            "typeDescriptions": { "typeString": "uint256" },
+        })
+    }
+
+    #[test]
+    fn same_named_fields_of_two_structs_are_kept_apart() {
+        let content = "contract A { struct S { uint value; } } contract B { struct S { uint value; } }";
+        let table = SourceTable::from([(
+            0,
+            SourceFile { uri: "file:///p/S.sol".into(), content: content.into() },
+        )]);
+        let ast = json!({
+            "nodeType": "SourceUnit",
+            "nodes": [
+                {
+                    "nodeType": "ContractDefinition", "name": "A", "src": "0:39:0",
+                    "nodes": [{
+                        "nodeType": "StructDefinition", "name": "S", "canonicalName": "A.S",
+                        "src": "13:24:0", "members": [member("value", "24:10:0")],
+                    }],
+                },
+                {
+                    "nodeType": "ContractDefinition", "name": "B", "src": "40:39:0",
+                    "nodes": [{
+                        "nodeType": "StructDefinition", "name": "S", "canonicalName": "B.S",
+                        "src": "53:24:0", "members": [member("value", "64:10:0")],
+                    }],
+                },
+            ],
+        });
+
+        let index = build_definition_index(&ast, &table);
+
+        assert_eq!(index["value"].len(), 2);
+        let a = &index[&qualified_member_key("A.S", "value")];
+        let b = &index[&qualified_member_key("B.S", "value")];
+        assert_eq!((a.len(), b.len()), (1, 1));
+        assert_eq!(a[0].location.range.start, lsp_types::Position::new(0, 24));
+        assert_eq!(b[0].location.range.start, lsp_types::Position::new(0, 64));
+        assert_eq!(a[0].container.as_deref(), Some("A.S"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_source_names_become_drive_letter_uris() {
+        let uri = source_name_to_uri(Path::new(r"C:\project"), "src/Foo.sol").unwrap();
+        assert_eq!(uri, "file:///C:/project/src/Foo.sol");
+        assert_eq!(
+            Url::parse(&uri).unwrap().to_file_path().unwrap(),
+            Path::new(r"C:\project\src\Foo.sol")
+        );
+    }
+
+    #[test]
+    fn relative_project_root_has_no_uri() {
+        assert_eq!(source_name_to_uri(Path::new("project"), "src/Foo.sol"), None);
+    }
+
+    #[test]
+    fn struct_type_string_keeps_the_qualified_name() {
+        assert_eq!(struct_name_from_type_string("struct B.S storage ref"), Some("B.S"));
+        assert_eq!(struct_name_from_type_string("struct Position memory"), Some("Position"));
+        assert_eq!(struct_name_from_type_string("uint256"), None);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lsp_types::{Location, Position};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::analysis::definitions::SourceTable;
+use crate::analysis::references::{
+    asts, collect_declarations, contains, name_span, span_to_location, walk,
+};
+
+/// A contract, interface or library and what it inherits from. Bases are
+/// identified by the location of their declared name, which stays valid
+/// across compiles (AST node ids don't).
+#[derive(Debug, Clone)]
+pub struct Contract {
+    pub name: String,
+    /// `contract`, `interface` or `library`
+    pub kind: String,
+    /// Span of the whole definition
+    pub location: Location,
+    /// Span of the declared name
+    pub name_location: Location,
+    /// Direct bases, in `is A, B` order
+    pub bases: Vec<Location>,
+    /// C3 linearization, most derived (this contract) first
+    pub linearized: Vec<Location>,
+}
+
+/// Global map: file URI → contracts defined in that file
+pub static CONTRACT_MAP: Lazy<Mutex<HashMap<String, Vec<Contract>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Collect every `ContractDefinition` of a solc output, grouped by file URI
+pub fn extract_contracts_from_solc_json(
+    json: &Value,
+    sources: &SourceTable,
+) -> HashMap<String, Vec<Contract>> {
+    let decls = collect_declarations(json, sources);
+    let mut contracts: HashMap<String, Vec<Contract>> = HashMap::new();
+
+    for ast in asts(json) {
+        walk(ast, &mut |obj| {
+            if obj.get("nodeType").and_then(|v| v.as_str()) != Some("ContractDefinition") {
+                return;
+            }
+            let Some(name) = obj.get("name").and_then(|v| v.as_str()) else {
+                return;
+            };
+            let Some(location) = obj
+                .get("src")
+                .and_then(|v| v.as_str())
+                .and_then(|s| span_to_location(s, sources))
+            else {
+                return;
+            };
+            let name_location = name_span(obj)
+                .and_then(|s| span_to_location(s, sources))
+                .unwrap_or_else(|| location.clone());
+
+            let bases = obj
+                .get("baseContracts")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|base| base.get("baseName")?.get("referencedDeclaration")?.as_i64())
+                .filter_map(|id| decls.get(&id).map(|(_, loc)| loc.clone()))
+                .collect();
+            let linearized = obj
+                .get("linearizedBaseContracts")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|id| decls.get(&id.as_i64()?).map(|(_, loc)| loc.clone()))
+                .collect();
+
+            contracts.entry(location.uri.to_string()).or_default().push(Contract {
+                name: name.to_string(),
+                kind: obj
+                    .get("contractKind")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("contract")
+                    .to_string(),
+                location,
+                name_location,
+                bases,
+                linearized,
+            });
+        });
+    }
+
+    contracts
+}
+
+/// The contract whose name is declared at `name_location`
+pub fn contract_named_at(
+    map: &HashMap<String, Vec<Contract>>,
+    name_location: &Location,
+) -> Option<Contract> {
+    map.get(name_location.uri.as_str())?
+        .iter()
+        .find(|c| c.name_location == *name_location)
+        .cloned()
+}
+
+/// The contract whose definition in `uri` encloses `pos`
+fn enclosing_contract<'a>(
+    map: &'a HashMap<String, Vec<Contract>>,
+    uri: &str,
+    pos: Position,
+) -> Option<&'a Contract> {
+    map.get(uri)?.iter().find(|c| contains(&c.location.range, pos))
+}
+
+/// Where the contract declaring each of `locations` stands in the
+/// linearization of the contract enclosing `pos` in `uri`: 0 for that
+/// contract itself, 1 for the base solc looks in next, and so on. Locations
+/// outside the inheritance chain (or outside any contract) are left out.
+pub fn linearization_ranks(
+    uri: &str,
+    pos: Position,
+    locations: &[Location],
+) -> HashMap<Location, usize> {
+    let Ok(map) = CONTRACT_MAP.lock() else {
+        return HashMap::new();
+    };
+    let Some(current) = enclosing_contract(&map, uri, pos) else {
+        return HashMap::new();
+    };
+
+    locations
+        .iter()
+        .filter_map(|location| {
+            let owner = enclosing_contract(&map, location.uri.as_str(), location.range.start)?;
+            let rank = current.linearized.iter().position(|l| *l == owner.name_location)?;
+            Some((location.clone(), rank))
+        })
+        .collect()
+}
+
+/// Contracts that list the one declared at `name_location` as a direct base
+pub fn direct_subtypes(
+    map: &HashMap<String, Vec<Contract>>,
+    name_location: &Location,
+) -> Vec<Contract> {
+    let mut subtypes: Vec<Contract> = map
+        .values()
+        .flatten()
+        .filter(|c| c.bases.contains(name_location))
+        .cloned()
+        .collect();
+    subtypes.sort_by_key(|c| (c.location.uri.to_string(), c.location.range.start.line));
+    subtypes
+}
@@ -1 +1,8 @@
 pub mod definitions;
+pub mod artifacts;
+pub mod ast;
+pub mod references;
+pub mod natspec;
+pub mod persist;
+pub mod inheritance;
+pub mod builtins;
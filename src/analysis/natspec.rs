@@ -0,0 +1,80 @@
+use serde_json::Value;
+
+/// NatSpec tags of a declaration's doc comment
+#[derive(Debug, Default)]
+pub struct NatSpec {
+    pub title: Option<String>,
+    pub notice: Option<String>,
+    pub dev: Option<String>,
+    /// `@param name description`, in declaration order
+    pub params: Vec<(String, String)>,
+    pub returns: Vec<String>,
+}
+
+/// Raw comment text of a node's `documentation`: a `StructuredDocumentation`
+/// node since solc 0.6.3, a plain string before that
+pub fn documentation_text(node: &Value) -> Option<&str> {
+    let doc = node.get("documentation")?;
+    doc.as_str().or_else(|| doc.get("text")?.as_str())
+}
+
+/// Parse comment text as solc normalizes it from `///` and `/** */` blocks.
+/// Untagged leading text counts as `@notice`; lines without a tag continue
+/// the previous one.
+pub fn parse(text: &str) -> NatSpec {
+    let mut spec = NatSpec::default();
+    let mut tag = "notice";
+    let mut body = String::new();
+
+    let lines = text.lines().map(|line| {
+        // defensive: older solc versions keep the leading `*` of block comments
+        line.trim().trim_start_matches('*').trim()
+    });
+
+    for line in lines {
+        if let Some(tagged) = line.strip_prefix('@') {
+            flush(&mut spec, tag, &body);
+            let (name, rest) = tagged.split_once(char::is_whitespace).unwrap_or((tagged, ""));
+            tag = match name {
+                "title" | "notice" | "dev" | "param" | "return" => name,
+                _ => "other", // @author, @inheritdoc, @custom:...
+            };
+            body = rest.trim().to_string();
+        } else if !line.is_empty() {
+            if !body.is_empty() {
+                body.push(' ');
+            }
+            body.push_str(line);
+        }
+    }
+    flush(&mut spec, tag, &body);
+
+    spec
+}
+
+fn flush(spec: &mut NatSpec, tag: &str, body: &str) {
+    let body = body.trim();
+    if body.is_empty() {
+        return;
+    }
+
+    let append = |slot: &mut Option<String>| match slot {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(body);
+        }
+        None => *slot = Some(body.to_string()),
+    };
+
+    match tag {
+        "title" => append(&mut spec.title),
+        "notice" => append(&mut spec.notice),
+        "dev" => append(&mut spec.dev),
+        "param" => {
+            let (name, description) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+            spec.params.push((name.to_string(), description.trim().to_string()));
+        }
+        "return" => spec.returns.push(body.to_string()),
+        _ => {}
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lsp_types::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::ast::AST_MAP;
+use crate::analysis::definitions::{DefinitionIndex, DEFINITION_MAP};
+use crate::util::cache::cache_root;
+use crate::util::hash::sha256_hex;
+use crate::util::log::log_to_file;
+use crate::util::text::strip_bom;
+
+/// Definitions of one file, with the hash of every file they point into
+/// (the file itself included), as of when they were built
+#[derive(Serialize, Deserialize)]
+struct CachedIndex {
+    file_hashes: HashMap<String, String>,
+    index: DefinitionIndex,
+}
+
+/// Hash of a source as solc sees it, so a BOM on disk doesn't make it stale
+fn content_hash(content: &str) -> String {
+    sha256_hex(strip_bom(content).as_bytes())
+}
+
+/// `~/.cache/emacs-solidity-server/index/<hash of project root>.json`
+fn cache_file(project_root: &Path) -> PathBuf {
+    let key = content_hash(&project_root.to_string_lossy());
+    cache_root()
+        .join("index")
+        .join(format!("{}.json", &key[..16]))
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+/// Write the definition index of every file under `project_root` to disk
+pub fn save_definition_index(project_root: &Path) {
+    let units = AST_MAP.lock().map(|u| u.clone()).unwrap_or_default();
+    let Ok(map) = DEFINITION_MAP.lock() else {
+        return;
+    };
+
+    // hash the text solc saw, falling back to the file as saved
+    let hash_of = |uri: &str| {
+        units
+            .get(uri)
+            .map(|u| u.content.clone())
+            .or_else(|| fs::read_to_string(uri_to_path(uri)?).ok())
+            .map(|content| content_hash(&content))
+    };
+
+    let cached: HashMap<&String, CachedIndex> = map
+        .iter()
+        .filter(|(uri, _)| uri_to_path(uri).is_some_and(|p| p.starts_with(project_root)))
+        .filter_map(|(uri, index)| {
+            let mut uris: HashSet<String> = indexed_uris(index);
+            uris.insert(uri.clone());
+            let file_hashes = uris
+                .into_iter()
+                .map(|uri| Some((uri.clone(), hash_of(&uri)?)))
+                .collect::<Option<HashMap<_, _>>>()?;
+            Some((
+                uri,
+                CachedIndex {
+                    file_hashes,
+                    index: index.clone(),
+                },
+            ))
+        })
+        .collect();
+
+    let path = cache_file(project_root);
+    let result = path
+        .parent()
+        .map(fs::create_dir_all)
+        .transpose()
+        .and_then(|_| {
+            let json = serde_json::to_string(&cached).map_err(std::io::Error::other)?;
+            fs::write(&path, json)
+        });
+
+    match result {
+        Ok(()) => log_to_file(&format!(
+            "[index-cache] Saved definitions of {} files to {}",
+            cached.len(),
+            path.display()
+        )),
+        Err(e) => log_to_file(&format!("[index-cache] Failed to save {}: {}", path.display(), e)),
+    }
+}
+
+/// URIs of the files the definitions of `index` are located in
+fn indexed_uris(index: &DefinitionIndex) -> HashSet<String> {
+    index
+        .values()
+        .flatten()
+        .map(|def| def.location.uri.to_string())
+        .collect()
+}
+
+/// Whether every file `entry` was built from still has the same content,
+/// remembering the hashes of files already read in `current`
+fn is_fresh(entry: &CachedIndex, current: &mut HashMap<String, Option<String>>) -> bool {
+    entry.file_hashes.iter().all(|(uri, hash)| {
+        let now = current.entry(uri.clone()).or_insert_with(|| {
+            uri_to_path(uri)
+                .and_then(|p| fs::read_to_string(p).ok())
+                .map(|content| content_hash(&content))
+        });
+        now.as_ref() == Some(hash)
+    })
+}
+
+/// Load the saved definition index for `project_root`, keeping only files whose
+/// content, and that of every file their definitions point into, is unchanged;
+/// the rest are indexed again when next compiled
+pub fn load_definition_index(project_root: &Path) {
+    let path = cache_file(project_root);
+    let Ok(json) = fs::read_to_string(&path) else {
+        return;
+    };
+    let cached: HashMap<String, CachedIndex> = match serde_json::from_str(&json) {
+        Ok(cached) => cached,
+        Err(e) => {
+            log_to_file(&format!("[index-cache] Ignoring unreadable {}: {}", path.display(), e));
+            return;
+        }
+    };
+
+    let total = cached.len();
+    let mut current = HashMap::new();
+    let fresh: Vec<(String, DefinitionIndex)> = cached
+        .into_iter()
+        .filter(|(_, entry)| is_fresh(entry, &mut current))
+        .map(|(uri, entry)| (uri, entry.index))
+        .collect();
+
+    let loaded = fresh.len();
+    if let Ok(mut map) = DEFINITION_MAP.lock() {
+        for (uri, index) in fresh {
+            // anything compiled since startup is newer than the cache
+            map.entry(uri).or_insert(index);
+        }
+    }
+
+    log_to_file(&format!(
+        "[index-cache] Loaded {} of {} cached files ({} stale)",
+        loaded,
+        total,
+        total - loaded
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::uri::{canonical_path, path_to_uri};
+
+    #[test]
+    fn entry_is_stale_once_an_imported_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = canonical_path(dir.path());
+        let main = root.join("Main.sol");
+        let import = root.join("Lib.sol");
+        fs::write(&main, "import \"./Lib.sol\";\n").unwrap();
+        fs::write(&import, "library Lib {}\n").unwrap();
+
+        let file_hashes = [&main, &import]
+            .into_iter()
+            .map(|p| (path_to_uri(p).unwrap(), content_hash(&fs::read_to_string(p).unwrap())))
+            .collect();
+        let entry = CachedIndex { file_hashes, index: DefinitionIndex::new() };
+        assert!(is_fresh(&entry, &mut HashMap::new()));
+
+        fs::write(&import, "library Lib { uint constant X = 1; }\n").unwrap();
+        assert!(!is_fresh(&entry, &mut HashMap::new()));
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use lsp_types::{Location, Position, Range, Url};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::analysis::ast::{parse_src_span, source_ast};
+use crate::analysis::definitions::SourceTable;
+use crate::util::position::byte_offset_to_position;
+
+/// One occurrence of a name in a file, and the declaration it resolves to.
+/// Declarations are recorded as occurrences of themselves.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    /// Range of the name at the use site
+    pub range: Range,
+    /// Range of the declared name
+    pub target: Location,
+    /// Whether this occurrence is the callee of a function call
+    pub is_call: bool,
+}
+
+/// Global map: file URI → every resolved name occurrence in that file
+pub static REFERENCE_MAP: Lazy<Mutex<HashMap<String, Vec<Reference>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Convert a solc `src` span into a location through the source table
+pub fn span_to_location(src: &str, sources: &SourceTable) -> Option<Location> {
+    let (start, end, file_index) = parse_src_span(src)?;
+    let file = sources.get(&file_index)?;
+    Some(Location {
+        uri: Url::parse(&file.uri).ok()?,
+        range: Range {
+            start: byte_offset_to_position(&file.content, start),
+            end: byte_offset_to_position(&file.content, end),
+        },
+    })
+}
+
+/// Span of just the name of a node: `nameLocation`/`memberLocation` when solc
+/// provides them (0.8.2+), else the whole `src`
+pub fn name_span(obj: &serde_json::Map<String, Value>) -> Option<&str> {
+    obj.get("nameLocation")
+        .or_else(|| obj.get("memberLocation"))
+        .or_else(|| obj.get("src"))
+        .and_then(|v| v.as_str())
+        // unknown locations are reported as `-1:-1:-1`
+        .filter(|s| !s.starts_with('-'))
+}
+
+pub fn walk(node: &Value, f: &mut dyn FnMut(&serde_json::Map<String, Value>)) {
+    if let Some(obj) = node.as_object() {
+        f(obj);
+        for value in obj.values() {
+            walk(value, f);
+        }
+    } else if let Some(array) = node.as_array() {
+        for value in array {
+            walk(value, f);
+        }
+    }
+}
+
+fn is_declaration(node_type: &str) -> bool {
+    node_type.ends_with("Definition") || node_type == "VariableDeclaration" || node_type == "EnumValue"
+}
+
+pub fn asts(json: &Value) -> impl Iterator<Item = &Value> {
+    json.get("sources")
+        .and_then(|v| v.as_object())
+        .into_iter()
+        .flat_map(|sources| sources.values())
+        .filter_map(source_ast)
+}
+
+/// Map of node id → (name, name location) for every named declaration in the output
+pub fn collect_declarations(json: &Value, sources: &SourceTable) -> HashMap<i64, (String, Location)> {
+    let mut decls = HashMap::new();
+    for ast in asts(json) {
+        walk(ast, &mut |obj| {
+            if let Some(id) = obj.get("id").and_then(|v| v.as_i64())
+                && let Some(name) = obj.get("name").and_then(|v| v.as_str())
+                && !name.is_empty()
+                && obj.get("nodeType").and_then(|v| v.as_str()).is_some_and(is_declaration)
+                && let Some(location) = name_span(obj).and_then(|s| span_to_location(s, sources))
+            {
+                decls.insert(id, (name.to_string(), location));
+            }
+        });
+    }
+    decls
+}
+
+/// Build per-file reference lists from solc output: every node with a
+/// `referencedDeclaration`, plus each declaration's own name
+pub fn extract_references_from_solc_json(
+    json: &Value,
+    sources: &SourceTable,
+) -> HashMap<String, Vec<Reference>> {
+    let decls = collect_declarations(json, sources);
+    let mut refs: HashMap<String, Vec<Reference>> = HashMap::new();
+
+    for (name, target) in decls.values() {
+        refs.entry(target.uri.to_string()).or_default().push(Reference {
+            name: name.clone(),
+            range: target.range,
+            target: target.clone(),
+            is_call: false,
+        });
+    }
+
+    // node ids of call expressions' callees, unwrapping `f{value: v}(...)`
+    let mut callee_ids = HashSet::new();
+    for ast in asts(json) {
+        walk(ast, &mut |obj| {
+            if obj.get("nodeType").and_then(|v| v.as_str()) != Some("FunctionCall") {
+                return;
+            }
+            let mut callee = obj.get("expression");
+            if let Some(options) = callee.filter(|c| {
+                c.get("nodeType").and_then(|v| v.as_str()) == Some("FunctionCallOptions")
+            }) {
+                callee = options.get("expression");
+            }
+            if let Some(id) = callee.and_then(|c| c.get("id")).and_then(|v| v.as_i64()) {
+                callee_ids.insert(id);
+            }
+        });
+    }
+
+    for ast in asts(json) {
+        walk(ast, &mut |obj| {
+            // `UserDefinedTypeName` carries its reference on the nested `pathNode`
+            if obj.contains_key("pathNode") {
+                return;
+            }
+            let Some(decl_id) = obj.get("referencedDeclaration").and_then(|v| v.as_i64()) else {
+                return;
+            };
+            let Some((decl_name, target)) = decls.get(&decl_id) else {
+                return;
+            };
+            let Some(location) = name_span(obj).and_then(|s| span_to_location(s, sources)) else {
+                return;
+            };
+            let name = obj
+                .get("memberName")
+                .or_else(|| obj.get("name"))
+                .and_then(|v| v.as_str())
+                .unwrap_or(decl_name);
+
+            let is_call = obj
+                .get("id")
+                .and_then(|v| v.as_i64())
+                .is_some_and(|id| callee_ids.contains(&id));
+
+            refs.entry(location.uri.to_string()).or_default().push(Reference {
+                name: name.to_string(),
+                range: location.range,
+                target: target.clone(),
+                is_call,
+            });
+        });
+    }
+
+    refs
+}
+
+fn span_size(range: &Range) -> (u32, u32) {
+    (
+        range.end.line - range.start.line,
+        range.end.character.saturating_sub(range.start.character),
+    )
+}
+
+pub fn contains(range: &Range, pos: Position) -> bool {
+    range.start <= pos && pos <= range.end
+}
+
+/// The occurrence under `pos` in `uri`, if any
+pub fn reference_at(uri: &str, pos: Position) -> Option<Reference> {
+    let map = REFERENCE_MAP.lock().ok()?;
+    map.get(uri)?
+        .iter()
+        .filter(|r| contains(&r.range, pos))
+        // innermost occurrence wins, e.g. a member name over its whole access path
+        .min_by_key(|r| span_size(&r.range))
+        .cloned()
+}
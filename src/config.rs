@@ -0,0 +1,125 @@
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::project::workspace::DEFAULT_INDEX_IGNORE;
+use crate::util::log::log_to_file;
+
+/// solc warnings about unused code: 2072 unused local variable,
+/// 5667 unused function parameter
+const DEFAULT_UNNECESSARY_CODES: [&str; 2] = ["2072", "5667"];
+
+/// Server settings, read from `initializationOptions`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Populate the definition index from existing build artifacts instead of compiling
+    pub use_build_artifacts: bool,
+    /// Compile every project source after `initialized` to warm the definition index
+    pub index_workspace: bool,
+    /// Directory names skipped when indexing the workspace
+    pub index_ignore: Vec<String>,
+    /// Kill a solc run that takes longer than this many seconds; 0 never does
+    pub compile_timeout_secs: u64,
+    /// Download the latest release of every minor series in the background.
+    /// When off, compilers are only fetched when a pragma needs one.
+    pub auto_update: bool,
+    /// Total size, in megabytes, the cached compilers may take; unlimited when unset
+    pub cache_quota_mb: Option<u64>,
+    /// Compiler downloads allowed to run at the same time
+    pub max_concurrent_downloads: usize,
+    /// solc warning codes shown as hints tagged `Unnecessary` (rendered dimmed)
+    pub unnecessary_codes: Vec<String>,
+    /// solc error codes never reported. `ignoreCodes` is the older name.
+    #[serde(alias = "ignoreCodes")]
+    pub ignored_diagnostic_codes: Vec<String>,
+    /// Let `ignored_diagnostic_codes` hide errors too, not only warnings
+    pub allow_ignoring_errors: bool,
+    /// solc warning/info codes reported as errors
+    pub error_codes: Vec<String>,
+    /// Extra directories imports are looked up in, like solc's `--include-path`
+    pub include_paths: Vec<String>,
+    /// solc `settings.modelChecker` (engine, targets, ...) to run the SMT
+    /// checker on every compile. Overrides `foundry.toml`'s `model_checker`.
+    pub model_checker: Option<Value>,
+    /// Extra solc standard-json `settings`, deep-merged into the ones the
+    /// server builds; `remappings` and `outputSelection` stay the server's
+    pub solc_settings: Option<Value>,
+    /// Largest message body, in megabytes, accepted from the client; bigger
+    /// messages are skipped
+    pub max_message_mb: u64,
+    /// Fallback solc binary hosts, tried in order when the primary one fails
+    pub solc_mirrors: Vec<String>,
+    /// Host serving `<platform>/list.json` and the compiler binaries
+    pub binaries_base_url: Option<String>,
+    /// When set, edits no longer trigger a compile; edited files are
+    /// re-validated together every this many seconds
+    pub revalidate_interval_secs: Option<u64>,
+    /// Age, in hours, after which the cached `list.json` is refreshed in the
+    /// background by the next compile
+    pub list_ttl_hours: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            use_build_artifacts: false,
+            index_workspace: false,
+            index_ignore: DEFAULT_INDEX_IGNORE.iter().map(|d| d.to_string()).collect(),
+            compile_timeout_secs: 30,
+            auto_update: true,
+            cache_quota_mb: None,
+            max_concurrent_downloads: 2,
+            unnecessary_codes: DEFAULT_UNNECESSARY_CODES.iter().map(|c| c.to_string()).collect(),
+            ignored_diagnostic_codes: vec![],
+            allow_ignoring_errors: false,
+            error_codes: vec![],
+            include_paths: vec![],
+            model_checker: None,
+            solc_settings: None,
+            max_message_mb: 64,
+            solc_mirrors: vec![],
+            binaries_base_url: None,
+            revalidate_interval_secs: None,
+            list_ttl_hours: 24,
+        }
+    }
+}
+
+static CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| RwLock::new(Config::default()));
+
+/// Replace the active config with the client's `initializationOptions`
+pub fn load_from_init_options(options: Option<&Value>) {
+    let mut config = match options {
+        Some(value) if !value.is_null() => match serde_json::from_value::<Config>(value.clone()) {
+            Ok(c) => c,
+            Err(e) => {
+                log_to_file(&format!("[config] Ignoring invalid initializationOptions: {}", e));
+                Config::default()
+            }
+        },
+        _ => Config::default(),
+    };
+
+    // the environment wins, so a pinned setup doesn't depend on the client config
+    if let Ok(value) = std::env::var("ESS_AUTO_UPDATE") {
+        config.auto_update = !matches!(value.trim(), "0" | "false" | "off" | "no");
+    }
+
+    if config.solc_settings.as_ref().is_some_and(|s| !s.is_object()) {
+        log_to_file("[config] Ignoring solcSettings: not a JSON object");
+        config.solc_settings = None;
+    }
+
+    log_to_file(&format!("[config] Effective config: {:?}", config));
+    if let Ok(mut slot) = CONFIG.write() {
+        *slot = config;
+    }
+}
+
+/// Snapshot of the active config
+pub fn get() -> Config {
+    CONFIG.read().map(|c| c.clone()).unwrap_or_default()
+}
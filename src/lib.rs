@@ -0,0 +1,47 @@
+//! Emacs Solidity Server as a library.
+//!
+//! The language server binary is a thin wrapper over [`server`]. The pieces it
+//! is built from are useful on their own, e.g. in a CI linter that compiles
+//! files the way the editor does: reading a file's `pragma solidity`, parsing a
+//! project's remappings, collecting the sources a file imports, keeping a cache
+//! of compilers and running `solc --standard-json`.
+//!
+//! ```no_run
+//! use std::collections::HashSet;
+//! use std::path::Path;
+//!
+//! use emacs_solidity_server::{parse_remappings, resolve_sources_recursive};
+//!
+//! let root = Path::new("/path/to/project");
+//! let remappings = parse_remappings(root, None);
+//! let sources = resolve_sources_recursive(
+//!     root,
+//!     &root.join("src/Token.sol"),
+//!     &remappings,
+//!     &mut HashSet::new(),
+//! );
+//! for virtual_path in sources.keys() {
+//!     println!("{}", virtual_path);
+//! }
+//! ```
+
+mod analysis;
+mod config;
+mod lsp;
+mod project;
+mod util;
+pub mod solc;
+
+pub use project::remappings::{best_match, parse_remappings, Remapping, TargetBase};
+pub use project::root::find_project_root;
+pub use solc::error::SolcError;
+pub use solc::manager::SolcManager;
+pub use solc::switcher::{extract_pragma, parse_pragma, Pragma};
+pub use util::fs::{run_solc, SourcePaths};
+pub use util::imports::{resolve_sources_recursive, virtual_path};
+
+/// Running the language server itself
+pub mod server {
+    pub use crate::lsp::transport::serve;
+    pub use crate::util::log::{log_to_file, log_to_path, log_to_stderr};
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyIncomingCallsParams, CallHierarchyItem,
+    CallHierarchyOutgoingCall, CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
+    Location, Range, SymbolKind, Url,
+};
+use serde_json::{json, Value};
+
+use crate::analysis::ast::{enclosing_function, function_nodes, FunctionNode, SourceUnit, AST_MAP};
+use crate::analysis::references::{reference_at, REFERENCE_MAP};
+use crate::util::position::{byte_offset_to_position, position_to_byte_offset};
+
+fn span_to_range(content: &str, span: (usize, usize)) -> Range {
+    Range {
+        start: byte_offset_to_position(content, span.0),
+        end: byte_offset_to_position(content, span.1),
+    }
+}
+
+fn item_for(uri: &Url, unit: &SourceUnit, function: &FunctionNode) -> CallHierarchyItem {
+    CallHierarchyItem {
+        name: function.name.clone(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: span_to_range(&unit.content, function.span),
+        selection_range: span_to_range(&unit.content, function.name_span),
+        data: None,
+    }
+}
+
+/// The function whose name sits exactly at `selection`
+fn function_at_selection(unit: &SourceUnit, selection: Range) -> Option<FunctionNode> {
+    function_nodes(unit)
+        .into_iter()
+        .find(|f| span_to_range(&unit.content, f.name_span) == selection)
+}
+
+fn respond(req: &Value, result: Value) -> Option<String> {
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": result,
+    }).to_string())
+}
+
+/// `textDocument/prepareCallHierarchy`: the function declared or called under the cursor
+pub fn handle_prepare_call_hierarchy(req: &Value) -> Option<String> {
+    let params: CallHierarchyPrepareParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document_position_params.text_document.uri.to_string();
+    let pos = params.text_document_position_params.position;
+
+    let reference = reference_at(&uri, pos);
+    let units = AST_MAP.lock().ok()?;
+    let items = reference.and_then(|r| {
+        let unit = units.get(r.target.uri.as_str())?;
+        let function = function_at_selection(unit, r.target.range)?;
+        Some(vec![item_for(&r.target.uri, unit, &function)])
+    });
+
+    respond(req, json!(items))
+}
+
+/// `callHierarchy/incomingCalls`: functions containing a call to the item
+pub fn handle_incoming_calls(req: &Value) -> Option<String> {
+    let params: CallHierarchyIncomingCallsParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let target = Location {
+        uri: params.item.uri.clone(),
+        range: params.item.selection_range,
+    };
+
+    let refs = REFERENCE_MAP.lock().ok()?;
+    let units = AST_MAP.lock().ok()?;
+
+    let mut callers: HashMap<(String, Range), CallHierarchyIncomingCall> = HashMap::new();
+    for (uri, file_refs) in refs.iter() {
+        let (Some(unit), Ok(url)) = (units.get(uri), Url::parse(uri)) else {
+            continue;
+        };
+        for r in file_refs.iter().filter(|r| r.is_call && r.target == target) {
+            let Some(offset) = position_to_byte_offset(&unit.content, r.range.start) else {
+                continue;
+            };
+            let Some(caller) = enclosing_function(unit, offset) else {
+                continue; // e.g. a call in a state variable initializer
+            };
+            let from = item_for(&url, unit, &caller);
+            callers
+                .entry((uri.clone(), from.selection_range))
+                .or_insert_with(|| CallHierarchyIncomingCall { from, from_ranges: vec![] })
+                .from_ranges
+                .push(r.range);
+        }
+    }
+
+    let mut calls: Vec<_> = callers.into_values().collect();
+    calls.sort_by_key(|c| (c.from.uri.to_string(), c.from.range.start.line, c.from.range.start.character));
+    respond(req, json!(calls))
+}
+
+/// `callHierarchy/outgoingCalls`: functions called from within the item's body
+pub fn handle_outgoing_calls(req: &Value) -> Option<String> {
+    let params: CallHierarchyOutgoingCallsParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.item.uri.to_string();
+
+    let refs = REFERENCE_MAP.lock().ok()?;
+    let units = AST_MAP.lock().ok()?;
+
+    let unit = units.get(&uri)?;
+    let function = function_at_selection(unit, params.item.selection_range)?;
+
+    let mut callees: HashMap<(String, Range), CallHierarchyOutgoingCall> = HashMap::new();
+    for r in refs.get(&uri).into_iter().flatten().filter(|r| r.is_call) {
+        let Some(offset) = position_to_byte_offset(&unit.content, r.range.start) else {
+            continue;
+        };
+        if offset < function.span.0 || offset > function.span.1 {
+            continue;
+        }
+
+        let to = units
+            .get(r.target.uri.as_str())
+            .and_then(|target_unit| {
+                let callee = function_at_selection(target_unit, r.target.range)?;
+                Some(item_for(&r.target.uri, target_unit, &callee))
+            })
+            .unwrap_or_else(|| CallHierarchyItem {
+                name: r.name.clone(),
+                kind: SymbolKind::FUNCTION,
+                tags: None,
+                detail: None,
+                uri: r.target.uri.clone(),
+                range: r.target.range,
+                selection_range: r.target.range,
+                data: None,
+            });
+
+        callees
+            .entry((r.target.uri.to_string(), r.target.range))
+            .or_insert_with(|| CallHierarchyOutgoingCall { to, from_ranges: vec![] })
+            .from_ranges
+            .push(r.range);
+    }
+
+    let mut calls: Vec<_> = callees.into_values().collect();
+    calls.sort_by_key(|c| (c.from_ranges[0].start.line, c.from_ranges[0].start.character));
+    respond(req, json!(calls))
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionParams, NumberOrString, Position, Range, TextEdit,
+    WorkspaceEdit,
+};
+use semver::Version;
+use serde_json::{json, Value};
+
+use crate::lsp::documents;
+use crate::lsp::handler::SOLC_MANAGER;
+use crate::solc::switcher::find_solidity_pragma;
+use crate::util::text::strip_bom;
+
+/// Diagnostic code of the "no `pragma solidity`" warning
+pub const MISSING_PRAGMA_CODE: &str = "missing-pragma";
+
+/// `^major.minor.0` of the newest known release, so any cached patch of that
+/// series satisfies it
+fn default_pragma() -> String {
+    let latest = SOLC_MANAGER
+        .get()
+        .and_then(|m| m.list().latest_release.clone())
+        .and_then(|v| Version::parse(&v).ok());
+
+    match latest {
+        Some(v) => format!("pragma solidity ^{}.{}.0;", v.major, v.minor),
+        None => "pragma solidity ^0.8.0;".to_string(),
+    }
+}
+
+/// Insert the pragma below the SPDX license line when there is one
+fn pragma_insert_line(text: &str) -> u32 {
+    let first = strip_bom(text).lines().next().unwrap_or("");
+    if first.trim_start().starts_with("// SPDX-License-Identifier") {
+        1
+    } else {
+        0
+    }
+}
+
+/// `textDocument/codeAction`: a quick fix inserting a default pragma into
+/// files that have none
+pub fn handle_code_action(req: &Value) -> Option<String> {
+    let params: CodeActionParams = serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document.uri;
+
+    let text = documents::text(uri.as_str())
+        .or_else(|| std::fs::read_to_string(uri.to_file_path().ok()?).ok())
+        .unwrap_or_default();
+
+    let mut actions = Vec::new();
+
+    if find_solidity_pragma(&text).is_none() {
+        let pragma = default_pragma();
+        let at = Position::new(pragma_insert_line(&text), 0);
+        let edit = TextEdit {
+            range: Range::new(at, at),
+            new_text: format!("{}\n", pragma),
+        };
+
+        let fixes: Vec<_> = params
+            .context
+            .diagnostics
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String(MISSING_PRAGMA_CODE.into())))
+            .collect();
+
+        actions.push(CodeAction {
+            title: format!("Insert `{}`", pragma),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: (!fixes.is_empty()).then_some(fixes),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri, vec![edit])])),
+                ..Default::default()
+            }),
+            is_preferred: Some(true),
+            ..Default::default()
+        });
+    }
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": actions,
+    }).to_string())
+}
@@ -0,0 +1,77 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use crate::analysis::ast::AST_MAP;
+use crate::analysis::definitions::DEFINITION_MAP;
+use crate::analysis::inheritance::CONTRACT_MAP;
+use crate::analysis::references::REFERENCE_MAP;
+use crate::util::log::log_to_file;
+
+/// Global map: open document URI → URIs of every file its last compile
+/// indexed, itself and its whole import closure included
+static DEPENDENCIES: Lazy<Mutex<HashMap<String, HashSet<String>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Remember which files the latest compile of `uri` indexed
+pub fn record(uri: &str, files: HashSet<String>) {
+    if let Ok(mut dependencies) = DEPENDENCIES.lock() {
+        dependencies.insert(uri.to_string(), files);
+    }
+}
+
+/// Files the latest compile of `uri` indexed; empty when it wasn't compiled
+pub fn files(uri: &str) -> HashSet<String> {
+    DEPENDENCIES
+        .lock()
+        .ok()
+        .and_then(|dependencies| dependencies.get(uri).cloned())
+        .unwrap_or_default()
+}
+
+/// Forget `uri`'s compile and return the files it indexed that no other
+/// open document depends on
+fn release(uri: &str) -> Vec<String> {
+    let Ok(mut dependencies) = DEPENDENCIES.lock() else {
+        return vec![];
+    };
+    let Some(files) = dependencies.remove(uri) else {
+        return vec![];
+    };
+    files
+        .into_iter()
+        .filter(|file| !dependencies.values().any(|other| other.contains(file)))
+        .collect()
+}
+
+/// `uri` was closed: drop the index entries only it needed. Files another
+/// open document imports stay, so navigating from it keeps working.
+pub fn close(uri: &str) {
+    let unused = release(uri);
+    if unused.is_empty() {
+        return;
+    }
+    log_to_file(&format!("[index] Evicting {} files only {} used", unused.len(), uri));
+
+    if let Ok(mut map) = DEFINITION_MAP.lock() {
+        for file in &unused {
+            map.remove(file);
+        }
+    }
+    if let Ok(mut map) = REFERENCE_MAP.lock() {
+        for file in &unused {
+            map.remove(file);
+        }
+    }
+    if let Ok(mut map) = AST_MAP.lock() {
+        for file in &unused {
+            map.remove(file);
+        }
+    }
+    if let Ok(mut map) = CONTRACT_MAP.lock() {
+        for file in &unused {
+            map.remove(file);
+        }
+    }
+}
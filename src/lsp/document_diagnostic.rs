@@ -0,0 +1,111 @@
+use lsp_types::{
+    DocumentDiagnosticParams, DocumentDiagnosticReport, FullDocumentDiagnosticReport,
+    RelatedFullDocumentDiagnosticReport, RelatedUnchangedDocumentDiagnosticReport,
+    UnchangedDocumentDiagnosticReport, Url,
+};
+use serde_json::{json, Value};
+
+use crate::lsp::dependencies;
+use crate::lsp::documents;
+use crate::lsp::outgoing;
+use crate::lsp::published;
+use crate::lsp::worker;
+use crate::util::hash::sha256_hex;
+use crate::util::log::log_to_file;
+
+/// Current text of a file: the open document, else the file on disk
+fn current_text(uri: &str) -> Option<String> {
+    documents::text(uri).or_else(|| std::fs::read_to_string(Url::parse(uri).ok()?.to_file_path().ok()?).ok())
+}
+
+/// Identifies a report by the document's text and that of every file its
+/// last compile read, so editing an import invalidates it
+fn result_id(uri: &str, text: &str) -> String {
+    let mut files: Vec<String> = dependencies::files(uri).into_iter().filter(|file| file != uri).collect();
+    files.sort();
+
+    let mut hashes = vec![sha256_hex(text.as_bytes())];
+    for file in files {
+        let text = current_text(&file).unwrap_or_default();
+        hashes.push(format!("{}={}", file, sha256_hex(text.as_bytes())));
+    }
+    sha256_hex(hashes.join("\n").as_bytes())
+}
+
+/// `textDocument/diagnostic`: compile the document on demand and answer with
+/// its diagnostics, or `unchanged` when the client already has the report for
+/// this exact text and imports. The compile goes through the worker; the
+/// response is sent when it finishes.
+pub fn handle_document_diagnostic(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let params: DocumentDiagnosticParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document.uri.to_string();
+
+    let Some(text) = current_text(&uri) else {
+        let message = format!("Cannot read {}", uri);
+        return Some(
+            json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32602, "message": message } })
+                .to_string(),
+        );
+    };
+
+    let current_id = result_id(&uri, &text);
+    if params.previous_result_id.as_deref() == Some(current_id.as_str()) {
+        let report = DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+            related_documents: None,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                result_id: current_id,
+            },
+        });
+        return Some(json!({ "jsonrpc": "2.0", "id": id, "result": report }).to_string());
+    }
+
+    let version = documents::get(&uri).and_then(|doc| doc.version);
+    worker::submit_then(&uri, &text, version, {
+        let (uri, text) = (uri.clone(), text.clone());
+        move || {
+            // the compile just recorded which files the document imports
+            let report = DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id(&uri, &text)),
+                    items: published::diagnostics(&uri).unwrap_or_default(),
+                },
+            });
+            let response = json!({ "jsonrpc": "2.0", "id": id, "result": report });
+            if let Err(e) = outgoing::send(&response.to_string()) {
+                log_to_file(&format!("[diagnostic] Failed to send report: {}", e));
+            }
+        }
+    });
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::uri::path_to_uri;
+    use std::collections::HashSet;
+    use std::fs;
+
+    #[test]
+    fn editing_an_import_changes_the_result_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = path_to_uri(&dir.path().join("Main.sol")).unwrap();
+        let lib_path = dir.path().join("Lib.sol");
+        let lib = path_to_uri(&lib_path).unwrap();
+        fs::write(&lib_path, "library Lib {}\n").unwrap();
+        let text = "import \"./Lib.sol\";\n";
+
+        let before = result_id(&main, text);
+        dependencies::record(&main, HashSet::from([main.clone(), lib]));
+        let compiled = result_id(&main, text);
+        assert_ne!(before, compiled);
+        assert_eq!(result_id(&main, text), compiled);
+
+        fs::write(&lib_path, "library Lib { uint constant X = 1; }\n").unwrap();
+        assert_ne!(result_id(&main, text), compiled);
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+
+use lsp_types::{DocumentLink, DocumentLinkParams, Range};
+use serde_json::{json, Value};
+
+use crate::lsp::documents;
+use crate::lsp::handler::{project_root_or_parent, remappings_for};
+use crate::util::imports::{import_regex, resolve_import};
+use crate::util::position::byte_offset_to_position;
+use crate::util::text::strip_bom;
+use crate::util::uri::{canonical_path, path_to_uri};
+
+/// `textDocument/documentLink`: the path of every import, linked to the file
+/// it resolves to. Imports that don't resolve get a link without a target,
+/// so they still show up as recognized.
+pub fn handle_document_link(req: &Value) -> Option<String> {
+    let params: DocumentLinkParams = serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let path = params.text_document.uri.to_file_path().ok()?;
+    let text = documents::text(params.text_document.uri.as_str())
+        .or_else(|| fs::read_to_string(&path).ok())?;
+    let text = strip_bom(&text);
+
+    let project_root = project_root_or_parent(&path);
+    let remappings = remappings_for(&path, &project_root);
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let links: Vec<DocumentLink> = import_regex()
+        .captures_iter(text)
+        .filter_map(|cap| {
+            let import = cap.get(1)?;
+            let target = resolve_import(import.as_str().trim(), dir, &remappings, &project_root)
+                .filter(|file| file.is_file())
+                .and_then(|file| path_to_uri(&canonical_path(&file)))
+                .and_then(|uri| uri.parse().ok());
+            Some(DocumentLink {
+                range: Range {
+                    start: byte_offset_to_position(text, import.start()),
+                    end: byte_offset_to_position(text, import.end()),
+                },
+                tooltip: target.is_none().then(|| "Import not found".to_string()),
+                target,
+                data: None,
+            })
+        })
+        .collect();
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": links,
+    }).to_string())
+}
@@ -0,0 +1,133 @@
+use lsp_types::{DocumentSymbol, DocumentSymbolParams, Range, SymbolKind};
+use serde_json::{json, Map, Value};
+
+use crate::analysis::ast::{parse_src_span, SourceUnit, AST_MAP};
+use crate::util::log::log_to_file;
+use crate::util::position::byte_offset_to_position;
+
+fn span_range(unit: &SourceUnit, src: Option<&Value>) -> Option<Range> {
+    let (start, end, file) = parse_src_span(src?.as_str()?)?;
+    (file == unit.file_index).then(|| Range {
+        start: byte_offset_to_position(&unit.content, start),
+        end: byte_offset_to_position(&unit.content, end),
+    })
+}
+
+/// Names of the direct bases in `is` order, e.g. `is ERC20, Ownable`
+fn inheritance_detail(node: &Map<String, Value>) -> Option<String> {
+    let bases: Vec<&str> = node
+        .get("baseContracts")?
+        .as_array()?
+        .iter()
+        .filter_map(|base| {
+            let name = base.get("baseName")?;
+            name.get("name").or_else(|| name.get("namePath"))?.as_str()
+        })
+        .collect();
+    (!bases.is_empty()).then(|| format!("is {}", bases.join(", ")))
+}
+
+fn symbol_kind(node: &Map<String, Value>, node_type: &str) -> Option<SymbolKind> {
+    let kind = match node_type {
+        "ContractDefinition" => match node.get("contractKind").and_then(|v| v.as_str()) {
+            Some("interface") => SymbolKind::INTERFACE,
+            Some("library") => SymbolKind::MODULE,
+            _ => SymbolKind::CLASS,
+        },
+        "FunctionDefinition" => match node.get("kind").and_then(|v| v.as_str()) {
+            Some("constructor") => SymbolKind::CONSTRUCTOR,
+            _ => SymbolKind::FUNCTION,
+        },
+        "ModifierDefinition" => SymbolKind::METHOD,
+        "EventDefinition" => SymbolKind::EVENT,
+        "ErrorDefinition" => SymbolKind::OBJECT,
+        "StructDefinition" => SymbolKind::STRUCT,
+        "EnumDefinition" => SymbolKind::ENUM,
+        "EnumValue" => SymbolKind::ENUM_MEMBER,
+        "UserDefinedValueTypeDefinition" => SymbolKind::TYPE_PARAMETER,
+        "VariableDeclaration" => match node.get("constant").and_then(|v| v.as_bool()) {
+            Some(true) => SymbolKind::CONSTANT,
+            _ => SymbolKind::FIELD,
+        },
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Child nodes that appear in the outline under a declaration: contract
+/// members, struct members and enum values. Function bodies are not descended into.
+fn member_nodes<'a>(node: &'a Map<String, Value>, node_type: &str) -> &'a [Value] {
+    let key = match node_type {
+        "SourceUnit" | "ContractDefinition" => "nodes",
+        "StructDefinition" | "EnumDefinition" => "members",
+        _ => return &[],
+    };
+    node.get(key).and_then(|v| v.as_array()).map_or(&[], Vec::as_slice)
+}
+
+fn symbols(unit: &SourceUnit, nodes: &[Value]) -> Vec<DocumentSymbol> {
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let node = node.as_object()?;
+            let node_type = node.get("nodeType")?.as_str()?;
+            let kind = symbol_kind(node, node_type)?;
+
+            let name = match node.get("name").and_then(|v| v.as_str()) {
+                Some(name) if !name.is_empty() => name.to_string(),
+                // constructor, fallback and receive are unnamed
+                _ => node.get("kind")?.as_str()?.to_string(),
+            };
+            let range = span_range(unit, node.get("src"))?;
+            let selection_range = span_range(unit, node.get("nameLocation"))
+                .filter(|name| range.start <= name.start && name.end <= range.end)
+                .unwrap_or(range);
+
+            let detail = match node_type {
+                "ContractDefinition" => inheritance_detail(node),
+                "VariableDeclaration" => node
+                    .get("typeDescriptions")
+                    .and_then(|t| t.get("typeString"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string),
+                _ => None,
+            };
+            let children = symbols(unit, member_nodes(node, node_type));
+
+            #[allow(deprecated)] // `deprecated` is a required field of the struct
+            Some(DocumentSymbol {
+                name,
+                detail,
+                kind,
+                tags: None,
+                deprecated: None,
+                range,
+                selection_range,
+                children: (!children.is_empty()).then_some(children),
+            })
+        })
+        .collect()
+}
+
+/// `textDocument/documentSymbol`: the outline of the file's last compile.
+/// Contracts list their direct bases as `detail`, e.g. `is ERC20, Ownable`.
+pub fn handle_document_symbol(req: &Value) -> Option<String> {
+    let params: DocumentSymbolParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document.uri.to_string();
+
+    let units = AST_MAP.lock().ok()?;
+    let result = match units.get(&uri) {
+        Some(unit) => symbols(unit, member_nodes(unit.ast.as_object()?, "SourceUnit")),
+        None => {
+            log_to_file(&format!("[documentSymbol] No AST for {}", uri));
+            vec![]
+        }
+    };
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": result,
+    }).to_string())
+}
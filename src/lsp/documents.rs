@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use crate::util::text::strip_bom;
+
+/// In-memory copy of a document the client has open
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub text: String,
+    /// `textDocument.version` of the last open or change, if the client sent one
+    pub version: Option<i32>,
+}
+
+/// Global map: file URI → open document
+pub static DOCUMENTS: Lazy<Mutex<HashMap<String, Document>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Store the latest text of a document. A `None` version (e.g. from
+/// `didSave`) keeps the version already known. A leading BOM is dropped.
+pub fn open_or_update(uri: &str, text: &str, version: Option<i32>) -> Option<i32> {
+    let mut docs = DOCUMENTS.lock().ok()?;
+    let version = version.or_else(|| docs.get(uri).and_then(|doc| doc.version));
+    docs.insert(
+        uri.to_string(),
+        Document {
+            text: strip_bom(text).to_string(),
+            version,
+        },
+    );
+    version
+}
+
+pub fn close(uri: &str) {
+    if let Ok(mut docs) = DOCUMENTS.lock() {
+        docs.remove(uri);
+    }
+}
+
+/// Snapshot of all open documents as (uri, document) pairs
+pub fn snapshot() -> Vec<(String, Document)> {
+    DOCUMENTS
+        .lock()
+        .map(|docs| {
+            docs.iter()
+                .map(|(uri, doc)| (uri.clone(), doc.clone()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// An open document
+pub fn get(uri: &str) -> Option<Document> {
+    DOCUMENTS.lock().ok()?.get(uri).cloned()
+}
+
+/// Current text of an open document
+pub fn text(uri: &str) -> Option<String> {
+    DOCUMENTS.lock().ok()?.get(uri).map(|doc| doc.text.clone())
+}
@@ -1,78 +1,184 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::{thread, time::Duration};
-use crate::solc::manager::SolcManager;
+use crate::solc::manager::{solc_cache_dir, SolcManager};
+use crate::solc::error::SolcError;
+use crate::solc::identity::{long_version, refresh_system_solc, system_solc, system_solc_version};
+use crate::solc::switcher::{
+    cached_compilers, find_solidity_pragma, get_solc_binary_from_cache, parse_pragma,
+    set_compiler_override, unavailable_version_message,
+};
+use crate::solc::usage;
 use crate::solc::versions::SolcList;
 
 use lsp_types::{
     Diagnostic, DiagnosticSeverity, InitializeResult, PublishDiagnosticsParams, Range,
     ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
-    GotoDefinitionResponse, Location, TextDocumentPositionParams, OneOf,
+    GotoDefinitionResponse, TextDocumentPositionParams, OneOf, Position,
+    ClientCapabilities, DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions,
+    FileSystemWatcher, GlobPattern, Registration, RegistrationParams,
+    SelectionRangeProviderCapability, LinkedEditingRangeServerCapabilities,
+    CallHierarchyServerCapability, WorkDoneProgressCancelParams, NumberOrString,
+    CodeActionProviderCapability, LocationLink, HoverProviderCapability, DiagnosticTag,
+    DiagnosticServerCapabilities, DiagnosticOptions, DocumentLinkOptions, MessageType, Location,
+    ExecuteCommandOptions,
 };
+use regex::Regex;
 use serde_json::{json, Value};
 
-use crate::project::remappings::{parse_remappings, Remapping};
-use crate::project::root::find_project_root;
-use crate::util::fs::run_solc;
+use crate::project::remappings::{
+    forge_std_missing, has_hardhat_config, invalidate_node_modules, invalidate_remappings,
+    parse_remappings, Remapping,
+};
+use crate::project::root::{
+    find_project_root, invalidate_project_roots, is_single_file, PROJECT_FILES,
+};
+use crate::project::workspace::foundry_ignored_error_codes;
+use crate::lsp::document_diagnostic::handle_document_diagnostic;
+use crate::lsp::document_link::handle_document_link;
+use crate::lsp::document_symbol::handle_document_symbol;
+use crate::lsp::dependencies;
+use crate::lsp::documents;
+use crate::lsp::indexer::spawn_workspace_index;
+use crate::lsp::code_action::{handle_code_action, MISSING_PRAGMA_CODE};
+use crate::lsp::call_hierarchy::{
+    handle_incoming_calls, handle_outgoing_calls, handle_prepare_call_hierarchy,
+};
+use crate::lsp::hover::handle_hover;
+use crate::lsp::type_hierarchy::{
+    handle_prepare_type_hierarchy, handle_subtypes, handle_supertypes,
+};
+use crate::lsp::linked_editing::handle_linked_editing_range;
+use crate::lsp::progress::{self, Progress};
+use crate::lsp::outgoing;
+use crate::lsp::published;
+use crate::lsp::revalidate;
+use crate::lsp::selection_range::handle_selection_range;
+use crate::lsp::worker;
+use crate::util::fs::{run_solc, SourcePaths};
+use crate::util::hash::sha256_hex;
+use crate::util::imports::resolve_sources_with_report;
 use crate::util::log::log_to_file;
 
-use crate::analysis::definitions::DEFINITION_MAP;
+use crate::analysis::ast::{imported_uris, AST_MAP};
+use crate::analysis::inheritance::linearization_ranks;
+use crate::analysis::references::{contains, reference_at};
+use crate::analysis::artifacts::{
+    load_definitions_from_artifacts, load_definitions_from_build_info,
+};
+use crate::analysis::persist::{load_definition_index, save_definition_index};
+use crate::analysis::definitions::{
+    qualified_member_key, struct_name_from_type_string, Definition, DefinitionIndex, DEFINITION_MAP,
+};
+use crate::config::{self, Config};
 use crate::util::position::{byte_offset_to_position, position_to_byte_offset};
 
-use crate::util::text::extract_identifier_at;
-use once_cell::sync::OnceCell;
-use std::sync::Arc;
-use crate::solc::platform::get_platform_id;
+use crate::util::text::{identifier_range_at, member_access_base, strip_bom};
+use crate::util::uri::{canonical_path, normalize_uri, path_to_uri};
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::solc::fetch::{binary_hosts, drain_downloads, fetch_list_json, retry_delay};
 
 pub static SOLC_MANAGER: OnceCell<Arc<SolcManager>> = OnceCell::new();
+pub static CLIENT_CAPABILITIES: OnceCell<ClientCapabilities> = OnceCell::new();
+pub static WORKSPACE_ROOT: OnceCell<PathBuf> = OnceCell::new();
+/// Set by `shutdown`; afterwards only `exit` is handled
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The compiler problem affecting every file last shown to the user; cleared
+/// once a compile gets past it, so it is shown again if it comes back
+static SOLC_PROBLEM_SHOWN: Mutex<Option<String>> = Mutex::new(None);
+
+/// How long `shutdown` waits for running compiler downloads
+const DOWNLOAD_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub fn handle_request(request: &str) -> Option<String> {
-    let parsed: Value = serde_json::from_str(request).ok()?;
+    let mut parsed: Value = serde_json::from_str(request).ok()?;
+    normalize_document_uris(&mut parsed);
     let method = parsed.get("method")?.as_str()?;
 
+    if SHUTDOWN_REQUESTED.load(Ordering::SeqCst) && method != "exit" {
+        // notifications are dropped, requests refused
+        let id = parsed.get("id")?.clone();
+        return Some(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32600, "message": "Server is shutting down" },
+        }).to_string());
+    }
+
     match method {
         "initialize" => {
             let id = parsed.get("id")?.clone();
 
+            config::load_from_init_options(
+                parsed.get("params").and_then(|p| p.get("initializationOptions")),
+            );
+
+            if let Some(root) = parsed.get("params").and_then(workspace_root_from_params) {
+                let project_root = find_project_root(&root).unwrap_or_else(|| root.clone());
+                thread::spawn(move || load_definition_index(&project_root));
+                let _ = WORKSPACE_ROOT.set(root);
+            }
+
+            if let Some(caps) = parsed
+                .get("params")
+                .and_then(|p| p.get("capabilities"))
+                .and_then(|c| serde_json::from_value::<ClientCapabilities>(c.clone()).ok())
+            {
+                let _ = CLIENT_CAPABILITIES.set(caps);
+            }
+
+            log_to_file(&format!("[solc-fetch] Binary hosts: {:?}", binary_hosts()));
+
             // Spawn background sync of latest solc versions
             thread::spawn(|| {
-                let cache_dir = dirs::cache_dir()
-                    .unwrap_or_else(|| PathBuf::from(".cache"))
-                    .join("emacs-solidity-server/solc");
+                let cache_dir = solc_cache_dir();
                 std::fs::create_dir_all(&cache_dir)
                     .expect("Failed to create cache directory");
 
                 let list_path = cache_dir.join("list.json");
 
-                let platform = get_platform_id();
-                let url = format!(
-                    "https://binaries.soliditylang.org/{}/list.json",
-                    platform
-                );
-
-                loop {
-                    match crate::solc::fetch::download_to_file(&url, &list_path) {
-                        Ok(_) => break,
+                let mut attempt = 0;
+                let list_changed = loop {
+                    match fetch_list_json(&list_path) {
+                        Ok(changed) => break changed,
+                        // offline: the list from the last session will do
+                        Err(e) if list_path.exists() => {
+                            log_to_file(&format!(
+                                "[solc-sync] Failed to refresh list.json, using the cached one: {:?}",
+                                e
+                            ));
+                            break false;
+                        }
                         Err(e) => {
                             log_to_file(&format!(
                                 "[solc-sync] Failed to download list.json, retrying: {:?}",
                                 e
                             ));
-                            thread::sleep(Duration::from_secs(5));
+                            thread::sleep(retry_delay(attempt));
+                            attempt += 1;
                         }
                     }
-                }
+                };
 
                 if let Ok(list) = SolcList::from_file(&list_path) {
                     let manager = Arc::new(SolcManager::new(cache_dir.clone(), list));
+                    manager.sweep_cache();
 
-                    if let Err(err) = manager.ensure_latest_versions() {
-                        log_to_file(&format!(
-                            "[solc-sync] Error ensuring solc versions: {:?}",
-                            err
-                        ));
+                    let all_cached = manager
+                        .list()
+                        .latest_per_minor()
+                        .values()
+                        .all(|release| manager.get_binary_path(&release.version).is_some());
+                    if config::get().auto_update && !list_changed && all_cached {
+                        log_to_file("[solc-sync] Release list unchanged and its latest compilers cached");
+                    } else if config::get().auto_update {
+                        sync_latest_versions(&manager);
                     } else {
-                        log_to_file("[solc-sync] Successfully ensured latest solc versions");
+                        log_to_file("[solc-sync] Auto-update disabled; compilers are fetched on demand");
                     }
 
                     if let Err(err) = manager.clean_unused_exact_versions() {
@@ -85,6 +191,7 @@ pub fn handle_request(request: &str) -> Option<String> {
                     if SOLC_MANAGER.set(manager.clone()).is_err() {
                         log_to_file("[solc-sync] SOLC_MANAGER already set");
                     }
+                    enforce_cache_quota();
                 }
             });
 
@@ -94,6 +201,26 @@ pub fn handle_request(request: &str) -> Option<String> {
                         TextDocumentSyncKind::FULL,
                     )),
                     definition_provider: Some(OneOf::Left(true)),
+                    selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                    linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(true)),
+                    call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                    code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                    hover_provider: Some(HoverProviderCapability::Simple(true)),
+                    document_symbol_provider: Some(OneOf::Left(true)),
+                    document_link_provider: Some(DocumentLinkOptions {
+                        resolve_provider: Some(false),
+                        work_done_progress_options: Default::default(),
+                    }),
+                    execute_command_provider: Some(ExecuteCommandOptions {
+                        commands: vec![RECOMPILE_COMMAND.into()],
+                        work_done_progress_options: Default::default(),
+                    }),
+                    diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                        identifier: Some("solc".into()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: false,
+                        ..Default::default()
+                    })),
                     ..Default::default()
                 },
                 server_info: Some(lsp_types::ServerInfo {
@@ -101,7 +228,34 @@ pub fn handle_request(request: &str) -> Option<String> {
                     version: Some("0.1.0".into()),
                 }),
             };
-            return Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string());
+            // lsp-types has no `typeHierarchyProvider` field yet
+            let mut result = serde_json::to_value(result).ok()?;
+            result["capabilities"]["typeHierarchyProvider"] = json!(true);
+            Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string())
+        }
+
+        "initialized" => {
+            let single_file = WORKSPACE_ROOT.get().is_none_or(|root| is_single_file(root));
+            if single_file {
+                log_to_file("[init] No project around the workspace root; single-file mode");
+            }
+            if config::get().index_workspace
+                && !single_file
+                && let Some(root) = WORKSPACE_ROOT.get()
+            {
+                spawn_workspace_index(
+                    find_project_root(root).unwrap_or_else(|| root.clone()),
+                    root.clone(),
+                );
+            }
+            if let Some(secs) = config::get().revalidate_interval_secs.filter(|secs| *secs > 0) {
+                revalidate::spawn_timer(Duration::from_secs(secs));
+            }
+            if single_file {
+                // nothing to watch: there is no project config to change
+                return None;
+            }
+            register_config_watchers()
         }
 
         "textDocument/didOpen"
@@ -118,7 +272,7 @@ pub fn handle_request(request: &str) -> Option<String> {
                 params
                     .get("contentChanges")?
                     .as_array()?
-                    .get(0)?
+                    .first()?
                     .get("text")?
                     .as_str()?
             } else {
@@ -128,124 +282,1411 @@ pub fn handle_request(request: &str) -> Option<String> {
                     .as_str()?
             };
 
-            return handle_and_publish(uri, source_code);
+            let version = params
+                .get("textDocument")?
+                .get("version")
+                .and_then(|v| v.as_i64())
+                .map(|v| v as i32);
+            let version = documents::open_or_update(uri, source_code, version);
+
+            // navigation works from the artifacts right away; the compile
+            // below still runs for the file's diagnostics
+            if method == "textDocument/didOpen" && config::get().use_build_artifacts {
+                load_from_build_artifacts(uri);
+            }
+
+            if method == "textDocument/didChange"
+                && config::get().revalidate_interval_secs.is_some_and(|secs| secs > 0)
+            {
+                revalidate::mark_dirty(uri);
+                return None;
+            }
+
+            worker::submit(uri, source_code, version);
+            None
         }
 
-        "textDocument/definition" => {
-            return handle_definition(&parsed);
+        "textDocument/didClose" => {
+            let uri = parsed
+                .get("params")?
+                .get("textDocument")?
+                .get("uri")?
+                .as_str()?;
+            documents::close(uri);
+            published::forget(uri);
+            // with workspace indexing on, the index is meant to cover every file
+            if !config::get().index_workspace {
+                dependencies::close(uri);
+            }
+            None
+        }
+
+        "workspace/didChangeWatchedFiles" => {
+            let params: DidChangeWatchedFilesParams =
+                serde_json::from_value(parsed.get("params")?.clone()).ok()?;
+            handle_watched_files_change(params);
+            None
+        }
+
+        "textDocument/definition" => handle_definition(&parsed),
+
+        "textDocument/selectionRange" => handle_selection_range(&parsed),
+
+        "textDocument/linkedEditingRange" => handle_linked_editing_range(&parsed),
+
+        "textDocument/hover" => handle_hover(&parsed),
+
+        "textDocument/diagnostic" => handle_document_diagnostic(&parsed),
+
+        "textDocument/documentSymbol" => handle_document_symbol(&parsed),
+
+        "textDocument/documentLink" => handle_document_link(&parsed),
+
+        "textDocument/codeAction" => handle_code_action(&parsed),
+
+        "textDocument/prepareCallHierarchy" => handle_prepare_call_hierarchy(&parsed),
+        "callHierarchy/incomingCalls" => handle_incoming_calls(&parsed),
+        "callHierarchy/outgoingCalls" => handle_outgoing_calls(&parsed),
+
+        "textDocument/prepareTypeHierarchy" => handle_prepare_type_hierarchy(&parsed),
+        "typeHierarchy/supertypes" => handle_supertypes(&parsed),
+        "typeHierarchy/subtypes" => handle_subtypes(&parsed),
+
+        "workspace/executeCommand" => handle_execute_command(&parsed),
+
+        "solidity/remappings" => handle_dump_remappings(&parsed),
+
+        "solidity/resolvedSources" => handle_resolved_sources(&parsed),
+
+        "solidity/whichSolc" => handle_which_solc(&parsed),
+
+        "solidity/refreshSystemSolc" => handle_refresh_system_solc(&parsed),
+
+        "solidity/listCompilers" => handle_list_compilers(&parsed),
+
+        "solidity/setCompilerOverride" => handle_set_compiler_override(&parsed),
+
+        "emacs-solidity-server/diagnostics" => published::handle_dump_diagnostics(&parsed),
+
+        "window/workDoneProgress/cancel" => {
+            if let Some(token) = parsed
+                .get("params")
+                .cloned()
+                .and_then(|p| serde_json::from_value::<WorkDoneProgressCancelParams>(p).ok())
+            {
+                progress::cancel(&token.token);
+            }
+            None
         }
 
         "shutdown" => {
+            SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+            drain_downloads(DOWNLOAD_DRAIN_TIMEOUT);
+            if let Some(root) = WORKSPACE_ROOT.get() {
+                save_definition_index(&find_project_root(root).unwrap_or_else(|| root.clone()));
+            }
             let id = parsed.get("id")?.clone();
-            return Some(json!({ "jsonrpc": "2.0", "id": id, "result": null }).to_string());
+            Some(json!({ "jsonrpc": "2.0", "id": id, "result": null }).to_string())
+        }
+        "exit" => {
+            let clean = SHUTDOWN_REQUESTED.load(Ordering::SeqCst);
+            if !clean {
+                // no `shutdown` first: still don't leave a truncated binary behind
+                drain_downloads(Duration::ZERO);
+            }
+            std::process::exit(if clean { 0 } else { 1 })
         }
-        "exit" => std::process::exit(0),
 
         _ => None,
     }
 }
 
-fn handle_and_publish(uri: &str, source_code: &str) -> Option<String> {
-    log_to_file("Reached handle_and_publish");
+/// Rewrite the document URIs of a message the way the server builds them,
+/// so every map keyed by URI agrees (e.g. Windows drive letter case)
+fn normalize_document_uris(message: &mut Value) {
+    let Some(params) = message.get_mut("params") else {
+        return;
+    };
+    for pointer in ["/textDocument/uri", "/uri"] {
+        if let Some(uri) = params.pointer_mut(pointer)
+            && let Some(text) = uri.as_str()
+        {
+            *uri = Value::String(normalize_uri(text));
+        }
+    }
+}
+
+/// Index a freshly opened file from build artifacts, if fresh ones exist,
+/// so goto-definition doesn't have to wait for its first compile
+fn load_from_build_artifacts(uri: &str) {
+    let Some(source_path) = Url::parse(uri).ok().and_then(|u| u.to_file_path().ok()) else {
+        return;
+    };
+    let Some(project_root) = find_project_root(&source_path) else {
+        return;
+    };
+
+    let loaded = if has_hardhat_config(&project_root) {
+        load_definitions_from_build_info(&project_root, &source_path)
+    } else {
+        load_definitions_from_artifacts(&project_root, &source_path, workspace_root())
+    };
+    log_to_file(&format!(
+        "[artifacts] {} {}",
+        if loaded { "Indexed from artifacts:" } else { "No fresh artifact:" },
+        source_path.display()
+    ));
+}
+
+/// Workspace root from `initialize` params: `rootUri`, then the first folder, then `rootPath`
+fn workspace_root_from_params(params: &Value) -> Option<PathBuf> {
+    let uri = params
+        .get("rootUri")
+        .and_then(|v| v.as_str())
+        .or_else(|| {
+            params
+                .get("workspaceFolders")?
+                .as_array()?
+                .first()?
+                .get("uri")?
+                .as_str()
+        });
+
+    match uri {
+        Some(uri) => Url::parse(uri).ok()?.to_file_path().ok(),
+        None => params.get("rootPath")?.as_str().map(PathBuf::from),
+    }
+}
+
+pub fn workspace_root() -> Option<&'static Path> {
+    WORKSPACE_ROOT.get().map(PathBuf::as_path)
+}
+
+/// Project root containing `path`, or its parent directory outside any project
+pub fn project_root_or_parent(path: &Path) -> PathBuf {
+    find_project_root(path)
+        .unwrap_or_else(|| path.parent().unwrap_or(Path::new("/")).to_path_buf())
+}
+
+/// Remappings `path` compiles with. A single file outside any project gets
+/// none, so only its relative imports resolve.
+pub fn remappings_for(path: &Path, project_root: &Path) -> Vec<Remapping> {
+    if is_single_file(path) {
+        return vec![];
+    }
+    parse_remappings(project_root, workspace_root())
+}
+
+/// Custom request: list the sources pulled in for a URI and the imports that failed
+fn handle_resolved_sources(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let uri = req.get("params")?.get("uri")?.as_str()?;
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+    let project_root = project_root_or_parent(&path);
+
+    let remappings = remappings_for(&path, &project_root);
+    let mut visited = HashSet::new();
+    let mut unresolved = Vec::new();
+    let sources = resolve_sources_with_report(
+        &project_root,
+        &path,
+        &remappings,
+        &mut visited,
+        &mut unresolved,
+    );
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": {
+            "projectRoot": project_root.to_string_lossy(),
+            "sources": sources.keys().collect::<Vec<_>>(),
+            "unresolved": unresolved,
+        },
+    }).to_string())
+}
+
+/// Custom request: report the project root and effective remappings for a URI
+fn handle_dump_remappings(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let uri = req.get("params")?.get("uri")?.as_str()?;
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+
+    let project_root = project_root_or_parent(&path);
+    let remappings: Vec<String> = remappings_for(&path, &project_root)
+        .iter()
+        .map(|r| r.to_solc(&project_root))
+        .collect();
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": {
+            "projectRoot": project_root.to_string_lossy(),
+            "remappings": remappings,
+        },
+    }).to_string())
+}
+
+/// `solidity/whichSolc`: the compiler a file compiles with and its exact build
+fn handle_which_solc(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let uri = req.get("params")?.get("uri")?.as_str()?;
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+
+    let binary = usage::resolved(&path).or_else(|| {
+        let text = documents::text(uri).or_else(|| fs::read_to_string(&path).ok())?;
+        get_solc_binary_from_cache(&path, &text, &project_root_or_parent(&path)).ok()
+    });
+    let result = binary.map(|binary| {
+        json!({
+            "path": binary.to_string_lossy(),
+            "longVersion": long_version(&binary),
+        })
+    });
+
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string())
+}
+
+/// `solidity/refreshSystemSolc`: look up the `solc` on `PATH` again, e.g.
+/// after switching it with `solc-select`, and report what was found
+fn handle_refresh_system_solc(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    refresh_system_solc();
+    let result = system_solc().map(|path| {
+        json!({
+            "path": path.to_string_lossy(),
+            "version": system_solc_version().map(|v| v.to_string()),
+        })
+    });
+
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string())
+}
+
+/// Command recompiling the document whose URI is its argument
+pub const RECOMPILE_COMMAND: &str = "emacs-solidity-server.recompile";
+
+/// `workspace/executeCommand`. The only command is [`RECOMPILE_COMMAND`]:
+/// forget the cached project layout and compile the file afresh, e.g. after
+/// `forge install` or an edit to an imported file outside the editor.
+fn handle_execute_command(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let params = req.get("params")?;
+    let command = params.get("command")?.as_str()?;
+    let uri = params
+        .get("arguments")
+        .and_then(|args| args.get(0))
+        .and_then(Value::as_str);
+
+    let (Some(uri), RECOMPILE_COMMAND) = (uri, command) else {
+        let message = format!("Unknown command or missing document URI: {}", command);
+        return Some(
+            json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32602, "message": message } })
+                .to_string(),
+        );
+    };
+
+    let path = Url::parse(uri).ok().and_then(|u| u.to_file_path().ok());
+    let doc = documents::get(uri).or_else(|| {
+        let text = fs::read_to_string(path.as_ref()?).ok()?;
+        Some(documents::Document { text, version: None })
+    });
+    let Some(doc) = doc else {
+        let message = format!("Cannot read {}", uri);
+        return Some(
+            json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32602, "message": message } })
+                .to_string(),
+        );
+    };
+
+    invalidate_project_roots();
+    invalidate_remappings();
+    if let Some(root) = path.as_deref().map(project_root_or_parent) {
+        invalidate_node_modules(&root);
+    }
+
+    log_to_file(&format!("[command] Recompiling {}", uri));
+    let name = path.map_or(uri.to_string(), |p| p.display().to_string());
+    worker::submit_then(uri, &doc.text, doc.version, move || {
+        let _ = outgoing::show_message(MessageType::INFO, &format!("Recompiled {}", name));
+    });
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": null }).to_string())
+}
+
+/// `solidity/listCompilers`: every downloaded compiler, newest first
+fn handle_list_compilers(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let result: Vec<Value> = cached_compilers()
+        .into_iter()
+        .map(|(version, path)| {
+            json!({ "version": version.to_string(), "path": path.to_string_lossy() })
+        })
+        .collect();
+
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string())
+}
+
+/// `solidity/setCompilerOverride`: compile one file with the given `version`
+/// instead of what its pragma asks for, or with its pragma again when
+/// `version` is null. An open document is recompiled right away.
+fn handle_set_compiler_override(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let params = req.get("params")?;
+    let uri = params.get("uri")?.as_str()?;
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+
+    let version = match params.get("version").and_then(Value::as_str) {
+        Some(version) => match semver::Version::parse(version.trim().trim_start_matches('v')) {
+            Ok(version) => Some(version),
+            Err(e) => {
+                let message = format!("Invalid version '{}': {}", version, e);
+                return Some(
+                    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32602, "message": message } })
+                        .to_string(),
+                );
+            }
+        },
+        None => None,
+    };
+    log_to_file(&format!("[solc-switch] Override for {}: {:?}", path.display(), version));
+    set_compiler_override(&path, version);
+
+    if let Some(doc) = documents::get(uri) {
+        worker::submit(uri, &doc.text, doc.version);
+    }
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": null }).to_string())
+}
+
+/// Ask the client to watch project config files so remapping edits are picked up
+fn register_config_watchers() -> Option<String> {
+    let dynamic = CLIENT_CAPABILITIES
+        .get()
+        .and_then(|c| c.workspace.as_ref())
+        .and_then(|w| w.did_change_watched_files.as_ref())
+        .and_then(|d| d.dynamic_registration)
+        .unwrap_or(false);
+    if !dynamic {
+        return None;
+    }
+
+    // package.json: installed packages feed the Hardhat remappings
+    let watchers = PROJECT_FILES
+        .iter()
+        .chain(&["package.json"])
+        .map(|file| FileSystemWatcher {
+            glob_pattern: GlobPattern::String(format!("**/{}", file)),
+            kind: None,
+        })
+        .collect();
+
+    let params = RegistrationParams {
+        registrations: vec![Registration {
+            id: "emacs-solidity-server/watch-project-config".into(),
+            method: "workspace/didChangeWatchedFiles".into(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        }],
+    };
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": "register-config-watchers",
+        "method": "client/registerCapability",
+        "params": params,
+    }).to_string())
+}
+
+/// Download the latest release of every minor series, reporting progress
+fn sync_latest_versions(manager: &SolcManager) {
+    let progress = Progress::begin(
+        "Syncing solc compilers",
+        manager.list().latest_per_minor().len(),
+    );
+    let is_cancelled = || progress.as_ref().is_some_and(Progress::is_cancelled);
+    let result = manager.ensure_latest_versions(&is_cancelled, |done, total, version| {
+        if let Some(p) = &progress {
+            p.report(done, &format!("solc {} ({}/{})", version, done, total));
+        }
+    });
+    if let Some(p) = progress {
+        let message = if p.is_cancelled() {
+            "solc sync cancelled"
+        } else {
+            "solc compilers up to date"
+        };
+        p.end(message);
+    }
+
+    if let Err(err) = result {
+        log_to_file(&format!(
+            "[solc-sync] Error ensuring solc versions: {:?}",
+            err
+        ));
+    } else {
+        log_to_file("[solc-sync] Successfully ensured latest solc versions");
+    }
+}
+
+/// Shrink the compiler cache to the configured quota, keeping every compiler
+/// an open document was last compiled with
+pub fn enforce_cache_quota() {
+    let (Some(quota_mb), Some(manager)) = (config::get().cache_quota_mb, SOLC_MANAGER.get()) else {
+        return;
+    };
+
+    let open: Vec<PathBuf> = documents::snapshot()
+        .into_iter()
+        .filter_map(|(uri, _)| Url::parse(&uri).ok()?.to_file_path().ok())
+        .collect();
+    let keep = usage::in_use(open.iter().map(PathBuf::as_path));
+
+    if let Err(e) = manager.enforce_quota(quota_mb.saturating_mul(1024 * 1024), &keep) {
+        log_to_file(&format!("[solc-prune] Error enforcing cache quota: {:?}", e));
+    }
+}
+
+/// Recompile open documents under any project whose config file changed
+fn handle_watched_files_change(params: DidChangeWatchedFilesParams) {
+    let changed_dirs: Vec<PathBuf> = params
+        .changes
+        .iter()
+        .filter_map(|change| change.uri.to_file_path().ok())
+        .filter_map(|path| path.parent().map(Path::to_path_buf))
+        .collect();
+
+    if changed_dirs.is_empty() {
+        return;
+    }
+    log_to_file(&format!("[watch] Project config changed in {:?}", changed_dirs));
+    invalidate_project_roots();
+    invalidate_remappings();
+    for dir in &changed_dirs {
+        invalidate_node_modules(dir);
+    }
+
+    for (uri, doc) in documents::snapshot() {
+        let Some(path) = Url::parse(&uri).ok().and_then(|u| u.to_file_path().ok()) else {
+            continue;
+        };
+        if !changed_dirs.iter().any(|dir| path.starts_with(dir)) {
+            continue;
+        }
+        worker::submit(&uri, &doc.text, doc.version);
+    }
+}
+
+/// Diagnostics of one compile, by file URI. The entry file is always present.
+pub type FileDiagnostics = HashMap<String, Vec<Diagnostic>>;
+
+/// Entry file → other files its last compile published diagnostics for
+static RELATED_PUBLISHED: Lazy<Mutex<HashMap<String, HashSet<String>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Compile a document and build a `publishDiagnostics` notification for it
+/// and for every other file the compile reported problems in. Files that had
+/// diagnostics from the previous compile of `uri` but have none now are cleared.
+pub fn handle_and_publish(uri: &str, source_code: &str, version: Option<i32>) -> Vec<String> {
+    let Some(mut by_file) = compile_diagnostics(uri, source_code) else {
+        return vec![];
+    };
+
+    let related: HashSet<String> = by_file.keys().filter(|file| *file != uri).cloned().collect();
+    if let Ok(mut published) = RELATED_PUBLISHED.lock() {
+        for stale in published.insert(uri.to_string(), related).unwrap_or_default() {
+            by_file.entry(stale).or_default();
+        }
+    }
+
+    by_file
+        .into_iter()
+        .filter_map(|(file, diagnostics)| {
+            // only the entry's text is known to match a client version
+            let version = if file == uri { version } else { None };
+            publish_diagnostics(&file, version, diagnostics)
+        })
+        .collect()
+}
+
+/// What to show in the file for a compile that produced no output; `None`
+/// when it isn't worth a diagnostic (it is logged, or shown once as a message)
+fn compile_error_diagnostic(e: &SolcError, source_code: &str) -> Option<Diagnostic> {
+    // a version no release has is the problem to report, not the
+    // missing compiler it leads to
+    if !matches!(e, SolcError::ShuttingDown)
+        && let Some(diagnostic) = unavailable_version(source_code)
+    {
+        return Some(diagnostic);
+    }
+    let severity = match e {
+        // something the user has to fix in the file or the cache
+        SolcError::NotExecutable { .. }
+        | SolcError::InvalidPragma(_)
+        | SolcError::ChecksumMismatch { .. } => DiagnosticSeverity::ERROR,
+        // transient: the next compile may well succeed
+        SolcError::TimedOut(_) | SolcError::NotFound { download_pending: true, .. } => {
+            DiagnosticSeverity::INFORMATION
+        }
+        // nothing on its way will fix it
+        SolcError::NotFound { download_pending: false, .. } => DiagnosticSeverity::WARNING,
+        // affects every file, so say it once rather than in each of them
+        SolcError::UnsupportedPlatform { .. } | SolcError::DownloadFailed { .. } => {
+            show_solc_problem(&e.to_string());
+            return None;
+        }
+        SolcError::ShuttingDown | SolcError::Io(_) => return None,
+    };
+    Some(file_diagnostic(severity, &e.to_string()))
+}
+
+/// Show a compiler problem affecting every file, unless it is the one
+/// already shown
+fn show_solc_problem(message: &str) {
+    let Ok(mut shown) = SOLC_PROBLEM_SHOWN.lock() else {
+        return;
+    };
+    if shown.as_deref() != Some(message) {
+        let _ = outgoing::show_message(MessageType::ERROR, message);
+        *shown = Some(message.to_string());
+    }
+}
+
+fn entry_only(uri: &str, diagnostic: Diagnostic) -> FileDiagnostics {
+    HashMap::from([(uri.to_string(), vec![diagnostic])])
+}
+
+/// Compile a document and collect its diagnostics, placed in the file each
+/// one is in. `None` means the compile failed in a way not worth reporting
+/// in the editor (it is logged).
+pub fn compile_diagnostics(uri: &str, source_code: &str) -> Option<FileDiagnostics> {
+    log_to_file("Reached compile_diagnostics");
+    // solc is given the text without its BOM; offsets are converted against the same text
+    let source_code = strip_bom(source_code);
 
     let source_path = Url::parse(uri).ok()?.to_file_path().ok()?;
-    let project_root = find_project_root(&source_path)
-        .unwrap_or_else(|| source_path.parent().unwrap_or(Path::new("/")).to_path_buf());
+    let project_root = project_root_or_parent(&source_path);
 
     log_to_file(&format!("Project root: {}", project_root.display()));
-    let remappings: Vec<Remapping> = parse_remappings(&project_root);
+    let remappings: Vec<Remapping> = remappings_for(&source_path, &project_root);
+
+    // one actionable error instead of a cascade of unresolved imports
+    if forge_std_missing(source_code, &remappings, &project_root) {
+        let message = format!(
+            "forge-std is not installed. Run `forge install foundry-rs/forge-std` in {}",
+            project_root.display()
+        );
+        return Some(entry_only(uri, file_diagnostic(DiagnosticSeverity::ERROR, &message)));
+    }
 
-    let output = run_solc(&source_path, source_code, &remappings, &project_root).ok()?;
+    // explain up front why the compiler may not be the one the file wants
+    let missing_pragma = find_solidity_pragma(source_code).is_none().then(|| Diagnostic {
+        range: Range::default(),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(MISSING_PRAGMA_CODE.into())),
+        source: Some("emacs-solidity-server".into()),
+        message: "No `pragma solidity` directive; using system solc".into(),
+        ..Default::default()
+    });
 
-    if let Ok(stderr) = String::from_utf8(output.stderr.clone()) {
-        if !stderr.trim().is_empty() {
-            log_to_file(&format!("solc stderr:\n{}", stderr));
+    let (output, source_paths) = match run_solc(&source_path, source_code, &remappings, &project_root) {
+        Ok(output) => output,
+        Err(e) if missing_pragma.is_some() => {
+            log_to_file(&format!("[solc] Compilation without pragma failed: {}", e));
+            let mut diagnostic = missing_pragma?;
+            diagnostic.message = format!("{} ({})", diagnostic.message, e);
+            return Some(entry_only(uri, diagnostic));
+        }
+        Err(e) => {
+            log_to_file(&format!("[solc] Compilation failed: {}", e));
+            return compile_error_diagnostic(&e, source_code).map(|d| entry_only(uri, d));
         }
+    };
+
+    if let Ok(mut shown) = SOLC_PROBLEM_SHOWN.lock() {
+        *shown = None;
+    }
+
+    if documents::get(uri).is_some() {
+        let files = source_paths
+            .values()
+            .filter_map(|path| path_to_uri(&canonical_path(path)))
+            .chain([uri.to_string()])
+            .collect();
+        dependencies::record(uri, files);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        log_to_file(&format!("solc stderr:\n{}", stderr));
     }
 
-    let stdout = String::from_utf8(output.stdout).ok()?;
-    let parsed_out: Value = serde_json::from_str(&stdout).unwrap_or_default();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let parsed_out: Value = match serde_json::from_str(&stdout) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            log_to_file(&format!("[solc] Output is not valid JSON: {}", e));
+            let from_stderr = stderr_diagnostics(&stderr);
+            if !from_stderr.is_empty() {
+                let texts = SourceTexts::new(&source_paths, &source_path, source_code);
+                let mut by_file = FileDiagnostics::from([(uri.to_string(), vec![])]);
+                for (file, diagnostic) in from_stderr {
+                    let target = file
+                        .and_then(|file| texts.uri(&file))
+                        .unwrap_or_else(|| uri.to_string());
+                    by_file.entry(target).or_default().push(diagnostic);
+                }
+                return Some(by_file);
+            }
+            let message = unparseable_output_message(&stdout, &stderr);
+            return Some(entry_only(uri, file_diagnostic(DiagnosticSeverity::ERROR, &message)));
+        }
+    };
     let errors = parsed_out["errors"]
         .as_array()
         .cloned()
         .unwrap_or_default();
+    let _ = outgoing::log_message(MessageType::INFO, &compile_summary(&source_path, &errors));
 
-    let diagnostics: Vec<Diagnostic> = errors
-        .iter()
-        .filter_map(|e| {
-            let msg = e.get("message")?.as_str()?.to_owned();
-            let severity = match e.get("severity")?.as_str()? {
-                "error" => Some(DiagnosticSeverity::ERROR),
-                "warning" => Some(DiagnosticSeverity::WARNING),
-                _ => None,
-            };
+    let mut config = config::get();
+    config
+        .ignored_diagnostic_codes
+        .extend(foundry_ignored_error_codes(&project_root));
+    let mut texts = SourceTexts::new(&source_paths, &source_path, source_code);
+    let mut by_file = FileDiagnostics::from([(
+        uri.to_string(),
+        missing_pragma.into_iter().chain(unavailable_version(source_code)).collect(),
+    )]);
+    for e in &errors {
+        let file = e
+            .get("sourceLocation")
+            .and_then(|loc| loc.get("file"))
+            .and_then(|f| f.as_str())
+            .unwrap_or("");
+        let Some(diagnostic) = solc_error_to_diagnostic(e, &mut texts, &config) else {
+            continue;
+        };
+        let target = texts.uri(file).unwrap_or_else(|| uri.to_string());
+        by_file.entry(target).or_default().push(diagnostic);
+    }
+
+    Some(by_file)
+}
+
+/// One-line outcome of a compile, e.g. `solc 0.8.25: 0 errors, 3 warnings`
+fn compile_summary(source_path: &Path, errors: &[Value]) -> String {
+    let count = |severity: &str| {
+        errors
+            .iter()
+            .filter(|e| e.get("severity").and_then(|s| s.as_str()) == Some(severity))
+            .count()
+    };
+    let version = usage::resolved(source_path)
+        .and_then(|binary| long_version(&binary))
+        .map(|long| long.split('+').next().unwrap_or_default().to_string());
+    let (errors, warnings) = (count("error"), count("warning"));
+    format!(
+        "solc {}: {} error{}, {} warning{}",
+        version.as_deref().unwrap_or("(unknown version)"),
+        errors,
+        if errors == 1 { "" } else { "s" },
+        warnings,
+        if warnings == 1 { "" } else { "s" },
+    )
+}
+
+/// Error at the version clause of a pragma no released compiler satisfies,
+/// with the nearest releases as suggestions. Needs the release list, so it
+/// is skipped until the startup sync has loaded it.
+fn unavailable_version(source_code: &str) -> Option<Diagnostic> {
+    let clause = find_solidity_pragma(source_code)?.trim();
+    let pragma = parse_pragma(source_code).ok()?;
+    let message = unavailable_version_message(&pragma, &SOLC_MANAGER.get()?.list())?;
+
+    // `clause` borrows from `source_code`
+    let start = clause.as_ptr() as usize - source_code.as_ptr() as usize;
+    let end = start + clause.len();
+    Some(Diagnostic {
+        range: Range {
+            start: byte_offset_to_position(source_code, start),
+            end: byte_offset_to_position(source_code, end),
+        },
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String("unknown-solc-version".into())),
+        source: Some("emacs-solidity-server".into()),
+        message,
+        ..Default::default()
+    })
+}
+
+/// Files and texts of a compile, by solc source unit name, for placing
+/// diagnostics and converting their byte offsets. The entry file is the
+/// in-memory text solc was given; other files come from the document store or disk.
+struct SourceTexts<'a> {
+    paths: &'a SourcePaths,
+    entry_path: PathBuf,
+    entry_text: &'a str,
+    loaded: HashMap<String, Option<String>>,
+}
+
+impl<'a> SourceTexts<'a> {
+    fn new(paths: &'a SourcePaths, entry_path: &Path, entry_text: &'a str) -> Self {
+        SourceTexts {
+            paths,
+            entry_path: canonical_path(entry_path),
+            entry_text,
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// Physical path of a unit name, `None` for names solc wasn't given
+    fn path(&self, source_name: &str) -> Option<PathBuf> {
+        self.paths.get(source_name).map(|path| canonical_path(path))
+    }
+
+    /// URI of the file behind a unit name; `None` for the entry file (whose
+    /// URI is the one the client sent) and for unknown names
+    fn uri(&self, source_name: &str) -> Option<String> {
+        path_to_uri(&self.path(source_name).filter(|path| *path != self.entry_path)?)
+    }
 
-            let loc = e.get("sourceLocation")?;
+    /// Text of a unit name; `None` for names solc wasn't given and for files
+    /// that can't be read, whose offsets can't be converted
+    fn get(&mut self, source_name: &str) -> Option<&str> {
+        let path = self.path(source_name)?;
+        if path == self.entry_path {
+            return Some(self.entry_text);
+        }
+
+        self.loaded
+            .entry(source_name.to_string())
+            .or_insert_with(|| {
+                Url::from_file_path(&path)
+                    .ok()
+                    .and_then(|uri| documents::text(uri.as_str()))
+                    .or_else(|| fs::read_to_string(&path).ok())
+                    .map(|text| strip_bom(&text).to_string())
+            })
+            .as_deref()
+    }
+}
+
+/// Convert one entry of solc's `errors` array, with offsets converted against
+/// the text of the file the error is in. Errors without a source location
+/// (e.g. a compiler version mismatch), or in a file whose text is unknown,
+/// are placed at the top of the file.
+fn solc_error_to_diagnostic(
+    e: &Value,
+    texts: &mut SourceTexts,
+    config: &Config,
+) -> Option<Diagnostic> {
+    let msg = e.get("message")?.as_str()?.to_owned();
+    let code = e.get("errorCode").and_then(|v| v.as_str());
+    let listed = |codes: &[String]| code.is_some_and(|c| codes.iter().any(|listed| listed == c));
+
+    let solc_severity = e.get("severity")?.as_str()?;
+    if listed(&config.ignored_diagnostic_codes) {
+        // a broken compile is never hidden unless the user asked for that
+        if solc_severity != "error" || config.allow_ignoring_errors {
+            return None;
+        }
+        log_to_file(&format!(
+            "[diagnostics] Not ignoring error {:?}: set allowIgnoringErrors to hide errors",
+            code
+        ));
+    }
+    // unused variables and the like are dimmed rather than flagged
+    let unnecessary = listed(&config.unnecessary_codes);
+    let (severity, tags) = match solc_severity {
+        "error" => (Some(DiagnosticSeverity::ERROR), None),
+        // the team's lint policy treats these as build failures
+        _ if listed(&config.error_codes) => (Some(DiagnosticSeverity::ERROR), None),
+        "warning" if unnecessary => (
+            Some(DiagnosticSeverity::HINT),
+            Some(vec![DiagnosticTag::UNNECESSARY]),
+        ),
+        "warning" => (Some(DiagnosticSeverity::WARNING), None),
+        "info" => (Some(DiagnosticSeverity::INFORMATION), None),
+        _ => (None, None),
+    };
+
+    let range = e
+        .get("sourceLocation")
+        .and_then(|loc| {
             let start = loc.get("start")?.as_u64()? as usize;
             let end = loc.get("end")?.as_u64()? as usize;
-
-            Some(Diagnostic {
-                range: Range {
-                    start: byte_offset_to_position(source_code, start),
-                    end: byte_offset_to_position(source_code, end),
-                },
-                severity,
-                message: msg,
-                ..Default::default()
+            let source_code = texts.get(loc.get("file")?.as_str()?)?;
+            Some(Range {
+                start: byte_offset_to_position(source_code, start),
+                end: byte_offset_to_position(source_code, end),
             })
         })
-        .collect();
+        .unwrap_or_default();
 
+    Some(Diagnostic {
+        range,
+        severity,
+        code: code.map(|c| NumberOrString::String(c.to_string())),
+        source: Some("solc".into()),
+        message: msg,
+        tags,
+        ..Default::default()
+    })
+}
+
+/// Whether the client declared `textDocument.publishDiagnostics.versionSupport`
+fn client_supports_diagnostic_versions() -> bool {
+    CLIENT_CAPABILITIES
+        .get()
+        .and_then(|c| c.text_document.as_ref())
+        .and_then(|t| t.publish_diagnostics.as_ref())
+        .and_then(|p| p.version_support)
+        .unwrap_or(false)
+}
+
+/// `version` is the document version the diagnostics were computed for
+fn publish_diagnostics(uri: &str, version: Option<i32>, diagnostics: Vec<Diagnostic>) -> Option<String> {
+    published::record(uri, version, &diagnostics);
     let publish = json!({
         "jsonrpc": "2.0",
         "method": "textDocument/publishDiagnostics",
         "params": PublishDiagnosticsParams {
             uri: Url::parse(uri).ok()?,
             diagnostics,
-            version: None,
+            version: version.filter(|_| client_supports_diagnostic_versions()),
         }
     });
 
     Some(publish.to_string())
 }
 
+/// Explain a solc run whose stdout isn't standard-json, preferring what solc
+/// printed on stderr (crashes, bad flags) over the stray stdout text
+fn unparseable_output_message(stdout: &str, stderr: &str) -> String {
+    const MAX_LEN: usize = 2000;
+
+    let said = [stderr.trim(), stdout.trim()]
+        .into_iter()
+        .find(|text| !text.is_empty());
+    let Some(said) = said else {
+        return "solc exited without producing any output".to_string();
+    };
+
+    let mut said = said.to_string();
+    if said.len() > MAX_LEN {
+        let mut cut = MAX_LEN;
+        while !said.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        said.truncate(cut);
+        said.push('…');
+    }
+    format!("solc did not produce standard-json output:\n{}", said)
+}
+
+/// A diagnostic's first line in solc's human-readable output, in either layout
+static STDERR_HEADER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:(.+?):(\d+):(\d+): )?(\w*Error|Warning|Info): (.+)$").unwrap());
+/// The ` --> file:line:col:` line following a header in the newer layout
+static STDERR_LOCATION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*-->\s*(.+?):(\d+):(\d+):?\s*$").unwrap());
+
+/// Diagnostics from solc's human-readable error output, for when it fails
+/// before producing standard-json. Both layouts solc has used are read:
+/// `Error: message` followed by ` --> file:line:col:`, and the older
+/// `file:line:col: Error: message`. Each comes with the unit name of its
+/// file when it has a location.
+fn stderr_diagnostics(stderr: &str) -> Vec<(Option<String>, Diagnostic)> {
+    let position = |line: &str, column: &str| {
+        let line: u32 = line.parse().unwrap_or(1);
+        let column: u32 = column.parse().unwrap_or(1);
+        Position::new(line.saturating_sub(1), column.saturating_sub(1))
+    };
+
+    let mut diagnostics: Vec<(Option<String>, Diagnostic)> = vec![];
+    for line in stderr.lines() {
+        if let Some(cap) = STDERR_LOCATION_RE.captures(line)
+            && let Some((file, diagnostic)) = diagnostics.last_mut()
+            && file.is_none()
+        {
+            let at = position(&cap[2], &cap[3]);
+            *file = Some(cap[1].to_string());
+            diagnostic.range = Range::new(at, at);
+            continue;
+        }
+        let Some(cap) = STDERR_HEADER_RE.captures(line.trim_end()) else {
+            continue;
+        };
+        let severity = match &cap[4] {
+            "Warning" => DiagnosticSeverity::WARNING,
+            "Info" => DiagnosticSeverity::INFORMATION,
+            _ => DiagnosticSeverity::ERROR,
+        };
+        let mut diagnostic = file_diagnostic(severity, &format!("{}: {}", &cap[4], &cap[5]));
+        diagnostic.source = Some("solc".into());
+        let file = cap.get(1).map(|file| {
+            let at = position(&cap[2], &cap[3]);
+            diagnostic.range = Range::new(at, at);
+            file.as_str().to_string()
+        });
+        diagnostics.push((file, diagnostic));
+    }
+    diagnostics
+}
+
+/// A single diagnostic anchored at the top of the file, for problems
+/// that prevent compilation altogether
+fn file_diagnostic(severity: DiagnosticSeverity, message: &str) -> Diagnostic {
+    Diagnostic {
+        range: Range::default(),
+        severity: Some(severity),
+        source: Some("emacs-solidity-server".into()),
+        message: message.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Resolve `base.field` to the field definitions of the struct type of `base`
+fn resolve_struct_member(
+    map: &HashMap<String, DefinitionIndex>,
+    base: &str,
+    field: &str,
+) -> Option<Vec<Definition>> {
+    let struct_names: Vec<&str> = map
+        .values()
+        .filter_map(|index| index.get(base))
+        .flatten()
+        .filter_map(|d| d.type_string.as_deref())
+        .filter_map(struct_name_from_type_string)
+        .collect();
+
+    struct_names.into_iter().find_map(|struct_name| {
+        let key = qualified_member_key(struct_name, field);
+        let defs: Vec<Definition> = map
+            .values()
+            .filter_map(|index| index.get(&key))
+            .flatten()
+            .cloned()
+            .collect();
+        (!defs.is_empty()).then_some(defs)
+    })
+}
+
+/// Whether the client declared `textDocument.definition.linkSupport`
+fn client_supports_definition_links() -> bool {
+    CLIENT_CAPABILITIES
+        .get()
+        .and_then(|c| c.text_document.as_ref())
+        .and_then(|t| t.definition.as_ref())
+        .and_then(|d| d.link_support)
+        .unwrap_or(false)
+}
+
+/// Order candidate definitions for a name at `pos` in `uri`: the declaration
+/// solc resolved the name to, then parameters and locals in scope at `pos`,
+/// then members of the enclosing contract and its bases in linearization
+/// order, then ones in the same file, then ones in files it imports directly;
+/// ties go by path and position so the list is stable. The resolved
+/// declaration, an in-scope local or the most derived override shadows
+/// everything else, and same-named symbols from unrelated files are dropped
+/// when a closer one exists.
+fn rank_definitions(defs: &mut Vec<Definition>, uri: &Url, pos: Position) {
+    // a local is invisible outside its function
+    defs.retain(|d| {
+        d.scope
+            .is_none_or(|scope| d.location.uri == *uri && contains(&scope, pos))
+    });
+
+    let resolved = reference_at(uri.as_str(), pos).map(|r| r.target);
+    let imports = AST_MAP
+        .lock()
+        .map(|units| imported_uris(&units, uri.as_str()))
+        .unwrap_or_default();
+    let locations: Vec<Location> = defs.iter().map(|d| d.location.clone()).collect();
+    let inherited = linearization_ranks(uri.as_str(), pos, &locations);
+    let chain_rank = |d: &Definition| inherited.get(&d.location).copied().unwrap_or(usize::MAX);
+
+    let tier = |d: &Definition| {
+        let name_range = d.name_range.unwrap_or(d.location.range);
+        if resolved
+            .as_ref()
+            .is_some_and(|t| t.uri == d.location.uri && t.range.start == name_range.start)
+        {
+            0
+        } else if d.scope.is_some() {
+            1
+        } else if inherited.contains_key(&d.location) {
+            2
+        } else if d.location.uri == *uri {
+            3
+        } else if imports.contains(d.location.uri.as_str()) {
+            4
+        } else {
+            5
+        }
+    };
+
+    defs.sort_by(|a, b| {
+        tier(a)
+            .cmp(&tier(b))
+            .then_with(|| chain_rank(a).cmp(&chain_rank(b)))
+            .then_with(|| a.location.uri.as_str().cmp(b.location.uri.as_str()))
+            .then_with(|| {
+                let (a, b) = (a.location.range.start, b.location.range.start);
+                (a.line, a.character).cmp(&(b.line, b.character))
+            })
+    });
+    defs.dedup_by(|a, b| a.location == b.location);
+    match defs.first().map(|d| (tier(d), chain_rank(d))) {
+        Some((best @ (0 | 1), _)) => defs.retain(|d| tier(d) == best),
+        // the most derived override, as solc would pick it
+        Some((2, nearest)) => defs.retain(|d| tier(d) == 2 && chain_rank(d) == nearest),
+        Some((best, _)) if best < 5 => defs.retain(|d| tier(d) < 5),
+        _ => {}
+    }
+}
+
+/// Whether `uri` has been indexed, by a compile, artifacts or the saved index
+fn is_indexed(uri: &Url) -> bool {
+    DEFINITION_MAP
+        .lock()
+        .is_ok_and(|map| map.contains_key(uri.as_str()))
+}
+
+/// URI → hash of the text whose compile left it without definitions. A
+/// go-to-definition on that same text neither recompiles nor warns again.
+static NOT_INDEXED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// `textDocument/definition`. When nothing is indexed for the file yet, e.g.
+/// right after opening it, it is compiled first and the response is sent
+/// once that is done; the message loop is not held up meanwhile.
 pub fn handle_definition(req: &Value) -> Option<String> {
     let params: TextDocumentPositionParams =
         serde_json::from_value(req.get("params")?.clone()).ok()?;
-    let uri = params.text_document.uri.clone();
-    let file_path = uri.to_file_path().ok()?;
+    let uri = params.text_document.uri;
+    if is_indexed(&uri) {
+        return definition_response(req);
+    }
+    let Ok(file_path) = uri.to_file_path() else {
+        return definition_response(req);
+    };
+    let text = documents::text(uri.as_str()).or_else(|| fs::read_to_string(&file_path).ok());
+    let Some(text) = text else {
+        return definition_response(req);
+    };
+    let text_hash = sha256_hex(text.as_bytes());
+    if NOT_INDEXED
+        .lock()
+        .is_ok_and(|failed| failed.get(uri.as_str()) == Some(&text_hash))
+    {
+        return definition_response(req);
+    }
+
+    log_to_file(&format!("[definition] {} is not indexed yet, compiling it first", uri));
+    let version = documents::get(uri.as_str()).and_then(|doc| doc.version);
+    let (key, req) = (uri.to_string(), req.clone());
+    worker::submit_then(&key, &text, version, move || {
+        if let Ok(mut failed) = NOT_INDEXED.lock() {
+            if is_indexed(&uri) {
+                failed.remove(uri.as_str());
+            } else if failed.insert(uri.to_string(), text_hash.clone()) != Some(text_hash) {
+                let message = format!(
+                    "No definitions indexed for {}: it did not compile, see its diagnostics",
+                    file_path.display()
+                );
+                log_to_file(&format!("[definition] {}", message));
+                let _ = outgoing::show_message(MessageType::WARNING, &message);
+            }
+        }
+
+        if let Some(response) = definition_response(&req)
+            && let Err(e) = outgoing::send(&response)
+        {
+            log_to_file(&format!("[definition] Failed to send response: {}", e));
+        }
+    });
+    None
+}
+
+/// Answer a go-to-definition from the definitions indexed so far, with
+/// `null` when there is nothing to go to; only a request without an id goes
+/// unanswered
+fn definition_response(req: &Value) -> Option<String> {
+    let id = req.get("id")?;
+    let result = req
+        .get("params")
+        .and_then(|params| serde_json::from_value::<TextDocumentPositionParams>(params.clone()).ok())
+        .and_then(|params| find_definitions(&params));
+
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string())
+}
+
+/// Definitions of the identifier under the cursor, read from the document's
+/// current text (the buffer that was compiled), else the file on disk
+fn find_definitions(params: &TextDocumentPositionParams) -> Option<GotoDefinitionResponse> {
+    let uri = &params.text_document.uri;
     let pos = params.position;
 
-    let content = fs::read_to_string(&file_path).ok()?;
-    let offset = position_to_byte_offset(&content, pos)?;
+    let content = documents::text(uri.as_str())
+        .or_else(|| fs::read_to_string(uri.to_file_path().ok()?).ok())?;
+    let content = strip_bom(&content);
+    let offset = position_to_byte_offset(content, pos)?;
 
-    let ident = extract_identifier_at(&content, offset)?;
+    let (start, end) = identifier_range_at(content, offset)?;
+    let ident = content[start..end].to_string();
     log_to_file(&format!("Looking up definition for '{}'", ident));
 
     let map = DEFINITION_MAP.lock().ok()?;
-    let matches = map
-        .values()
-        .flat_map(|index| index.get(&ident))
-        .next();
-
-    let result = if let Some(defs) = matches {
-        let locations: Vec<Location> = defs.iter().map(|d| {
-            log_to_file(&format!(
-                "- [{}] {} at {:?}",
-                d.kind, d.name, d.location.range
-            ));
-            d.location.clone()
-        }).collect();
-
-        GotoDefinitionResponse::Array(locations)
-    } else {
+
+    // `value.field` where `value` is a struct: prefer that struct's field
+    let member_matches = member_access_base(content, start)
+        .and_then(|base| resolve_struct_member(&map, &base, &ident));
+
+    let matches = member_matches.or_else(|| {
+        let all: Vec<Definition> = map
+            .values()
+            .flat_map(|index| index.get(&ident))
+            .flatten()
+            .cloned()
+            .collect();
+        (!all.is_empty()).then_some(all)
+    });
+
+    let mut defs = matches.unwrap_or_else(|| {
         log_to_file(&format!("No definition found for '{}'", ident));
-        GotoDefinitionResponse::Array(vec![])
+        vec![]
+    });
+    drop(map);
+    rank_definitions(&mut defs, uri, pos);
+    for d in &defs {
+        log_to_file(&format!("- [{}] {} at {:?}", d.kind, d.name, d.location.range));
+    }
+
+    let result = if client_supports_definition_links() {
+        let origin = Range {
+            start: byte_offset_to_position(content, start),
+            end: byte_offset_to_position(content, end),
+        };
+        GotoDefinitionResponse::Link(
+            defs.into_iter()
+                .map(|d| LocationLink {
+                    origin_selection_range: Some(origin),
+                    target_uri: d.location.uri,
+                    target_range: d.location.range,
+                    target_selection_range: d.name_range.unwrap_or(d.location.range),
+                })
+                .collect(),
+        )
+    } else {
+        GotoDefinitionResponse::Array(defs.into_iter().map(|d| d.location).collect())
     };
 
-    Some(json!({
-        "jsonrpc": "2.0",
-        "id": req.get("id")?,
-        "result": result,
-    }).to_string())
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::ast::SourceUnit;
+    use crate::analysis::inheritance::{Contract, CONTRACT_MAP};
+
+    fn located_error(file: &str, start: u64, end: u64) -> Value {
+        json!({
+            "message": "boom",
+            "severity": "error",
+            "sourceLocation": { "file": file, "start": start, "end": end },
+        })
+    }
+
+    #[test]
+    fn first_line_error_in_a_bom_prefixed_import_keeps_its_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = dir.path().join("Main.sol");
+        let lib = dir.path().join("Lib.sol");
+        fs::write(&lib, "\u{feff}library Lib {}\n").unwrap();
+        let paths = SourcePaths::from([
+            ("Main.sol".to_string(), entry.clone()),
+            ("Lib.sol".to_string(), lib),
+        ]);
+        let mut texts = SourceTexts::new(&paths, &entry, "import \"./Lib.sol\";\n");
+
+        // solc is given, and counts offsets in, the text without its BOM
+        let diagnostic =
+            solc_error_to_diagnostic(&located_error("Lib.sol", 8, 11), &mut texts, &Config::default()).unwrap();
+        assert_eq!(diagnostic.range, Range::new(Position::new(0, 8), Position::new(0, 11)));
+    }
+
+    #[test]
+    fn error_in_an_unknown_or_unreadable_file_is_placed_at_the_top() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = dir.path().join("Main.sol");
+        let entry_text = "\n\n\ncontract Main {}\n";
+        let paths = SourcePaths::from([
+            ("Main.sol".to_string(), entry.clone()),
+            ("Gone.sol".to_string(), dir.path().join("Gone.sol")),
+        ]);
+        let mut texts = SourceTexts::new(&paths, &entry, entry_text);
+        let config = Config::default();
+
+        let in_entry = solc_error_to_diagnostic(&located_error("Main.sol", 3, 11), &mut texts, &config);
+        assert_eq!(in_entry.unwrap().range.start, Position::new(3, 0));
+
+        for file in ["Other.sol", "Gone.sol"] {
+            let diagnostic = solc_error_to_diagnostic(&located_error(file, 3, 11), &mut texts, &config);
+            assert_eq!(diagnostic.unwrap().range, Range::default(), "{}", file);
+        }
+    }
+
+    #[test]
+    fn missing_compiler_is_only_informational_while_a_download_can_bring_one() {
+        let not_found = |download_pending| SolcError::NotFound {
+            requirement: "any version (no pragma)".into(),
+            download_pending,
+        };
+        let severity = |e| compile_error_diagnostic(&e, "contract A {}").unwrap().severity;
+
+        assert_eq!(severity(not_found(true)), Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(severity(not_found(false)), Some(DiagnosticSeverity::WARNING));
+        assert!(compile_error_diagnostic(&SolcError::ShuttingDown, "contract A {}").is_none());
+    }
+
+    fn location(uri: &str, start: u32, end: u32) -> Location {
+        Location::new(uri.parse().unwrap(), Range::new(Position::new(start, 0), Position::new(end, 0)))
+    }
+
+    fn definition(name: &str, uri: &str, line: u32) -> Definition {
+        Definition {
+            name: name.into(),
+            location: location(uri, line, line),
+            name_range: None,
+            kind: "FunctionDefinition".into(),
+            container: None,
+            type_string: None,
+            scope: None,
+        }
+    }
+
+    fn unit(ast: Value) -> SourceUnit {
+        SourceUnit { ast, content: String::new(), file_index: 0 }
+    }
+
+    #[test]
+    fn current_file_then_imports_and_unrelated_files_dropped() {
+        let (main, lib, other) = ("file:///rank/Main.sol", "file:///rank/Lib.sol", "file:///rank/Other.sol");
+        if let Ok(mut units) = AST_MAP.lock() {
+            let import = json!({ "nodeType": "ImportDirective", "absolutePath": "rank/Lib.sol" });
+            units.insert(main.into(), unit(json!({ "absolutePath": "rank/Main.sol", "nodes": [import] })));
+            units.insert(lib.into(), unit(json!({ "absolutePath": "rank/Lib.sol", "nodes": [] })));
+        }
+
+        let mut defs = vec![
+            definition("Token", other, 1),
+            definition("Token", lib, 2),
+            definition("Token", main, 5),
+        ];
+        rank_definitions(&mut defs, &main.parse().unwrap(), Position::new(20, 0));
+
+        let uris: Vec<&str> = defs.iter().map(|d| d.location.uri.as_str()).collect();
+        assert_eq!(uris, [main, lib]);
+    }
+
+    #[test]
+    fn most_derived_override_wins() {
+        let (child, base, grand) = ("file:///rank/Child.sol", "file:///rank/Base.sol", "file:///rank/Grand.sol");
+        let contract = |name: &str, uri: &str, linearized: Vec<Location>| Contract {
+            name: name.into(),
+            kind: "contract".into(),
+            location: location(uri, 0, 10),
+            name_location: location(uri, 0, 0),
+            bases: linearized.iter().skip(1).take(1).cloned().collect(),
+            linearized,
+        };
+        let chain = vec![location(child, 0, 0), location(base, 0, 0), location(grand, 0, 0)];
+        if let Ok(mut map) = CONTRACT_MAP.lock() {
+            map.insert(child.into(), vec![contract("Child", child, chain.clone())]);
+            map.insert(base.into(), vec![contract("Base", base, chain[1..].to_vec())]);
+            map.insert(grand.into(), vec![contract("Grand", grand, chain[2..].to_vec())]);
+        }
+        let uri: Url = child.parse().unwrap();
+        let inside_child = Position::new(5, 0);
+
+        let mut defs = vec![definition("foo", grand, 3), definition("foo", base, 4)];
+        rank_definitions(&mut defs, &uri, inside_child);
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].location.uri.as_str(), base);
+
+        let mut defs = vec![definition("foo", grand, 3), definition("foo", base, 4), definition("foo", child, 2)];
+        rank_definitions(&mut defs, &uri, inside_child);
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].location.uri.as_str(), child);
+    }
+
+    #[test]
+    fn inherited_member_beats_a_same_named_one_in_the_current_file() {
+        let (child, base) = ("file:///derived/Child.sol", "file:///derived/Base.sol");
+        let contract = |name: &str, uri: &str, start: u32, linearized: Vec<Location>| Contract {
+            name: name.into(),
+            kind: "contract".into(),
+            location: location(uri, start, start + 10),
+            name_location: location(uri, start, start),
+            bases: linearized.iter().skip(1).cloned().collect(),
+            linearized,
+        };
+        let (child_name, base_name) = (location(child, 0, 0), location(base, 0, 0));
+        if let Ok(mut map) = CONTRACT_MAP.lock() {
+            map.insert(
+                child.into(),
+                vec![
+                    contract("Child", child, 0, vec![child_name.clone(), base_name.clone()]),
+                    contract("Unrelated", child, 20, vec![location(child, 20, 20)]),
+                ],
+            );
+            map.insert(base.into(), vec![contract("Base", base, 0, vec![base_name])]);
+        }
+
+        let mut defs = vec![definition("foo", child, 22), definition("foo", base, 4)];
+        rank_definitions(&mut defs, &child.parse().unwrap(), Position::new(5, 0));
+
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].location, location(base, 4, 4));
+    }
+
+    fn definition_request(uri: &str, line: u32, character: u32) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "textDocument/definition",
+            "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            },
+        })
+    }
+
+    #[test]
+    fn definition_is_looked_up_in_the_unsaved_buffer() {
+        let uri = "file:///unsaved/Buffer.sol";
+        let target = "file:///unsaved/Target.sol";
+        documents::open_or_update(uri, "contract C {\n    UnsavedTarget t;\n}\n", Some(1));
+        if let Ok(mut map) = DEFINITION_MAP.lock() {
+            let defs = vec![definition("UnsavedTarget", target, 3)];
+            map.insert(target.into(), DefinitionIndex::from([("UnsavedTarget".to_string(), defs)]));
+        }
+
+        let response = definition_response(&definition_request(uri, 1, 6)).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"][0]["uri"], target);
+        documents::close(uri);
+    }
+
+    #[test]
+    fn definition_outside_any_readable_text_is_answered_with_null() {
+        let missing = definition_request("file:///nowhere/Missing.sol", 0, 0);
+        let response: Value = serde_json::from_str(&definition_response(&missing).unwrap()).unwrap();
+        assert_eq!(response["id"], 7);
+        assert!(response["result"].is_null());
+
+        let response = handle_definition(&definition_request("untitled:Scratch.sol", 0, 0)).unwrap();
+        assert!(serde_json::from_str::<Value>(&response).unwrap()["result"].is_null());
+    }
 }
@@ -0,0 +1,187 @@
+use std::fs;
+
+use lsp_types::{Hover, HoverContents, HoverParams, MarkupContent, MarkupKind, Position, Range, Url};
+use serde_json::{json, Value};
+
+use crate::analysis::ast::{declaration_at, AST_MAP};
+use crate::analysis::builtins;
+use crate::analysis::natspec::{self, NatSpec};
+use crate::analysis::references::reference_at;
+use crate::lsp::documents;
+use crate::util::position::{byte_offset_to_position, position_to_byte_offset};
+use crate::util::text::{identifier_range_at, member_access_base, strip_bom};
+
+fn str_field<'a>(node: &'a Value, key: &str) -> Option<&'a str> {
+    node.get(key)?.as_str().filter(|s| !s.is_empty())
+}
+
+/// `uint256 amount, address to` from a `ParameterList` node
+fn parameter_list(list: Option<&Value>) -> String {
+    list.and_then(|l| l.get("parameters"))
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten()
+        .map(|p| {
+            let ty = p
+                .get("typeDescriptions")
+                .and_then(|t| str_field(t, "typeString"))
+                .unwrap_or("?");
+            match str_field(p, "name") {
+                Some(name) => format!("{} {}", ty, name),
+                None => ty.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// One-line Solidity-like signature of a declaration node
+fn signature(node: &Value) -> String {
+    let name = str_field(node, "name").unwrap_or("");
+    match str_field(node, "nodeType").unwrap_or("") {
+        "FunctionDefinition" => {
+            let kind = str_field(node, "kind").unwrap_or("function");
+            let mut sig = if kind == "function" {
+                format!("function {}({})", name, parameter_list(node.get("parameters")))
+            } else {
+                format!("{}({})", kind, parameter_list(node.get("parameters")))
+            };
+            if let Some(visibility) = str_field(node, "visibility") {
+                sig.push(' ');
+                sig.push_str(visibility);
+            }
+            if let Some(mutability) = str_field(node, "stateMutability").filter(|m| *m != "nonpayable") {
+                sig.push(' ');
+                sig.push_str(mutability);
+            }
+            let returns = parameter_list(node.get("returnParameters"));
+            if !returns.is_empty() {
+                sig.push_str(&format!(" returns ({})", returns));
+            }
+            sig
+        }
+        "ModifierDefinition" => format!("modifier {}({})", name, parameter_list(node.get("parameters"))),
+        "EventDefinition" => format!("event {}({})", name, parameter_list(node.get("parameters"))),
+        "ErrorDefinition" => format!("error {}({})", name, parameter_list(node.get("parameters"))),
+        "ContractDefinition" => {
+            let kind = str_field(node, "contractKind").unwrap_or("contract");
+            let is_abstract = node.get("abstract").and_then(|v| v.as_bool()).unwrap_or(false);
+            format!("{}{} {}", if is_abstract { "abstract " } else { "" }, kind, name)
+        }
+        "StructDefinition" => format!("struct {}", name),
+        "EnumDefinition" => format!("enum {}", name),
+        "UserDefinedValueTypeDefinition" => {
+            let underlying = node
+                .get("underlyingType")
+                .and_then(|t| t.get("typeDescriptions"))
+                .and_then(|t| str_field(t, "typeString"))
+                .unwrap_or("?");
+            format!("type {} is {}", name, underlying)
+        }
+        "VariableDeclaration" => {
+            let ty = node
+                .get("typeDescriptions")
+                .and_then(|t| str_field(t, "typeString"))
+                .unwrap_or("?");
+            let is_state = node.get("stateVariable").and_then(|v| v.as_bool()).unwrap_or(false);
+            match str_field(node, "visibility").filter(|_| is_state) {
+                Some(visibility) => format!("{} {} {}", ty, visibility, name),
+                None => format!("{} {}", ty, name),
+            }
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Markdown for the NatSpec tags, empty when there are none
+fn render_natspec(spec: &NatSpec) -> String {
+    let mut sections = Vec::new();
+
+    if let Some(title) = &spec.title {
+        sections.push(format!("**{}**", title));
+    }
+    if let Some(notice) = &spec.notice {
+        sections.push(notice.clone());
+    }
+    if let Some(dev) = &spec.dev {
+        sections.push(format!("*{}*", dev));
+    }
+    if !spec.params.is_empty() {
+        let params: Vec<String> = spec
+            .params
+            .iter()
+            .map(|(name, description)| format!("- `{}` — {}", name, description))
+            .collect();
+        sections.push(format!("**Parameters**\n{}", params.join("\n")));
+    }
+    if !spec.returns.is_empty() {
+        let returns: Vec<String> = spec.returns.iter().map(|r| format!("- {}", r)).collect();
+        sections.push(format!("**Returns**\n{}", returns.join("\n")));
+    }
+
+    sections.join("\n\n")
+}
+
+/// Hover for a global, member of a global or unit under the cursor, e.g.
+/// `msg.sender` or `ether`, which have no declaration in the AST
+fn builtin_hover(uri: &str, pos: Position) -> Option<Hover> {
+    let text = documents::text(uri).or_else(|| {
+        let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+        fs::read_to_string(path).ok()
+    })?;
+    let text = strip_bom(&text);
+    let offset = position_to_byte_offset(text, pos)?;
+    let (start, end) = identifier_range_at(text, offset)?;
+    let base = member_access_base(text, start);
+    let builtin = builtins::lookup(base.as_deref(), &text[start..end])?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```solidity\n{}\n```\n\n{}", builtin.signature, builtin.description),
+        }),
+        range: Some(Range {
+            start: byte_offset_to_position(text, start),
+            end: byte_offset_to_position(text, end),
+        }),
+    })
+}
+
+/// `textDocument/hover`: signature and NatSpec of the declaration under the
+/// cursor, or the description of a builtin such as `msg.sender`
+pub fn handle_hover(req: &Value) -> Option<String> {
+    let params: HoverParams = serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document_position_params.text_document.uri.to_string();
+    let pos = params.text_document_position_params.position;
+
+    let hover = reference_at(&uri, pos).and_then(|r| {
+        let units = AST_MAP.lock().ok()?;
+        let unit = units.get(r.target.uri.as_str())?;
+        let name_start = position_to_byte_offset(&unit.content, r.target.range.start)?;
+        let node = declaration_at(unit, name_start)?;
+
+        let mut value = format!("```solidity\n{}\n```", signature(node));
+        let docs = natspec::documentation_text(node)
+            .map(|text| render_natspec(&natspec::parse(text)))
+            .unwrap_or_default();
+        if !docs.is_empty() {
+            value.push_str("\n\n");
+            value.push_str(&docs);
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(r.range),
+        })
+    })
+    .or_else(|| builtin_hover(&uri, pos));
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": hover,
+    }).to_string())
+}
@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::config;
+use crate::lsp::progress::Progress;
+use crate::project::remappings::parse_remappings;
+use crate::project::workspace::{collect_solidity_files, find_sources_dir};
+use crate::util::fs::run_solc;
+use crate::util::log::log_to_file;
+
+/// Compile every source file of the project in the background so
+/// goto-definition works for files that haven't been opened yet
+pub fn spawn_workspace_index(project_root: PathBuf, workspace_root: PathBuf) {
+    thread::spawn(move || index_workspace(&project_root, &workspace_root));
+}
+
+fn index_workspace(project_root: &Path, workspace_root: &Path) {
+    let sources_dir = find_sources_dir(project_root);
+    let files = collect_solidity_files(&sources_dir, &config::get().index_ignore);
+    let remappings = parse_remappings(project_root, Some(workspace_root));
+
+    log_to_file(&format!(
+        "[index] Indexing {} files under {}",
+        files.len(),
+        sources_dir.display()
+    ));
+
+    let progress = Progress::begin("Indexing workspace", files.len());
+
+    for (i, file) in files.iter().enumerate() {
+        if progress.as_ref().is_some_and(|p| p.is_cancelled()) {
+            log_to_file(&format!("[index] Cancelled after {} of {} files", i, files.len()));
+            if let Some(p) = progress {
+                p.end(&format!("Indexing cancelled after {} contracts", i));
+            }
+            return;
+        }
+
+        match fs::read_to_string(file) {
+            Ok(content) => {
+                if let Err(e) = run_solc(file, &content, &remappings, project_root) {
+                    log_to_file(&format!("[index] Failed to compile {}: {}", file.display(), e));
+                }
+            }
+            Err(e) => log_to_file(&format!("[index] Failed to read {}: {}", file.display(), e)),
+        }
+
+        if let Some(p) = &progress {
+            p.report(i + 1, &format!("Indexing {}/{} contracts", i + 1, files.len()));
+        }
+    }
+
+    if let Some(p) = progress {
+        p.end(&format!("Indexed {} contracts", files.len()));
+    }
+    log_to_file("[index] Workspace indexing finished");
+}
@@ -0,0 +1,36 @@
+use lsp_types::{LinkedEditingRangeParams, LinkedEditingRanges};
+use serde_json::{json, Value};
+
+use crate::analysis::references::{reference_at, REFERENCE_MAP};
+
+/// `textDocument/linkedEditingRange`: every occurrence in the same file of the
+/// declaration under the cursor, so the client can edit them together
+pub fn handle_linked_editing_range(req: &Value) -> Option<String> {
+    let params: LinkedEditingRangeParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document_position_params.text_document.uri.to_string();
+    let pos = params.text_document_position_params.position;
+
+    let result = reference_at(&uri, pos).and_then(|current| {
+        let map = REFERENCE_MAP.lock().ok()?;
+        let mut ranges: Vec<_> = map
+            .get(&uri)?
+            .iter()
+            .filter(|r| r.target == current.target && r.name == current.name)
+            .map(|r| r.range)
+            .collect();
+        ranges.sort_by_key(|r| (r.start.line, r.start.character));
+        ranges.dedup();
+
+        Some(LinkedEditingRanges {
+            ranges,
+            word_pattern: None,
+        })
+    });
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": result,
+    }).to_string())
+}
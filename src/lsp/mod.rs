@@ -1,2 +1,20 @@
 pub mod handler;
 pub mod types;
+pub mod outgoing;
+pub mod documents;
+pub mod progress;
+pub mod indexer;
+pub mod worker;
+pub mod selection_range;
+pub mod linked_editing;
+pub mod call_hierarchy;
+pub mod code_action;
+pub mod transport;
+pub mod hover;
+pub mod document_diagnostic;
+pub mod revalidate;
+pub mod type_hierarchy;
+pub mod published;
+pub mod document_symbol;
+pub mod document_link;
+pub mod dependencies;
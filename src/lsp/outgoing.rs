@@ -0,0 +1,58 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use lsp_types::{LogMessageParams, MessageType, ShowMessageParams};
+use once_cell::sync::Lazy;
+use serde_json::json;
+
+/// Shared write half of the transport, so background work can emit messages too
+static WRITER: Lazy<Mutex<Option<Box<dyn Write + Send>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Install the writer every framed message is sent to
+pub fn set_writer(writer: Box<dyn Write + Send>) {
+    if let Ok(mut slot) = WRITER.lock() {
+        *slot = Some(writer);
+    }
+}
+
+/// Frame a JSON-RPC message with its `Content-Length` header and write it out
+pub fn send(message: &str) -> io::Result<()> {
+    let mut slot = WRITER
+        .lock()
+        .map_err(|_| io::Error::other("outgoing writer poisoned"))?;
+    let Some(writer) = slot.as_mut() else {
+        return Ok(()); // no transport yet
+    };
+
+    let bytes = message.as_bytes();
+    let header = format!("Content-Length: {}\r\n\r\n", bytes.len());
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(bytes)?;
+    writer.flush()
+}
+
+/// Send a `window/logMessage` notification
+pub fn log_message(typ: MessageType, message: &str) -> io::Result<()> {
+    let params = LogMessageParams {
+        typ,
+        message: message.to_string(),
+    };
+    send(&json!({
+        "jsonrpc": "2.0",
+        "method": "window/logMessage",
+        "params": params,
+    }).to_string())
+}
+
+/// Send a `window/showMessage` notification, which editors pop up
+pub fn show_message(typ: MessageType, message: &str) -> io::Result<()> {
+    let params = ShowMessageParams {
+        typ,
+        message: message.to_string(),
+    };
+    send(&json!({
+        "jsonrpc": "2.0",
+        "method": "window/showMessage",
+        "params": params,
+    }).to_string())
+}
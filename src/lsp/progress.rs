@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+    WorkDoneProgressReport,
+};
+use once_cell::sync::Lazy;
+use serde_json::json;
+
+use crate::lsp::handler::CLIENT_CAPABILITIES;
+use crate::lsp::outgoing;
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// Cancellation flags of the sessions still running, by token
+static CANCEL_FLAGS: Lazy<Mutex<HashMap<NumberOrString, Arc<AtomicBool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Handle `window/workDoneProgress/cancel` by flagging the session;
+/// the task running it stops at its next `is_cancelled` check
+pub fn cancel(token: &NumberOrString) {
+    if let Ok(flags) = CANCEL_FLAGS.lock()
+        && let Some(flag) = flags.get(token)
+    {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A `$/progress` work-done session reporting `done/total` items
+pub struct Progress {
+    token: NumberOrString,
+    total: usize,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    /// Create a progress token and send the `begin` message.
+    /// Returns `None` when the client doesn't support work-done progress.
+    pub fn begin(title: &str, total: usize) -> Option<Self> {
+        let supported = CLIENT_CAPABILITIES
+            .get()
+            .and_then(|c| c.window.as_ref())
+            .and_then(|w| w.work_done_progress)
+            .unwrap_or(false);
+        if !supported {
+            return None;
+        }
+
+        let n = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        let token = NumberOrString::String(format!("emacs-solidity-server/progress/{}", n));
+
+        let create = json!({
+            "jsonrpc": "2.0",
+            "id": format!("progress-create-{}", n),
+            "method": "window/workDoneProgress/create",
+            "params": WorkDoneProgressCreateParams { token: token.clone() },
+        });
+        let _ = outgoing::send(&create.to_string());
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Ok(mut flags) = CANCEL_FLAGS.lock() {
+            flags.insert(token.clone(), cancelled.clone());
+        }
+
+        let progress = Progress { token, total, cancelled };
+        progress.send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(true),
+            message: Some(format!("0/{}", total)),
+            percentage: Some(0),
+        }));
+        Some(progress)
+    }
+
+    /// Report that `done` of `total` items are finished, e.g. with
+    /// `Indexing 42/350 contracts` as the message
+    pub fn report(&self, done: usize, message: &str) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(true),
+            message: Some(message.to_string()),
+            percentage: Some(self.percentage(done)),
+        }));
+    }
+
+    /// Whether the user asked the client to cancel this session
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn end(self, message: &str) {
+        if let Ok(mut flags) = CANCEL_FLAGS.lock() {
+            flags.remove(&self.token);
+        }
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message.to_string()),
+        }));
+    }
+
+    fn percentage(&self, done: usize) -> u32 {
+        if self.total == 0 {
+            return 100;
+        }
+        (done.min(self.total) * 100 / self.total) as u32
+    }
+
+    fn send(&self, value: WorkDoneProgress) {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "$/progress",
+            "params": ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            },
+        });
+        let _ = outgoing::send(&notification.to_string());
+    }
+}
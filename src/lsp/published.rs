@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use lsp_types::Diagnostic;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+/// Files whose last diagnostics are kept; the least recently published go first
+const MAX_FILES: usize = 256;
+
+/// The diagnostics last sent to the client for a file
+#[derive(Debug, Clone)]
+struct Published {
+    version: Option<i32>,
+    diagnostics: Vec<Diagnostic>,
+    at: SystemTime,
+}
+
+/// Global map: file URI → diagnostics last published for it
+static PUBLISHED: Lazy<Mutex<HashMap<String, Published>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Remember what was just published for `uri`
+pub fn record(uri: &str, version: Option<i32>, diagnostics: &[Diagnostic]) {
+    let Ok(mut published) = PUBLISHED.lock() else {
+        return;
+    };
+    published.insert(
+        uri.to_string(),
+        Published {
+            version,
+            diagnostics: diagnostics.to_vec(),
+            at: SystemTime::now(),
+        },
+    );
+
+    while published.len() > MAX_FILES {
+        let Some(oldest) = published.iter().min_by_key(|(_, p)| p.at).map(|(uri, _)| uri.clone()) else {
+            break;
+        };
+        published.remove(&oldest);
+    }
+}
+
+/// The diagnostics last published for `uri`
+pub fn diagnostics(uri: &str) -> Option<Vec<Diagnostic>> {
+    PUBLISHED.lock().ok()?.get(uri).map(|p| p.diagnostics.clone())
+}
+
+pub fn forget(uri: &str) {
+    if let Ok(mut published) = PUBLISHED.lock() {
+        published.remove(uri);
+    }
+}
+
+/// `emacs-solidity-server/diagnostics`: the diagnostics last published for
+/// `{ uri }`, without compiling. `null` when nothing was published for it.
+pub fn handle_dump_diagnostics(req: &Value) -> Option<String> {
+    let id = req.get("id")?.clone();
+    let uri = req.get("params")?.get("uri")?.as_str()?;
+
+    let result = PUBLISHED.lock().ok()?.get(uri).map(|p| {
+        json!({
+            "uri": uri,
+            "version": p.version,
+            "diagnostics": p.diagnostics,
+        })
+    });
+
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string())
+}
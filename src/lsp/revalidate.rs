@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use crate::lsp::{documents, worker};
+use crate::util::log::log_to_file;
+
+/// Open documents edited since the last tick
+static DIRTY: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Queue `uri` for the next tick instead of compiling it now
+pub fn mark_dirty(uri: &str) {
+    if let Ok(mut dirty) = DIRTY.lock() {
+        dirty.insert(uri.to_string());
+    }
+}
+
+/// Every `interval`, hand the documents edited since the previous tick to
+/// the compile worker. Documents closed in the meantime are dropped.
+pub fn spawn_timer(interval: Duration) {
+    log_to_file(&format!("[revalidate] Re-validating edited files every {:?}", interval));
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        let uris: Vec<String> = match DIRTY.lock() {
+            Ok(mut dirty) => dirty.drain().collect(),
+            Err(_) => continue,
+        };
+        if uris.is_empty() {
+            continue;
+        }
+
+        for (uri, doc) in documents::snapshot() {
+            if uris.contains(&uri) {
+                worker::submit(&uri, &doc.text, doc.version);
+            }
+        }
+    });
+}
@@ -0,0 +1,55 @@
+use lsp_types::{Position, Range, SelectionRange, SelectionRangeParams};
+use serde_json::{json, Value};
+
+use crate::analysis::ast::{enclosing_spans, AST_MAP};
+use crate::util::log::log_to_file;
+use crate::util::position::{byte_offset_to_position, position_to_byte_offset};
+
+/// `textDocument/selectionRange`: for each position, the chain of enclosing
+/// AST node ranges (identifier → expression → statement → ... → contract)
+pub fn handle_selection_range(req: &Value) -> Option<String> {
+    let params: SelectionRangeParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document.uri.to_string();
+
+    let units = AST_MAP.lock().ok()?;
+    let unit = units.get(&uri);
+    if unit.is_none() {
+        log_to_file(&format!("[selectionRange] No AST for {}", uri));
+    }
+
+    let result: Vec<SelectionRange> = params
+        .positions
+        .iter()
+        .map(|&pos| {
+            unit.and_then(|unit| {
+                let offset = position_to_byte_offset(&unit.content, pos)?;
+                let spans = enclosing_spans(&unit.ast, unit.file_index, offset);
+                spans.into_iter().fold(None, |parent, (start, end)| {
+                    Some(SelectionRange {
+                        range: Range {
+                            start: byte_offset_to_position(&unit.content, start),
+                            end: byte_offset_to_position(&unit.content, end),
+                        },
+                        parent: parent.map(Box::new),
+                    })
+                })
+            })
+            .unwrap_or_else(|| empty_selection(pos))
+        })
+        .collect();
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": result,
+    }).to_string())
+}
+
+/// Fallback when nothing is known about the position: the empty range at it
+fn empty_selection(pos: Position) -> SelectionRange {
+    SelectionRange {
+        range: Range { start: pos, end: pos },
+        parent: None,
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::{self, BufRead, Error, ErrorKind, Read, Write};
+
+use crate::config;
+use crate::lsp::handler::handle_request;
+use crate::lsp::outgoing;
+use crate::util::log::log_to_file;
+
+/// Bodies are read in pieces of at most this size, so a large
+/// `Content-Length` only costs memory as the bytes actually arrive
+const READ_CHUNK: usize = 1024 * 1024;
+
+/// Read one LSP header block and return its `Content-Length`.
+/// Returns `Ok(None)` at end of stream, including a stream that ends
+/// mid-header. An `InvalidData` error means the header block was malformed
+/// (missing, unparsable or conflicting lengths) and the message should be
+/// skipped.
+/// Whether the length is acceptable is up to the caller.
+pub fn read_headers(reader: &mut impl BufRead) -> io::Result<Option<usize>> {
+    let mut content_length: Option<usize> = None;
+    let mut conflict = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None); // EOF
+        }
+
+        let header = line.trim_end_matches(['\r', '\n']);
+        if header.is_empty() {
+            break; // end of headers
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            continue; // not a header; tolerated like unknown headers
+        };
+        if !name.trim().eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+
+        let length: usize = value.trim().parse().map_err(|_| {
+            Error::new(ErrorKind::InvalidData, format!("Invalid Content-Length: {:?}", value.trim()))
+        })?;
+        match content_length {
+            Some(previous) if previous != length => conflict = Some((previous, length)),
+            _ => content_length = Some(length),
+        }
+    }
+
+    if let Some((first, second)) = conflict {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Conflicting Content-Length headers: {} and {}", first, second),
+        ));
+    }
+
+    match content_length {
+        None | Some(0) => Err(Error::new(ErrorKind::InvalidData, "Missing Content-Length")),
+        Some(length) => Ok(Some(length)),
+    }
+}
+
+/// Read a `length` byte body, growing the buffer chunk by chunk rather than
+/// allocating it all upfront. `Ok(None)` if the stream ends first.
+fn read_body(reader: &mut impl BufRead, length: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut content = Vec::with_capacity(length.min(READ_CHUNK));
+    while content.len() < length {
+        let chunk = (length - content.len()).min(READ_CHUNK) as u64;
+        if reader.by_ref().take(chunk).read_to_end(&mut content)? == 0 {
+            return Ok(None);
+        }
+    }
+    Ok(Some(content))
+}
+
+/// Run the read/dispatch/write loop over any transport until the stream ends.
+/// Responses and server-initiated messages all go to `writer`.
+pub fn serve(mut reader: impl BufRead, writer: impl Write + Send + 'static) {
+    outgoing::set_writer(Box::new(writer));
+
+    loop {
+        // --- Parse LSP headers ---
+        let content_length = match read_headers(&mut reader) {
+            Ok(Some(length)) => length,
+            Ok(None) => return, // EOF
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                log_to_file(&format!("[transport] Skipping message: {}", e));
+                continue;
+            }
+            Err(e) => {
+                log_to_file(&format!("[transport] Failed to read headers: {}", e));
+                return;
+            }
+        };
+
+        let max_length = config::get().max_message_mb.saturating_mul(1024 * 1024);
+        if content_length as u64 > max_length {
+            log_to_file(&format!(
+                "[transport] Skipping message: Content-Length {} exceeds the {} byte limit",
+                content_length, max_length
+            ));
+            // drop the body unread so the next header block is found
+            let mut body = (&mut reader).take(content_length as u64);
+            if let Err(e) = io::copy(&mut body, &mut io::sink()) {
+                log_to_file(&format!("[transport] Failed to skip oversized message: {}", e));
+                return;
+            }
+            continue;
+        }
+
+        // --- Read the actual JSON payload ---
+        let content = match read_body(&mut reader, content_length) {
+            Ok(Some(content)) => content,
+            Ok(None) => {
+                log_to_file("[transport] Stream ended mid-message");
+                return;
+            }
+            Err(e) => {
+                log_to_file(&format!("[transport] Stream ended mid-message: {}", e));
+                return;
+            }
+        };
+
+        let request_str = String::from_utf8_lossy(&content);
+
+        // --- Handle request ---
+        if let Some(response) = handle_request(&request_str)
+            && let Err(e) = outgoing::send(&response)
+        {
+            // the client is gone (e.g. the editor quit); nothing left to serve
+            if matches!(e.kind(), ErrorKind::BrokenPipe | ErrorKind::ConnectionReset) {
+                log_to_file("[transport] Client closed the connection");
+            } else {
+                log_to_file(&format!("[transport] Failed to write response: {}", e));
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn headers(raw: &str) -> io::Result<Option<usize>> {
+        read_headers(&mut Cursor::new(raw.as_bytes()))
+    }
+
+    #[test]
+    fn content_length_is_case_insensitive_and_trimmed() {
+        assert_eq!(headers("Content-Length: 42\r\n\r\n").unwrap(), Some(42));
+        assert_eq!(headers("content-length:  7 \r\nContent-Type: x\r\n\r\n").unwrap(), Some(7));
+        // bare `\n` line endings are tolerated
+        assert_eq!(headers("CONTENT-LENGTH: 3\n\n").unwrap(), Some(3));
+    }
+
+    #[test]
+    fn repeated_equal_content_length_is_accepted() {
+        assert_eq!(headers("Content-Length: 5\r\ncontent-length: 5\r\n\r\n").unwrap(), Some(5));
+    }
+
+    #[test]
+    fn conflicting_content_length_skips_only_that_message() {
+        let raw = "Content-Length: 5\r\nContent-Length: 6\r\n\r\nContent-Length: 2\r\n\r\n{}";
+        let mut reader = Cursor::new(raw.as_bytes());
+
+        let err = read_headers(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("Conflicting"), "{}", err);
+        // the next header block is read normally
+        assert_eq!(read_headers(&mut reader).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn missing_or_invalid_content_length_is_invalid_data() {
+        for raw in ["Content-Type: x\r\n\r\n", "Content-Length: 0\r\n\r\n", "Content-Length: lots\r\n\r\n"] {
+            let err = headers(raw).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn missing_final_crlf_is_end_of_stream() {
+        assert_eq!(headers("Content-Length: 5\r\n").unwrap(), None);
+        assert_eq!(headers("Content-Length: 5").unwrap(), None);
+    }
+
+    #[test]
+    fn eof_mid_header_is_end_of_stream() {
+        assert_eq!(headers("").unwrap(), None);
+        assert_eq!(headers("Content-Le").unwrap(), None);
+        assert_eq!(headers("Content-Type: x\r\nContent-Length: 1").unwrap(), None);
+    }
+
+    #[test]
+    fn body_larger_than_a_chunk_is_read_whole() {
+        let body: Vec<u8> = (0..READ_CHUNK * 2 + 17).map(|i| (i % 251) as u8).collect();
+        let mut stream = body.clone();
+        stream.extend_from_slice(b"Content-Length: 2\r\n\r\n");
+        let mut reader = Cursor::new(stream);
+
+        assert_eq!(read_body(&mut reader, body.len()).unwrap(), Some(body));
+        // nothing past the body was consumed
+        assert_eq!(read_headers(&mut reader).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn body_cut_short_is_end_of_stream() {
+        let mut reader = Cursor::new(vec![b'x'; READ_CHUNK + 10]);
+
+        assert_eq!(read_body(&mut reader, READ_CHUNK * 2).unwrap(), None);
+        assert_eq!(read_body(&mut Cursor::new(b"{}"), 2).unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(read_body(&mut Cursor::new(b""), 0).unwrap(), Some(vec![]));
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use lsp_types::{
+    Location, SymbolKind, TypeHierarchyItem, TypeHierarchyPrepareParams,
+    TypeHierarchySubtypesParams, TypeHierarchySupertypesParams,
+};
+use serde_json::{json, Value};
+
+use crate::analysis::inheritance::{contract_named_at, direct_subtypes, Contract, CONTRACT_MAP};
+use crate::analysis::references::reference_at;
+
+/// Detail is the kind plus the full linearized inheritance chain, e.g.
+/// `contract is ERC20, Context`
+fn item_for(contract: &Contract, contracts: &HashMap<String, Vec<Contract>>) -> TypeHierarchyItem {
+    let kind = match contract.kind.as_str() {
+        "interface" => SymbolKind::INTERFACE,
+        "library" => SymbolKind::MODULE,
+        _ => SymbolKind::CLASS,
+    };
+    let ancestors: Vec<String> = contract
+        .linearized
+        .iter()
+        .filter(|loc| **loc != contract.name_location)
+        .filter_map(|loc| contract_named_at(contracts, loc))
+        .map(|c| c.name)
+        .collect();
+    let detail = if ancestors.is_empty() {
+        contract.kind.clone()
+    } else {
+        format!("{} is {}", contract.kind, ancestors.join(", "))
+    };
+    TypeHierarchyItem {
+        name: contract.name.clone(),
+        kind,
+        tags: None,
+        detail: Some(detail),
+        uri: contract.name_location.uri.clone(),
+        range: contract.location.range,
+        selection_range: contract.name_location.range,
+        data: None,
+    }
+}
+
+fn item_location(item: &TypeHierarchyItem) -> Location {
+    Location {
+        uri: item.uri.clone(),
+        range: item.selection_range,
+    }
+}
+
+fn respond(req: &Value, result: Value) -> Option<String> {
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": req.get("id")?,
+        "result": result,
+    }).to_string())
+}
+
+/// `textDocument/prepareTypeHierarchy`: the contract declared or referenced under the cursor
+pub fn handle_prepare_type_hierarchy(req: &Value) -> Option<String> {
+    let params: TypeHierarchyPrepareParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+    let uri = params.text_document_position_params.text_document.uri.to_string();
+    let pos = params.text_document_position_params.position;
+
+    let reference = reference_at(&uri, pos);
+    let contracts = CONTRACT_MAP.lock().ok()?;
+    let items = reference
+        .and_then(|r| contract_named_at(&contracts, &r.target))
+        .map(|contract| vec![item_for(&contract, &contracts)]);
+
+    respond(req, json!(items))
+}
+
+/// `typeHierarchy/supertypes`: the direct bases of the item, in `is` order
+pub fn handle_supertypes(req: &Value) -> Option<String> {
+    let params: TypeHierarchySupertypesParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+
+    let contracts = CONTRACT_MAP.lock().ok()?;
+    let items = contract_named_at(&contracts, &item_location(&params.item)).map(|contract| {
+        contract
+            .bases
+            .iter()
+            .filter_map(|base| contract_named_at(&contracts, base))
+            .map(|base| item_for(&base, &contracts))
+            .collect::<Vec<_>>()
+    });
+
+    respond(req, json!(items))
+}
+
+/// `typeHierarchy/subtypes`: compiled contracts inheriting directly from the item
+pub fn handle_subtypes(req: &Value) -> Option<String> {
+    let params: TypeHierarchySubtypesParams =
+        serde_json::from_value(req.get("params")?.clone()).ok()?;
+
+    let contracts = CONTRACT_MAP.lock().ok()?;
+    let items: Vec<TypeHierarchyItem> = direct_subtypes(&contracts, &item_location(&params.item))
+        .iter()
+        .map(|c| item_for(c, &contracts))
+        .collect();
+
+    respond(req, json!(items))
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use once_cell::sync::Lazy;
+
+use crate::lsp::handler::{enforce_cache_quota, handle_and_publish};
+use crate::lsp::outgoing;
+use crate::util::log::log_to_file;
+
+/// Run once a job is done with, whether it compiled or a newer job made it moot
+pub type OnDone = Box<dyn FnOnce() + Send>;
+
+/// A document to compile and publish diagnostics for
+pub struct CompileJob {
+    pub uri: String,
+    pub text: String,
+    pub version: Option<i32>,
+    /// Position of this job among all submitted for `uri`
+    pub generation: u64,
+    pub on_done: Option<OnDone>,
+}
+
+/// Global map: file URI → generation of its newest compile job
+static GENERATIONS: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Whether a newer job has been submitted for `uri` since `generation`
+fn superseded(uri: &str, generation: u64) -> bool {
+    GENERATIONS
+        .lock()
+        .is_ok_and(|latest| latest.get(uri).is_some_and(|g| *g > generation))
+}
+
+/// Queue feeding the compile worker, started on first use
+static QUEUE: Lazy<Mutex<Sender<CompileJob>>> = Lazy::new(|| {
+    let (tx, rx) = channel();
+    thread::spawn(move || run(rx));
+    Mutex::new(tx)
+});
+
+/// Hand a document to the compile worker; diagnostics are published asynchronously
+pub fn submit(uri: &str, text: &str, version: Option<i32>) {
+    enqueue(uri, text, version, None);
+}
+
+/// Like [`submit`], calling `on_done` once the diagnostics are published
+pub fn submit_then(
+    uri: &str,
+    text: &str,
+    version: Option<i32>,
+    on_done: impl FnOnce() + Send + 'static,
+) {
+    enqueue(uri, text, version, Some(Box::new(on_done)));
+}
+
+fn enqueue(uri: &str, text: &str, version: Option<i32>, on_done: Option<OnDone>) {
+    let generation = match GENERATIONS.lock() {
+        Ok(mut latest) => {
+            let next = latest.get(uri).map_or(1, |g| g + 1);
+            latest.insert(uri.to_string(), next);
+            next
+        }
+        Err(_) => 0,
+    };
+    let job = CompileJob {
+        uri: uri.to_string(),
+        text: text.to_string(),
+        version,
+        generation,
+        on_done,
+    };
+    if let Ok(tx) = QUEUE.lock()
+        && tx.send(job).is_err()
+    {
+        log_to_file("[worker] Compile worker is gone, dropping job");
+    }
+}
+
+fn run(rx: Receiver<CompileJob>) {
+    for mut job in rx {
+        let on_done = job.on_done.take();
+        compile(&job);
+        if let Some(on_done) = on_done {
+            on_done();
+        }
+    }
+}
+
+fn compile(job: &CompileJob) {
+    // a newer edit is queued behind this one; compiling it would be wasted work
+    if superseded(&job.uri, job.generation) {
+        return;
+    }
+    let publishes = handle_and_publish(&job.uri, &job.text, job.version);
+    // never let an older compile overwrite a newer one's results
+    if superseded(&job.uri, job.generation) {
+        log_to_file(&format!("[worker] Dropping stale diagnostics for {}", job.uri));
+        return;
+    }
+    for publish in publishes {
+        if let Err(e) = outgoing::send(&publish) {
+            log_to_file(&format!("[worker] Failed to publish diagnostics: {}", e));
+        }
+    }
+    // a compile may have triggered a download
+    enforce_cache_quota();
+}
@@ -1,62 +1,84 @@
-mod lsp;
-mod config;
-mod project;
-mod analysis;
-mod util;
-pub mod solc;
+use std::io::{self, BufReader};
+use std::net::TcpListener;
+use std::path::Path;
 
+use emacs_solidity_server::server::{self, log_to_file};
 
-use std::io::{self, BufRead, BufReader, Read, Write};
-use lsp::handler::handle_request;
+const USAGE: &str =
+    "Usage: emacs-solidity-server [--stdio | --socket <port>] [--log-file <path> | --log-stderr]";
 
 fn main() {
-    let stdin = io::stdin();
-    let stdout = io::stdout();
-    let mut reader = BufReader::new(stdin.lock());
-    let mut writer = stdout.lock();
-    let mut buffer = String::new();
-
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 && args[1] != "--stdio" {
-        eprintln!("Expected --stdio as argument");
-        std::process::exit(1);
-    }
+    let mut args = std::env::args().skip(1);
+    let mut transport_args = Vec::new();
 
-    loop {
-        // --- Parse LSP headers ---
-        let mut content_length = 0;
-        loop {
-            buffer.clear();
-            if reader.read_line(&mut buffer).unwrap() == 0 {
-                return; // EOF
-            }
-            if buffer == "\r\n" {
-                break; // End of headers
-            }
-            if buffer.to_lowercase().starts_with("content-length:") {
-                let parts: Vec<&str> = buffer.split(':').collect();
-                content_length = parts[1].trim().parse::<usize>().unwrap_or(0);
-            }
+    // logging flags may come in any position; the rest select the transport
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log-stderr" => server::log_to_stderr(),
+            "--log-file" => match args.next() {
+                Some(path) => log_to_path(&path),
+                None => {
+                    eprintln!("--log-file needs a path\n{}", USAGE);
+                    std::process::exit(1);
+                }
+            },
+            _ if arg.starts_with("--log-file=") => log_to_path(&arg["--log-file=".len()..]),
+            _ => transport_args.push(arg),
         }
+    }
 
-        if content_length == 0 {
-            eprintln!("Invalid Content-Length");
-            continue;
+    match transport_args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] | ["--stdio"] => {
+            let stdin = io::stdin();
+            server::serve(BufReader::new(stdin.lock()), io::stdout());
+        }
+        ["--socket", port] => serve_socket(port),
+        [arg] if arg.starts_with("--socket=") => serve_socket(&arg["--socket=".len()..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
         }
+    }
+}
 
-        // --- Read the actual JSON payload ---
-        let mut content = vec![0u8; content_length];
-        reader.read_exact(&mut content).unwrap();
+fn log_to_path(path: &str) {
+    if let Err(e) = server::log_to_path(Path::new(path)) {
+        eprintln!("Failed to open log file {}: {}", path, e);
+        std::process::exit(1);
+    }
+}
 
-        let request_str = String::from_utf8_lossy(&content);
+/// Accept one client on `127.0.0.1:<port>` and serve it
+fn serve_socket(port: &str) {
+    let Ok(port) = port.parse::<u16>() else {
+        eprintln!("Invalid port {:?}\n{}", port, USAGE);
+        std::process::exit(1);
+    };
 
-        // --- Handle request ---
-        if let Some(response) = handle_request(&request_str) {
-            let response_bytes = response.as_bytes();
-            let header = format!("Content-Length: {}\r\n\r\n", response_bytes.len());
-            writer.write_all(header.as_bytes()).unwrap();
-            writer.write_all(response_bytes).unwrap();
-            writer.flush().unwrap();
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on port {}: {}", port, e);
+            std::process::exit(1);
         }
-    }
+    };
+    log_to_file(&format!("[transport] Waiting for a client on 127.0.0.1:{}", port));
+
+    let (stream, peer) = match listener.accept() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to accept a client: {}", e);
+            std::process::exit(1);
+        }
+    };
+    log_to_file(&format!("[transport] Client connected from {}", peer));
+
+    let writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Failed to set up the connection: {}", e);
+            std::process::exit(1);
+        }
+    };
+    server::serve(BufReader::new(stream), writer);
 }
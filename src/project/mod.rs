@@ -1,2 +1,3 @@
 pub mod remappings;
 pub mod root;
+pub mod workspace;
@@ -1,34 +1,204 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::config;
+use crate::util::imports::{import_regex, resolve_import};
+use crate::util::log::log_to_file;
+use crate::util::text::blank_comments;
+
+/// What a remapping target is relative to
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetBase {
+    /// Declared as an absolute path
+    Absolute,
+    /// Relative to the project root (the common case)
+    ProjectRoot,
+    /// Relative to the editor's workspace root, e.g. a monorepo whose `lib/`
+    /// lives above the package
+    WorkspaceRoot(PathBuf),
+}
 
 #[derive(Debug, Clone)]
 pub struct Remapping {
     pub prefix: String,
+    /// Target as declared
     pub target: PathBuf,
+    pub base: TargetBase,
 }
 
-pub fn parse_remappings_txt(path: &Path) -> Vec<Remapping> {
-    if let Ok(content) = fs::read_to_string(path) {
-        content
-            .lines()
-            .filter_map(|line| {
-                let parts: Vec<&str> = line.trim().split('=').map(str::trim).collect();
-                if parts.len() == 2 {
-                    Some(Remapping {
-                        prefix: parts[0].to_string(),
-                        target: PathBuf::from(parts[1]),
-                    })
-                } else {
-                    None
-                }
-            })
-            .collect()
-    } else {
-        vec![]
+/// A target spelled the one way, so `./lib/oz`, `lib\oz` and `lib//oz` all
+/// compare equal to `lib/oz`. A leading `//` (a UNC path) is kept.
+fn normalize_target(target: &str) -> String {
+    let target = target.replace('\\', "/");
+    let (unc, rest) = match target.strip_prefix("//") {
+        Some(rest) => ("//", rest),
+        None => ("", target.as_str()),
+    };
+    let mut normalized = rest.to_string();
+    while normalized.contains("//") {
+        normalized = normalized.replace("//", "/");
+    }
+    normalized.insert_str(0, unc);
+    while let Some(rest) = normalized.strip_prefix("./").filter(|rest| !rest.is_empty()) {
+        normalized = rest.to_string();
     }
+    normalized
+}
+
+impl Remapping {
+    /// Like solc, a target gets a trailing `/` when its prefix has one, so
+    /// `@oz/=lib/oz` behaves as `@oz/=lib/oz/`
+    fn new(prefix: &str, target: &str) -> Self {
+        let target = normalize_target(target);
+        let target = if prefix.ends_with('/') && !target.is_empty() && !target.ends_with('/') {
+            PathBuf::from(format!("{}/", target))
+        } else {
+            PathBuf::from(target)
+        };
+        let base = if target.is_absolute() {
+            TargetBase::Absolute
+        } else {
+            TargetBase::ProjectRoot
+        };
+        Remapping {
+            prefix: prefix.to_string(),
+            target,
+            base,
+        }
+    }
+
+    /// Absolute path of the target
+    pub fn resolved_target(&self, project_root: &Path) -> PathBuf {
+        self.resolve(&self.target, project_root)
+    }
+
+    /// `path`, relative to the target's base, made absolute
+    fn resolve(&self, path: &Path, project_root: &Path) -> PathBuf {
+        match &self.base {
+            TargetBase::Absolute => path.to_path_buf(),
+            TargetBase::ProjectRoot => project_root.join(path),
+            TargetBase::WorkspaceRoot(root) => root.join(path),
+        }
+    }
+
+    /// The remapping as passed to solc. Targets are expressed relative to the
+    /// project root so they land on the same virtual paths as the sources.
+    pub fn to_solc(&self, project_root: &Path) -> String {
+        let target = match self.base {
+            TargetBase::ProjectRoot => self.target.clone(),
+            _ => {
+                let resolved = self.resolved_target(project_root);
+                pathdiff::diff_paths(&resolved, project_root).unwrap_or(resolved)
+            }
+        };
+        let mut target = target.to_string_lossy().replace('\\', "/");
+        if self.target.to_string_lossy().ends_with('/') && !target.ends_with('/') {
+            target.push('/');
+        }
+        format!("{}={}", self.prefix, target)
+    }
+
+    /// Rewrite `import` through this remapping if its prefix matches. As in
+    /// solc, the prefix is a plain string prefix and is replaced by the target,
+    /// the rest of the import appended as is: with `@oz=lib/oz`, `@oz/X.sol`
+    /// becomes `lib/oz/X.sol` and `@ozzy/X.sol` becomes `lib/ozzy/X.sol`.
+    pub fn apply(&self, import: &str, project_root: &Path) -> Option<PathBuf> {
+        let rest = import.strip_prefix(&self.prefix)?;
+        let remapped = format!("{}{}", self.target.to_string_lossy(), rest);
+        Some(self.resolve(Path::new(&remapped), project_root))
+    }
+}
+
+/// The remapping solc would apply to `import`: the one with the longest
+/// matching prefix, so `@openzeppelin/contracts/=...` beats `@openzeppelin/=...`.
+/// Among equally long prefixes the earlier (higher precedence) one wins.
+pub fn best_match<'a>(remappings: &'a [Remapping], import: &str) -> Option<&'a Remapping> {
+    remappings
+        .iter()
+        .filter(|r| import.starts_with(&r.prefix))
+        .rev()
+        .max_by_key(|r| r.prefix.len())
+}
+
+/// Resolve a non-relative import through the best matching remapping
+pub fn remap_import(remappings: &[Remapping], import: &str, project_root: &Path) -> Option<PathBuf> {
+    best_match(remappings, import)?.apply(import, project_root)
+}
+
+const FORGE_STD_PREFIX: &str = "forge-std/";
+
+/// Whether `source` imports forge-std but none of those imports resolves to
+/// a file, i.e. `forge install` hasn't been run. Imports are resolved as for
+/// compiling, so any remapping or include path that reaches the files
+/// counts; commented-out imports are ignored. A single missing file of an
+/// installed forge-std is left for solc to report.
+pub fn forge_std_missing(source: &str, remappings: &[Remapping], project_root: &Path) -> bool {
+    let code = blank_comments(source);
+    let imports: Vec<String> = import_regex()
+        .captures_iter(&code)
+        .map(|cap| cap[1].trim().to_string())
+        .filter(|import| import.starts_with(FORGE_STD_PREFIX))
+        .collect();
+
+    !imports.is_empty()
+        && imports.iter().all(|import| {
+            !resolve_import(import, project_root, remappings, project_root)
+                .is_some_and(|path| path.is_file())
+        })
+}
+
+/// Decide what a relative target is relative to: the project root when the
+/// target exists there, else the workspace root when it exists there instead
+fn resolve_base(rem: &mut Remapping, project_root: &Path, workspace_root: Option<&Path>) {
+    if rem.base != TargetBase::ProjectRoot || project_root.join(&rem.target).exists() {
+        return;
+    }
+    if let Some(root) = workspace_root
+        && root != project_root
+        && root.join(&rem.target).exists()
+    {
+        log_to_file(&format!(
+            "[remappings] '{}' resolved against workspace root {}",
+            rem.prefix,
+            root.display()
+        ));
+        rem.base = TargetBase::WorkspaceRoot(root.to_path_buf());
+    }
+}
+
+/// One `prefix=target` entry. Only the first `=` splits, so targets may
+/// contain `=` themselves.
+fn parse_remapping(entry: &str) -> Option<Remapping> {
+    let (prefix, target) = entry.trim().split_once('=')?;
+    let (prefix, target) = (prefix.trim(), target.trim());
+    if prefix.is_empty() || target.is_empty() {
+        return None;
+    }
+    Some(Remapping::new(prefix, target))
+}
+
+/// Remappings from a `remappings.txt`, one per line. Blank lines and `#` or
+/// `//` comments are skipped.
+pub fn parse_remappings_txt(path: &Path) -> Vec<Remapping> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("//"))
+        .filter_map(parse_remapping)
+        .collect()
 }
 
+/// Remappings from a `foundry.toml`: the `remappings = [...]` array (as in
+/// `[profile.default]`, possibly spanning several lines) and the entries of a
+/// `[remappings]` table.
 pub fn parse_foundry_toml(path: &Path) -> Vec<Remapping> {
     let content = match fs::read_to_string(path) {
         Ok(c) => c,
@@ -36,8 +206,19 @@ pub fn parse_foundry_toml(path: &Path) -> Vec<Remapping> {
     };
 
     let mut remappings = vec![];
-    let mut in_remappings_block = false;
+    let array_re = Regex::new(r"(?m)^\s*remappings\s*=\s*\[([^\]]*)\]").unwrap();
+    let string_re = Regex::new(r#""([^"]*)"|'([^']*)'"#).unwrap();
+    if let Some(cap) = array_re.captures(&content) {
+        let entries = cap[1].lines().map(|line| line.split('#').next().unwrap_or_default());
+        for entry in entries {
+            for string in string_re.captures_iter(entry) {
+                let value = string.get(1).or_else(|| string.get(2)).map_or("", |m| m.as_str());
+                remappings.extend(parse_remapping(value));
+            }
+        }
+    }
 
+    let mut in_remappings_block = false;
     for line in content.lines() {
         let line = line.trim();
 
@@ -46,51 +227,519 @@ pub fn parse_foundry_toml(path: &Path) -> Vec<Remapping> {
             continue;
         }
 
-        if in_remappings_block && line.contains('=') {
-            let parts: Vec<&str> = line.trim_matches('"').split('=').map(str::trim).collect();
-            if parts.len() == 2 {
-                remappings.push(Remapping {
-                    prefix: parts[0].to_string(),
-                    target: PathBuf::from(parts[1]),
-                });
+        if in_remappings_block && !line.starts_with('#') {
+            let entry = line.replace(['"', '\''], "");
+            remappings.extend(parse_remapping(&entry));
+        }
+    }
+
+    remappings
+}
+
+const NODE_MODULES: &str = "node_modules";
+
+/// Project root → remappings synthesized from its `node_modules`
+static NODE_MODULES_REMAPPINGS: Lazy<Mutex<HashMap<PathBuf, Vec<Remapping>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Whether `dir` has a `.sol` file within `depth` levels, skipping nested
+/// `node_modules` and hidden directories
+fn contains_solidity(dir: &Path, depth: usize) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() {
+            depth > 0
+                && name != NODE_MODULES
+                && !name.starts_with('.')
+                && contains_solidity(&path, depth - 1)
+        } else {
+            name.ends_with(".sol")
+        }
+    })
+}
+
+/// One remapping per installed package that ships Solidity sources, e.g.
+/// `@openzeppelin/contracts/=node_modules/@openzeppelin/contracts/` and
+/// `solmate/=node_modules/solmate/`, the way Hardhat resolves imports
+fn scan_node_modules(project_root: &Path) -> Vec<Remapping> {
+    let node_modules = project_root.join(NODE_MODULES);
+    let Ok(entries) = fs::read_dir(&node_modules) else {
+        return vec![];
+    };
+
+    let mut packages = vec![];
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || !entry.path().is_dir() {
+            continue;
+        }
+        if name.starts_with('@') {
+            // a scope: its children are the packages
+            let Ok(scoped) = fs::read_dir(entry.path()) else {
+                continue;
+            };
+            for package in scoped.flatten().filter(|p| p.path().is_dir()) {
+                packages.push(format!("{}/{}", name, package.file_name().to_string_lossy()));
             }
+        } else {
+            packages.push(name);
         }
     }
 
+    packages.sort();
+    packages
+        .into_iter()
+        .filter(|package| contains_solidity(&node_modules.join(package), 3))
+        .map(|package| {
+            Remapping::new(&format!("{}/", package), &format!("{}/{}/", NODE_MODULES, package))
+        })
+        .collect()
+}
+
+/// Remappings for the packages in `project_root/node_modules`, scanned once
+/// per project and cached until [`invalidate_node_modules`]
+fn node_modules_remappings(project_root: &Path) -> Vec<Remapping> {
+    let Ok(mut cache) = NODE_MODULES_REMAPPINGS.lock() else {
+        return scan_node_modules(project_root);
+    };
+    cache
+        .entry(project_root.to_path_buf())
+        .or_insert_with(|| {
+            let remappings = scan_node_modules(project_root);
+            log_to_file(&format!(
+                "[remappings] Found {} Solidity packages in {}",
+                remappings.len(),
+                project_root.join(NODE_MODULES).display()
+            ));
+            remappings
+        })
+        .clone()
+}
+
+/// Remappings giving the `includePaths` directories solc `--include-path`
+/// semantics: every entry `name` of an include directory is importable as
+/// `name/...` (or `name` for a file). Relative include paths are taken from
+/// the project root, else from the workspace root.
+fn include_path_remappings(project_root: &Path, workspace_root: Option<&Path>) -> Vec<Remapping> {
+    let mut remappings = vec![];
+    for include in config::get().include_paths {
+        let include = Path::new(&include);
+        let dir = [Some(project_root), workspace_root]
+            .into_iter()
+            .flatten()
+            .map(|root| root.join(include))
+            .find(|dir| dir.is_dir());
+        let Some(entries) = dir.and_then(|dir| fs::read_dir(dir).ok()) else {
+            log_to_file(&format!("[remappings] Include path {} not found", include.display()));
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = entry.path();
+            if name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                remappings.push(Remapping::new(
+                    &format!("{}/", name),
+                    &format!("{}/", path.to_string_lossy()),
+                ));
+            } else if name.ends_with(".sol") {
+                remappings.push(Remapping::new(&name, &path.to_string_lossy()));
+            }
+        }
+    }
     remappings
 }
 
+/// Forget the cached `node_modules` scan of a project, e.g. after its
+/// `package.json` changed
+pub fn invalidate_node_modules(project_root: &Path) {
+    if let Ok(mut cache) = NODE_MODULES_REMAPPINGS.lock() {
+        cache.remove(project_root);
+    }
+}
+
+/// Whether `root` is a Hardhat project
+pub fn has_hardhat_config(root: &Path) -> bool {
+    root.join("hardhat.config.js").exists() || root.join("hardhat.config.ts").exists()
+}
+
 fn has_hardhat_or_truffle_config(root: &Path) -> bool {
-    root.join("hardhat.config.js").exists()
-        || root.join("hardhat.config.ts").exists()
-        || root.join("truffle-config.js").exists()
+    has_hardhat_config(root) || root.join("truffle-config.js").exists()
 }
 
 
-pub fn parse_remappings(project_root: &Path) -> Vec<Remapping> {
-    let mut seen = HashSet::new();
+/// Project root and workspace root remappings were parsed for
+type RemappingsKey = (PathBuf, Option<PathBuf>);
+
+/// Remappings per project, until a project config changes
+static REMAPPINGS: Lazy<Mutex<HashMap<RemappingsKey, Vec<Remapping>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Forget every cached set of remappings, e.g. after a `remappings.txt` or
+/// `foundry.toml` changed
+pub fn invalidate_remappings() {
+    if let Ok(mut cache) = REMAPPINGS.lock() {
+        cache.clear();
+    }
+}
+
+/// Remappings of a project, parsed once and reused until `invalidate_remappings`
+pub fn parse_remappings(project_root: &Path, workspace_root: Option<&Path>) -> Vec<Remapping> {
+    let key = (project_root.to_path_buf(), workspace_root.map(Path::to_path_buf));
+    if let Some(cached) = REMAPPINGS.lock().ok().and_then(|cache| cache.get(&key).cloned()) {
+        return cached;
+    }
+    let remappings = collect_remappings(project_root, workspace_root);
+    if let Ok(mut cache) = REMAPPINGS.lock() {
+        cache.insert(key, remappings.clone());
+    }
+    remappings
+}
+
+/// Collect remappings for a project. When the same prefix is declared with
+/// conflicting targets, the first source wins, in order of precedence:
+/// `remappings.txt`, then `foundry.toml`, then the defaults: `forge-std/` for
+/// Foundry projects, and for Hardhat/Truffle ones a remapping per package in
+/// `node_modules` followed by a catch-all `@`.
+/// Entries of the `includePaths` directories come last.
+/// Relative targets missing under the project root are looked up under
+/// `workspace_root` as well.
+fn collect_remappings(project_root: &Path, workspace_root: Option<&Path>) -> Vec<Remapping> {
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
     let mut all = vec![];
 
-    for rem in parse_remappings_txt(&project_root.join("remappings.txt"))
-        .into_iter()
-        .chain(parse_foundry_toml(&project_root.join("foundry.toml")))
-    {
-        let key = format!("{}={}", rem.prefix, rem.target.display());
-        if seen.insert(key) {
-            all.push(rem);
+    let mut add = |rem: Remapping, origin: &str| {
+        match seen.get(&rem.prefix) {
+            Some(existing) if *existing == rem.target => {}
+            Some(existing) => {
+                log_to_file(&format!(
+                    "[remappings] Conflicting targets for prefix '{}': keeping '{}', ignoring '{}' from {}",
+                    rem.prefix,
+                    existing.display(),
+                    rem.target.display(),
+                    origin
+                ));
+            }
+            None => {
+                seen.insert(rem.prefix.clone(), rem.target.clone());
+                all.push(rem);
+            }
         }
+    };
+
+    for rem in parse_remappings_txt(&project_root.join("remappings.txt")) {
+        add(rem, "remappings.txt");
+    }
+    for rem in parse_foundry_toml(&project_root.join("foundry.toml")) {
+        add(rem, "foundry.toml");
+    }
+    // `forge init` layouts rely on forge's auto-detected forge-std remapping
+    if project_root.join("foundry.toml").exists() {
+        add(Remapping::new(FORGE_STD_PREFIX, "lib/forge-std/src/"), "foundry defaults");
     }
     // If hardhat.config.js or hardhat.config.ts or truffle-config.js exists
     if has_hardhat_or_truffle_config(project_root) {
-        let scoped_node_modules_remap = Remapping {
-            prefix: "@".to_string(),
-            target: PathBuf::from("node_modules/@"),
-        };
-
-        let key = format!("{}={}", scoped_node_modules_remap.prefix, scoped_node_modules_remap.target.display());
-        if seen.insert(key) {
-            all.push(scoped_node_modules_remap);
+        for rem in node_modules_remappings(project_root) {
+            add(rem, "node_modules");
         }
+        let scoped_node_modules_remap = Remapping::new("@", "node_modules/@");
+        add(scoped_node_modules_remap, "hardhat/truffle defaults");
+    }
+    for rem in include_path_remappings(project_root, workspace_root) {
+        add(rem, "includePaths");
+    }
+
+    for rem in &mut all {
+        resolve_base(rem, project_root, workspace_root);
     }
     all
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn project(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn pairs(remappings: &[Remapping]) -> Vec<(String, String)> {
+        remappings
+            .iter()
+            .map(|r| (r.prefix.clone(), r.target.to_string_lossy().to_string()))
+            .collect()
+    }
+
+    fn pair(prefix: &str, target: &str) -> (String, String) {
+        (prefix.to_string(), target.to_string())
+    }
+
+    #[test]
+    fn remappings_txt_skips_comments_and_blank_lines() {
+        let dir = project(&[(
+            "remappings.txt",
+            "# dependencies\n\n@openzeppelin/=lib/openzeppelin-contracts/\n  // solmate\nsolmate/ = lib/solmate/src/\nnot a remapping\n",
+        )]);
+
+        let remappings = parse_remappings_txt(&dir.path().join("remappings.txt"));
+
+        assert_eq!(
+            pairs(&remappings),
+            [
+                pair("@openzeppelin/", "lib/openzeppelin-contracts/"),
+                pair("solmate/", "lib/solmate/src/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn remappings_txt_splits_on_first_equals_only() {
+        let dir = project(&[("remappings.txt", "weird/=lib/a=b/\n")]);
+
+        let remappings = parse_remappings_txt(&dir.path().join("remappings.txt"));
+
+        assert_eq!(pairs(&remappings), [pair("weird/", "lib/a=b/")]);
+    }
+
+    #[test]
+    fn foundry_toml_profile_array() {
+        let dir = project(&[(
+            "foundry.toml",
+            "[profile.default]\nsrc = \"src\"\nremappings = [\n    \"@openzeppelin/=lib/openzeppelin-contracts/\", # OZ\n    'ds-test/=lib/ds-test/src/',\n]\n\n[fmt]\nline_length = 100\n",
+        )]);
+
+        let remappings = parse_foundry_toml(&dir.path().join("foundry.toml"));
+
+        assert_eq!(
+            pairs(&remappings),
+            [
+                pair("@openzeppelin/", "lib/openzeppelin-contracts/"),
+                pair("ds-test/", "lib/ds-test/src/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn foundry_toml_inline_array_and_table() {
+        let dir = project(&[(
+            "foundry.toml",
+            "[profile.default]\nremappings = [\"a/=lib/a/\"]\n\n[remappings]\n\"b/\" = \"lib/b/\"\n",
+        )]);
+
+        let remappings = parse_foundry_toml(&dir.path().join("foundry.toml"));
+
+        assert_eq!(pairs(&remappings), [pair("a/", "lib/a/"), pair("b/", "lib/b/")]);
+    }
+
+    #[test]
+    fn both_files_dedup_with_remappings_txt_winning() {
+        let dir = project(&[
+            ("remappings.txt", "@oz/=lib/oz-txt/\nsolmate/=lib/solmate/src/\n"),
+            (
+                "foundry.toml",
+                "[profile.default]\nremappings = [\"@oz/=lib/oz-toml/\", \"solmate/=lib/solmate/src/\"]\n",
+            ),
+        ]);
+
+        let remappings = parse_remappings(dir.path(), None);
+
+        assert_eq!(
+            pairs(&remappings),
+            [
+                pair("@oz/", "lib/oz-txt/"),
+                pair("solmate/", "lib/solmate/src/"),
+                pair("forge-std/", "lib/forge-std/src/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn hardhat_project_maps_node_modules_packages() {
+        let dir = project(&[
+            ("hardhat.config.js", "module.exports = {};\n"),
+            ("node_modules/@openzeppelin/contracts/token/ERC20.sol", ""),
+            ("node_modules/solmate/src/tokens/ERC20.sol", ""),
+            ("node_modules/left-pad/index.js", ""),
+        ]);
+
+        let remappings = parse_remappings(dir.path(), None);
+
+        assert_eq!(
+            pairs(&remappings),
+            [
+                pair("@openzeppelin/contracts/", "node_modules/@openzeppelin/contracts/"),
+                pair("solmate/", "node_modules/solmate/"),
+                pair("@", "node_modules/@"),
+            ]
+        );
+        assert_eq!(
+            remap_import(&remappings, "@openzeppelin/contracts/token/ERC20.sol", dir.path()),
+            Some(dir.path().join("node_modules/@openzeppelin/contracts/token/ERC20.sol"))
+        );
+    }
+
+    #[test]
+    fn foundry_project_resolves_at_alias_without_node_modules() {
+        let dir = project(&[
+            ("foundry.toml", "[profile.default]\nsrc = \"src\"\n"),
+            ("remappings.txt", "@oz/=lib/openzeppelin-contracts/contracts/\n"),
+            ("lib/openzeppelin-contracts/contracts/token/ERC20.sol", "contract ERC20 {}\n"),
+            ("src/Token.sol", "import \"@oz/token/ERC20.sol\";\n"),
+        ]);
+
+        let remappings = parse_remappings(dir.path(), None);
+
+        // no Hardhat config, so no `@` catch-all into node_modules
+        assert!(remappings.iter().all(|r| r.prefix != "@"));
+        let resolved = remap_import(&remappings, "@oz/token/ERC20.sol", dir.path()).unwrap();
+        assert_eq!(
+            resolved,
+            dir.path().join("lib/openzeppelin-contracts/contracts/token/ERC20.sol")
+        );
+        assert!(resolved.is_file());
+    }
+
+    #[test]
+    fn forge_std_is_missing_only_when_its_imports_dont_resolve() {
+        let dir = project(&[("foundry.toml", "[profile.default]\n")]);
+        let root = dir.path();
+        let test = "import {Test} from \"forge-std/Test.sol\";\ncontract T is Test {}\n";
+
+        let remappings = parse_remappings(root, None);
+        assert!(forge_std_missing(test, &remappings, root));
+
+        // commented-out imports and look-alikes don't count
+        let commented = "// import \"forge-std/Test.sol\";\n/* import 'forge-std/Vm.sol'; */\n";
+        assert!(!forge_std_missing(commented, &remappings, root));
+
+        // a prefix without a slash reaches the file just as well
+        fs::create_dir_all(root.join("deps/forge-std/src")).unwrap();
+        fs::write(root.join("deps/forge-std/src/Test.sol"), "").unwrap();
+        let unslashed = vec![Remapping::new("forge-std", "deps/forge-std/src")];
+        assert!(!forge_std_missing(test, &unslashed, root));
+
+        // as does the default remapping once `forge install` has run
+        fs::create_dir_all(root.join("lib/forge-std/src")).unwrap();
+        fs::write(root.join("lib/forge-std/src/Test.sol"), "").unwrap();
+        assert!(!forge_std_missing(test, &remappings, root));
+    }
+
+    #[test]
+    fn directory_prefix_keeps_the_rest_of_the_import() {
+        let root = Path::new("/project");
+        let remapping = Remapping::new("@company/", "packages/");
+
+        assert_eq!(
+            remapping.apply("@company/math/Safe.sol", root),
+            Some(PathBuf::from("/project/packages/math/Safe.sol"))
+        );
+        assert_eq!(remapping.apply("@other/math/Safe.sol", root), None);
+    }
+
+    #[test]
+    fn target_gets_trailing_slash_of_prefix() {
+        let root = Path::new("/project");
+        let remapping = Remapping::new("@company/", "packages");
+
+        assert_eq!(remapping.target, PathBuf::from("packages/"));
+        assert_eq!(remapping.to_solc(root), "@company/=packages/");
+        assert_eq!(
+            remapping.apply("@company/math/Safe.sol", root),
+            Some(PathBuf::from("/project/packages/math/Safe.sol"))
+        );
+    }
+
+    #[test]
+    fn prefix_without_slash_is_a_plain_string_prefix() {
+        let root = Path::new("/project");
+        let remapping = Remapping::new("@oz", "lib/oz");
+
+        assert_eq!(remapping.target, PathBuf::from("lib/oz"));
+        assert_eq!(
+            remapping.apply("@oz/token/ERC20.sol", root),
+            Some(PathBuf::from("/project/lib/oz/token/ERC20.sol"))
+        );
+        assert_eq!(
+            remapping.apply("@ozzy/ERC20.sol", root),
+            Some(PathBuf::from("/project/lib/ozzy/ERC20.sol"))
+        );
+    }
+
+    #[test]
+    fn absolute_target_ignores_project_root() {
+        let remapping = Remapping::new("ds-test/", "/opt/ds-test/src/");
+
+        assert_eq!(
+            remapping.apply("ds-test/test.sol", Path::new("/project")),
+            Some(PathBuf::from("/opt/ds-test/src/test.sol"))
+        );
+    }
+
+    #[test]
+    fn spellings_of_one_target_are_deduplicated() {
+        let dir = project(&[
+            ("remappings.txt", "@oz/=./lib/oz\nsolmate/=lib//solmate/src/\n"),
+            ("foundry.toml", "remappings = ['@oz/=lib\\oz', \"solmate/=lib/solmate/src\"]\n"),
+        ]);
+
+        let remappings = parse_remappings(dir.path(), None);
+
+        assert_eq!(
+            pairs(&remappings),
+            [
+                pair("@oz/", "lib/oz/"),
+                pair("solmate/", "lib/solmate/src/"),
+                pair("forge-std/", "lib/forge-std/src/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn more_specific_remapping_wins_whatever_its_order() {
+        let dir = project(&[
+            (
+                "remappings.txt",
+                "@openzeppelin/=lib/oz/\n@openzeppelin/contracts/=lib/oz-contracts/contracts/\n",
+            ),
+            ("lib/oz-contracts/contracts/token/ERC20.sol", ""),
+        ]);
+        let remappings = parse_remappings(dir.path(), None);
+
+        let resolved = remap_import(&remappings, "@openzeppelin/contracts/token/ERC20.sol", dir.path());
+        assert_eq!(resolved, Some(dir.path().join("lib/oz-contracts/contracts/token/ERC20.sol")));
+
+        let resolved = remap_import(&remappings, "@openzeppelin/utils/Strings.sol", dir.path());
+        assert_eq!(resolved, Some(dir.path().join("lib/oz/utils/Strings.sol")));
+    }
+
+    #[test]
+    fn best_match_prefers_longest_overlapping_prefix() {
+        let remappings = vec![
+            Remapping::new("@openzeppelin/", "lib/oz/"),
+            Remapping::new("@openzeppelin/contracts/", "lib/oz-contracts/contracts/"),
+            Remapping::new("@openzeppelin/contracts/", "lib/shadowed/"),
+        ];
+
+        let best = best_match(&remappings, "@openzeppelin/contracts/token/ERC20.sol").unwrap();
+        assert_eq!(best.target, PathBuf::from("lib/oz-contracts/contracts/"));
+
+        let best = best_match(&remappings, "@openzeppelin/foundry/Test.sol").unwrap();
+        assert_eq!(best.target, PathBuf::from("lib/oz/"));
+
+        assert!(best_match(&remappings, "solmate/ERC20.sol").is_none());
+    }
+}
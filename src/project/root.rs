@@ -1,6 +1,10 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-const PROJECT_FILES: [&str; 5] = [
+use once_cell::sync::Lazy;
+
+pub const PROJECT_FILES: [&str; 5] = [
     "foundry.toml",
     "remappings.txt",
     "hardhat.config.js",
@@ -8,7 +12,33 @@ const PROJECT_FILES: [&str; 5] = [
     "truffle-config.js",
 ];
 
+/// Directory → project root found from it, until a project config changes
+static PROJECT_ROOTS: Lazy<Mutex<HashMap<PathBuf, Option<PathBuf>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Forget every cached project root, e.g. after a project config file was
+/// created or deleted
+pub fn invalidate_project_roots() {
+    if let Ok(mut cache) = PROJECT_ROOTS.lock() {
+        cache.clear();
+    }
+}
+
+/// Outermost ancestor of `start` holding a project config file. Computed
+/// once per directory and reused until `invalidate_project_roots`.
 pub fn find_project_root(start: &Path) -> Option<PathBuf> {
+    let dir = if start.is_dir() { start } else { start.parent().unwrap_or(start) };
+    if let Some(cached) = PROJECT_ROOTS.lock().ok().and_then(|cache| cache.get(dir).cloned()) {
+        return cached;
+    }
+    let root = walk_up_for_project_root(dir);
+    if let Ok(mut cache) = PROJECT_ROOTS.lock() {
+        cache.insert(dir.to_path_buf(), root.clone());
+    }
+    root
+}
+
+fn walk_up_for_project_root(start: &Path) -> Option<PathBuf> {
     let mut current = start.to_path_buf();
     let mut last_match = None;
 
@@ -26,3 +56,9 @@ pub fn find_project_root(start: &Path) -> Option<PathBuf> {
 
     last_match
 }
+
+/// A file outside any project: no project config file and no `.git` in any
+/// ancestor. Such files compile on their own, with relative imports only.
+pub fn is_single_file(path: &Path) -> bool {
+    find_project_root(path).is_none() && !path.ancestors().any(|dir| dir.join(".git").exists())
+}
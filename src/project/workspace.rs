@@ -0,0 +1,252 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::util::log::log_to_file;
+
+/// Directory holding the project's own contracts, following `paths.sources`
+/// (Hardhat) or `src` (Foundry) when configured, else the usual defaults.
+pub fn find_sources_dir(project_root: &Path) -> PathBuf {
+    if let Ok(content) = fs::read_to_string(project_root.join("foundry.toml")) {
+        let src_re = Regex::new(r#"(?m)^\s*src\s*=\s*["']([^"']+)["']"#).unwrap();
+        if let Some(cap) = src_re.captures(&content) {
+            return project_root.join(&cap[1]);
+        }
+    }
+
+    let sources_re = Regex::new(r#"sources\s*:\s*["']([^"']+)["']"#).unwrap();
+    for config in ["hardhat.config.js", "hardhat.config.ts"] {
+        if let Ok(content) = fs::read_to_string(project_root.join(config))
+            && let Some(cap) = sources_re.captures(&content)
+        {
+            return project_root.join(&cap[1]);
+        }
+    }
+
+    for default in ["src", "contracts"] {
+        let dir = project_root.join(default);
+        if dir.is_dir() {
+            return dir;
+        }
+    }
+
+    project_root.to_path_buf()
+}
+
+/// Names forge accepts in `ignored_error_codes` for the solc codes they stand for
+const FORGE_ERROR_CODE_NAMES: [(&str, &str); 8] = [
+    ("license", "1878"),
+    ("code-size", "5574"),
+    ("init-code-size", "3860"),
+    ("func-mutability", "2018"),
+    ("unused-var", "2072"),
+    ("unused-param", "5667"),
+    ("unreachable", "5740"),
+    ("shadowing", "2519"),
+];
+
+/// solc error codes `foundry.toml` tells forge to ignore (`ignored_error_codes`),
+/// with forge's names (e.g. `"license"`) translated to their numeric code
+pub fn foundry_ignored_error_codes(project_root: &Path) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(project_root.join("foundry.toml")) else {
+        return vec![];
+    };
+    let codes_re = Regex::new(r"(?m)^\s*ignored_error_codes\s*=\s*\[([^\]]*)\]").unwrap();
+    let Some(cap) = codes_re.captures(&content) else {
+        return vec![];
+    };
+
+    cap[1]
+        .split(',')
+        .map(|code| code.trim().trim_matches(|c| c == '"' || c == '\''))
+        .filter(|code| !code.is_empty())
+        .map(|code| {
+            FORGE_ERROR_CODE_NAMES
+                .iter()
+                .find(|(name, _)| *name == code)
+                .map_or(code, |(_, number)| number)
+                .to_string()
+        })
+        .collect()
+}
+
+/// A scalar or array TOML value as JSON: strings, integers, booleans and
+/// arrays of those. Anything else (inline tables, dates) is `None`.
+fn toml_value(raw: &str) -> Option<Value> {
+    let raw = raw.trim();
+    if let Some(items) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        return items
+            .split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(toml_value)
+            .collect::<Option<Vec<_>>>()
+            .map(Value::Array);
+    }
+    for quote in ['"', '\''] {
+        if let Some(text) = raw.strip_prefix(quote).and_then(|r| r.strip_suffix(quote)) {
+            return Some(Value::String(text.to_string()));
+        }
+    }
+    match raw {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => raw.replace('_', "").parse::<i64>().ok().map(Value::from),
+    }
+}
+
+/// `line` without its trailing `# comment`; a `#` inside a string is kept
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// `show_unproved` → `showUnproved`
+fn camel_case(key: &str) -> String {
+    let mut parts = key.split('_');
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+/// solc's `settings.modelChecker` from `foundry.toml`'s
+/// `[profile.default.model_checker]` table, keys translated to solc's
+/// camelCase (`show_unproved` → `showUnproved`). Values that aren't plain
+/// strings, numbers, booleans or arrays of those (e.g. the `contracts` map)
+/// are skipped. Arrays may span several lines; `#` comments are ignored.
+pub fn foundry_model_checker(project_root: &Path) -> Option<Value> {
+    let content = fs::read_to_string(project_root.join("foundry.toml")).ok()?;
+    let mut settings = Map::new();
+    let mut in_model_checker = false;
+    let mut lines = content.lines().map(|line| strip_toml_comment(line).trim());
+
+    while let Some(line) = lines.next() {
+        if line.starts_with('[') {
+            in_model_checker = line == "[profile.default.model_checker]";
+            continue;
+        }
+        if !in_model_checker {
+            continue;
+        }
+        let Some((key, raw)) = line.split_once('=') else {
+            continue;
+        };
+        let mut raw = raw.trim().to_string();
+        // an array continues up to the line closing it
+        if raw.starts_with('[') {
+            while !raw.ends_with(']') {
+                let Some(next) = lines.next() else {
+                    break;
+                };
+                raw.push(' ');
+                raw.push_str(next);
+            }
+        }
+        match toml_value(&raw) {
+            Some(value) => {
+                settings.insert(camel_case(key.trim()), value);
+            }
+            None => log_to_file(&format!(
+                "[model_checker] Skipping unsupported value for {}",
+                key.trim()
+            )),
+        }
+    }
+
+    (!settings.is_empty()).then_some(Value::Object(settings))
+}
+
+/// Directories skipped by workspace indexing: dependencies and build output
+pub const DEFAULT_INDEX_IGNORE: [&str; 5] = ["lib", "node_modules", "out", "cache", "artifacts"];
+
+/// Recursively collect every `.sol` file under `dir`, sorted for a stable order.
+/// Directories whose name is in `ignored` (or that are hidden) are not descended into.
+pub fn collect_solidity_files(dir: &Path, ignored: &[String]) -> Vec<PathBuf> {
+    fn walk(dir: &Path, ignored: &[String], acc: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') || ignored.contains(&name) {
+                    continue;
+                }
+                walk(&path, ignored, acc);
+            } else if path.extension().and_then(|e| e.to_str()) == Some("sol") {
+                acc.push(path);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    walk(dir, ignored, &mut files);
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn model_checker(toml: &str) -> Option<Value> {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("foundry.toml"), toml).unwrap();
+        foundry_model_checker(dir.path())
+    }
+
+    #[test]
+    fn model_checker_keys_are_camel_cased() {
+        let toml = "[profile.default]\nsrc = \"src\"\n\n[profile.default.model_checker]\n\
+                    engine = 'chc'\ntimeout = 10_000\nshow_unproved = true\n\n[fmt]\nline_length = 100\n";
+
+        assert_eq!(
+            model_checker(toml),
+            Some(json!({ "engine": "chc", "timeout": 10000, "showUnproved": true }))
+        );
+        assert_eq!(model_checker("[profile.default]\nsrc = \"src\"\n"), None);
+    }
+
+    #[test]
+    fn model_checker_values_with_inline_comments() {
+        let toml = "[profile.default.model_checker]\n\
+                    engine = \"chc\" # constrained Horn clauses\n\
+                    # timeout = 1\n\
+                    targets = [\"assert\", \"underflow\"] # the rest are too slow\n\
+                    solvers = [\"z3#4\"]\n";
+
+        assert_eq!(
+            model_checker(toml),
+            Some(json!({ "engine": "chc", "targets": ["assert", "underflow"], "solvers": ["z3#4"] }))
+        );
+    }
+
+    #[test]
+    fn model_checker_array_spanning_several_lines() {
+        let toml = "[profile.default.model_checker]\n\
+                    targets = [\n    \"assert\", # checked first\n    \"overflow\",\n]\n\
+                    engine = \"bmc\"\n";
+
+        assert_eq!(
+            model_checker(toml),
+            Some(json!({ "targets": ["assert", "overflow"], "engine": "bmc" }))
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Why a compiler could not be found, fetched or run
+#[derive(Debug)]
+pub enum SolcError {
+    /// No cached compiler fits and there is no `solc` on `PATH` to fall back
+    /// to. `download_pending` is set when a download can still provide one.
+    NotFound { requirement: String, download_pending: bool },
+    /// The file's `pragma solidity` can't be parsed
+    InvalidPragma(String),
+    /// Every host failed to serve a file
+    DownloadFailed { url: String, reason: String },
+    /// A downloaded or cached file doesn't have the checksum `list.json` gives
+    ChecksumMismatch { path: PathBuf, expected: String, actual: String },
+    /// No solc builds are published for this OS/architecture
+    UnsupportedPlatform { os: String, arch: String },
+    /// The compiler exists but can't be executed
+    NotExecutable { path: PathBuf, source: io::Error },
+    /// solc ran longer than `compileTimeoutSecs` and was killed
+    TimedOut(Duration),
+    /// The server is shutting down and starts no new downloads
+    ShuttingDown,
+    Io(io::Error),
+}
+
+impl fmt::Display for SolcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolcError::NotFound { requirement, download_pending: true } => write!(
+                f,
+                "No solc matching {} is cached yet and there is no solc on PATH to use meanwhile",
+                requirement
+            ),
+            SolcError::NotFound { requirement, download_pending: false } => write!(
+                f,
+                "No solc matching {} can be downloaded and there is no solc on PATH to use instead",
+                requirement
+            ),
+            SolcError::InvalidPragma(reason) => {
+                write!(f, "No valid `pragma solidity` directive found: {}", reason)
+            }
+            SolcError::DownloadFailed { url, reason } => write!(f, "Downloading {} failed: {}", url, reason),
+            SolcError::ChecksumMismatch { path, expected, actual } => write!(
+                f,
+                "Checksum mismatch for {}: expected {}, got {}",
+                path.display(),
+                expected,
+                actual
+            ),
+            SolcError::UnsupportedPlatform { os, arch } => {
+                write!(f, "No solc builds are published for {} on {}", os, arch)
+            }
+            SolcError::NotExecutable { path, source } => write!(
+                f,
+                "solc binary {} is not executable ({}). Delete it from the cache so it is downloaded again, or fix the file permissions.",
+                path.display(),
+                source
+            ),
+            SolcError::TimedOut(timeout) => {
+                write!(f, "solc compilation timed out after {}s", timeout.as_secs())
+            }
+            SolcError::ShuttingDown => write!(f, "Server is shutting down"),
+            SolcError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SolcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SolcError::NotExecutable { source, .. } | SolcError::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SolcError {
+    fn from(e: io::Error) -> Self {
+        SolcError::Io(e)
+    }
+}
@@ -1,20 +1,369 @@
+use std::collections::HashSet;
+use std::hash::{BuildHasher, RandomState};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
+use once_cell::sync::Lazy;
 use sha2::{Digest, Sha256};
-use anyhow::{Result, anyhow};
-use reqwest::blocking::Client;
-
-pub fn download_to_file(url: &str, dest: &Path) -> Result<()> {
-    let client = Client::new();
-    let mut resp = client.get(url).send()?.error_for_status()?;
-    let mut file = File::create(dest)?;
-    resp.copy_to(&mut file)?;
-    Ok(())
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::solc::error::SolcError;
+use crate::solc::platform::get_platform_id;
+use crate::util::log::log_to_file;
+
+const OFFICIAL_BINARIES_URL: &str = "https://binaries.soliditylang.org";
+
+/// One client for every download so connections are pooled. Proxies come
+/// from the usual `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY` environment variables.
+static CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .connect_timeout(Duration::from_secs(15))
+        .timeout(Duration::from_secs(300))
+        .user_agent(concat!("emacs-solidity-server/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build HTTP client")
+});
+
+/// Number of downloads in flight, bounded by `maxConcurrentDownloads`
+static ACTIVE: Mutex<usize> = Mutex::new(0);
+static SLOT_FREED: Condvar = Condvar::new();
+
+/// Suffix of a download still being written; renamed into place once complete
+pub const PARTIAL_SUFFIX: &str = ".part";
+
+/// Wait before the first retry of a failed download, doubled on each further failure
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// How long to wait before retry number `attempt` (from 0) of a failed
+/// download: exponential backoff, randomized between half and all of it so
+/// server instances sharing a cache or mirror don't retry in lockstep
+pub fn retry_delay(attempt: u32) -> Duration {
+    let full = BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_DELAY);
+    let half = full / 2;
+    // a fresh `RandomState` is randomly keyed, which is all the randomness needed
+    let jitter = RandomState::new().hash_one(attempt) % (half.as_millis() as u64 + 1);
+    half + Duration::from_millis(jitter)
+}
+
+/// Files being written by a download right now
+static PARTIAL: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Whether a download of this server is writing `path` right now
+pub fn is_being_written(path: &Path) -> bool {
+    PARTIAL.lock().is_ok_and(|partials| partials.contains(path))
+}
+
+/// Marks a file as being written until dropped
+pub struct Writing(PathBuf);
+
+impl Writing {
+    pub fn start(path: &Path) -> Self {
+        if let Ok(mut partials) = PARTIAL.lock() {
+            partials.insert(path.to_path_buf());
+        }
+        Writing(path.to_path_buf())
+    }
+}
+
+impl Drop for Writing {
+    fn drop(&mut self) {
+        if let Ok(mut partials) = PARTIAL.lock() {
+            partials.remove(&self.0);
+        }
+    }
+}
+
+/// Set on shutdown; no new downloads start afterwards
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// A download slot, released on drop
+struct Slot;
+
+impl Slot {
+    fn acquire() -> Self {
+        let limit = config::get().max_concurrent_downloads.max(1);
+        // a poisoned count stays poisoned, so the download just goes ahead
+        // unbounded and `drop` leaves the count alone as well
+        let Ok(mut active) = ACTIVE.lock() else {
+            return Slot;
+        };
+        while *active >= limit {
+            active = match SLOT_FREED.wait(active) {
+                Ok(active) => active,
+                Err(_) => return Slot,
+            };
+        }
+        *active += 1;
+        Slot
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE.lock() {
+            *active -= 1;
+        }
+        // wakes both queued downloads and a draining shutdown
+        SLOT_FREED.notify_all();
+    }
+}
+
+fn download_failed(url: &str, e: impl std::fmt::Display) -> SolcError {
+    SolcError::DownloadFailed {
+        url: url.to_string(),
+        reason: e.to_string(),
+    }
+}
+
+pub fn download_to_file(url: &str, dest: &Path) -> Result<(), SolcError> {
+    if STOPPING.load(Ordering::SeqCst) {
+        return Err(SolcError::ShuttingDown);
+    }
+    let _slot = Slot::acquire();
+    let resp = CLIENT
+        .get(url)
+        .send()
+        .and_then(Response::error_for_status)
+        .map_err(|e| download_failed(url, e))?;
+    write_response(resp, dest)
+}
+
+/// Write a response body to `dest`
+fn write_response(mut resp: Response, dest: &Path) -> Result<(), SolcError> {
+    // written next to `dest` and renamed, so `dest` is never a truncated file
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    let _writing = Writing::start(&partial);
+    let url = resp.url().to_string();
+    let written = File::create(&partial)
+        .map_err(SolcError::from)
+        .and_then(|mut file| resp.copy_to(&mut file).map_err(|e| download_failed(&url, e)))
+        .and_then(|_| std::fs::rename(&partial, dest).map_err(SolcError::from));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+
+    written
+}
+
+/// Stop starting downloads and wait up to `timeout` for running ones to
+/// finish. Files still being written after that are deleted, so the cache
+/// never keeps a truncated binary across restarts.
+pub fn drain_downloads(timeout: Duration) {
+    STOPPING.store(true, Ordering::SeqCst);
+
+    let deadline = Instant::now() + timeout;
+    if let Ok(mut active) = ACTIVE.lock() {
+        while *active > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match SLOT_FREED.wait_timeout(active, deadline - now) {
+                Ok((guard, _)) => active = guard,
+                Err(_) => break,
+            }
+        }
+    }
+
+    let partials: Vec<PathBuf> = PARTIAL
+        .lock()
+        .map(|mut partials| partials.drain().collect())
+        .unwrap_or_default();
+    for path in partials {
+        match std::fs::remove_file(&path) {
+            Ok(()) => log_to_file(&format!(
+                "[solc-fetch] Removed unfinished download {}",
+                path.display()
+            )),
+            Err(e) => log_to_file(&format!(
+                "[solc-fetch] Could not remove unfinished download {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+}
+
+/// Whether `url` can serve as a binaries base URL (absolute http/https)
+fn is_valid_base_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https") && u.has_host())
+}
+
+/// The primary binaries host: `ESS_SOLC_BASE_URL` (or its older alias
+/// `ESS_SOLC_MIRROR`), then `binariesBaseUrl`, then the official host.
+/// Invalid URLs are logged and skipped.
+fn primary_host() -> String {
+    let env = ["ESS_SOLC_BASE_URL", "ESS_SOLC_MIRROR"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok().map(|url| (name, url)));
+    let configured = config::get()
+        .binaries_base_url
+        .map(|url| ("binariesBaseUrl", url));
+
+    for (origin, url) in env.chain(configured) {
+        let url = url.trim();
+        if url.is_empty() {
+            continue;
+        }
+        if is_valid_base_url(url) {
+            return url.to_string();
+        }
+        log_to_file(&format!("[solc-fetch] Ignoring invalid {} {:?}", origin, url));
+    }
+    OFFICIAL_BINARIES_URL.to_string()
+}
+
+/// Base URLs of solc binary hosts in the order they are tried: the primary
+/// host, then the valid `solcMirrors` fallbacks
+pub fn binary_hosts() -> Vec<String> {
+    let mut hosts = vec![primary_host()];
+    for mirror in config::get().solc_mirrors {
+        if !is_valid_base_url(&mirror) {
+            log_to_file(&format!("[solc-fetch] Ignoring invalid mirror {:?}", mirror));
+        } else if !hosts.contains(&mirror) {
+            hosts.push(mirror);
+        }
+    }
+    hosts
+        .into_iter()
+        .map(|url| url.trim_end_matches('/').to_string())
+        .collect()
+}
+
+/// Validators of the last `list.json` download, kept next to it
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListValidators {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+fn validators_path(list_path: &Path) -> PathBuf {
+    list_path.with_extension("json.etag")
+}
+
+/// Whether the release list at `list_path` was fetched (or confirmed
+/// unchanged) longer than `listTtlHours` ago
+pub fn list_is_stale(list_path: &Path) -> bool {
+    let ttl = Duration::from_secs(config::get().list_ttl_hours.saturating_mul(3600));
+    std::fs::metadata(list_path)
+        .and_then(|meta| meta.modified())
+        .is_ok_and(|fetched| fetched.elapsed().is_ok_and(|age| age > ttl))
+}
+
+/// Refresh the release list at `dest` with a conditional GET, so an unchanged
+/// list isn't downloaded again. Returns whether `dest` changed.
+pub fn fetch_list_json(dest: &Path) -> Result<bool, SolcError> {
+    if STOPPING.load(Ordering::SeqCst) {
+        return Err(SolcError::ShuttingDown);
+    }
+    let platform = get_platform_id()?;
+    let cached: ListValidators = std::fs::read(validators_path(dest))
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .filter(|_| dest.exists())
+        .unwrap_or_default();
+    let mut last_error = download_failed("list.json", "no solc binary hosts configured");
+
+    for host in binary_hosts() {
+        let url = format!("{}/{}/list.json", host, platform);
+        let mut request = CLIENT.get(&url);
+        // validators only mean something to the host that issued them
+        if cached.url == url {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let _slot = Slot::acquire();
+        let resp = match request.send().and_then(Response::error_for_status) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log_to_file(&format!("[solc-fetch] {} failed: {:?}", url, e));
+                last_error = download_failed(&url, e);
+                continue;
+            }
+        };
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            log_to_file("[solc-fetch] list.json unchanged since last download");
+            // the mtime is when the list was last known current
+            let touched = File::options()
+                .append(true)
+                .open(dest)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            if let Err(e) = touched {
+                log_to_file(&format!("[solc-fetch] Failed to touch {}: {}", dest.display(), e));
+            }
+            return Ok(false);
+        }
+
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = ListValidators {
+            url: url.clone(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        match write_response(resp, dest) {
+            Ok(()) => {
+                if let Ok(raw) = serde_json::to_vec(&validators) {
+                    let _ = std::fs::write(validators_path(dest), raw);
+                }
+                return Ok(true);
+            }
+            Err(e) => {
+                log_to_file(&format!("[solc-fetch] {} failed: {:?}", url, e));
+                last_error = e;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Download `<host>/<platform>/<path>` (e.g. `list.json` or a release's
+/// `path`) from the first host that serves it
+pub fn download_release_file(path: &str, dest: &Path) -> Result<(), SolcError> {
+    let platform = get_platform_id()?;
+    let mut last_error = download_failed(path, "no solc binary hosts configured");
+
+    for host in binary_hosts() {
+        let url = format!("{}/{}/{}", host, platform, path);
+        match download_to_file(&url, dest) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                log_to_file(&format!("[solc-fetch] {} failed: {:?}", url, e));
+                last_error = e;
+            }
+        }
+    }
+
+    Err(last_error)
 }
 
-pub fn verify_sha256(path: &Path, expected: &str) -> Result<()> {
+pub fn verify_sha256(path: &Path, expected: &str) -> Result<(), SolcError> {
     let mut file = File::open(path)?;
     let mut hasher = Sha256::new();
     let mut buffer = [0; 8192];
@@ -27,7 +376,11 @@ pub fn verify_sha256(path: &Path, expected: &str) -> Result<()> {
     }
     let result = format!("0x{:x}", hasher.finalize());
     if result != expected {
-        return Err(anyhow!("Checksum mismatch for {:?}: expected {}, got {}", path, expected, result));
+        return Err(SolcError::ChecksumMismatch {
+            path: path.to_path_buf(),
+            expected: expected.to_string(),
+            actual: result,
+        });
     }
     Ok(())
 }
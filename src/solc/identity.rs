@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use semver::Version;
+use which::which;
+
+use crate::util::log::log_to_file;
+
+/// Binary → its `long_version`, so each compiler is only asked once
+static LONG_VERSIONS: Lazy<Mutex<HashMap<PathBuf, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A long version, e.g. `0.8.25+commit.b61c2a91`
+static LONG_VERSION_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\d+\.\d+\.\d+(?:-[0-9A-Za-z.]+)?\+commit\.[0-9a-f]+").unwrap());
+
+/// `0.8.25+commit.b61c2a91` out of `solc --version`'s
+/// `Version: 0.8.25+commit.b61c2a91.Linux.g++`, matching list.json's `longVersion`
+fn parse_long_version(output: &str) -> Option<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Version:"))
+        .find_map(|rest| LONG_VERSION_RE.find(rest))
+        .map(|m| m.as_str().to_string())
+}
+
+/// Exact build of the compiler at `binary`, commit hash included. Two
+/// binaries with the same version but different commits can behave differently.
+pub fn long_version(binary: &Path) -> Option<String> {
+    if let Some(known) = LONG_VERSIONS.lock().ok()?.get(binary) {
+        return Some(known.clone());
+    }
+
+    let output = match Command::new(binary).arg("--version").output() {
+        Ok(output) => output,
+        Err(e) => {
+            log_to_file(&format!("[solc] Could not run {} --version: {}", binary.display(), e));
+            return None;
+        }
+    };
+    let long_version = parse_long_version(&String::from_utf8_lossy(&output.stdout))?;
+
+    if let Ok(mut known) = LONG_VERSIONS.lock() {
+        known.insert(binary.to_path_buf(), long_version.clone());
+    }
+    Some(long_version)
+}
+
+/// The `solc` on `PATH`, looked up once: `Some(None)` if there is none
+static SYSTEM_SOLC: Lazy<Mutex<Option<Option<PathBuf>>>> = Lazy::new(|| Mutex::new(None));
+
+/// The `solc` on `PATH`, resolved on first use and reused until
+/// `refresh_system_solc`
+pub fn system_solc() -> Option<PathBuf> {
+    let mut slot = SYSTEM_SOLC.lock().ok()?;
+    slot.get_or_insert_with(|| {
+        let found = which("solc").ok();
+        log_to_file(&format!("[solc] System solc: {:?}", found));
+        found
+    })
+    .clone()
+}
+
+/// Version of the system solc, e.g. to check it against a pragma
+pub fn system_solc_version() -> Option<Version> {
+    let long_version = long_version(&system_solc()?)?;
+    Version::parse(long_version.split('+').next()?).ok()
+}
+
+/// Forget the system solc and its version, e.g. after `solc-select use`
+/// swapped it, so the next lookup scans `PATH` again
+pub fn refresh_system_solc() {
+    let previous = SYSTEM_SOLC.lock().ok().and_then(|mut slot| slot.take()).flatten();
+    if let Some(previous) = previous
+        && let Ok(mut known) = LONG_VERSIONS.lock()
+    {
+        known.remove(&previous);
+    }
+}
@@ -1,14 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError, RwLock, RwLockReadGuard};
 use std::thread;
-use std::time::Duration;
 
-use anyhow::{Result, Context};
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use semver::{Version, VersionReq};
 
+use crate::solc::error::SolcError;
 use crate::solc::versions::{SolcList, SolcRelease};
-use crate::solc::fetch::{download_to_file, verify_sha256};
-use crate::solc::platform::get_platform_id;
+use crate::solc::fetch::{
+    download_release_file, is_being_written, retry_delay, verify_sha256, Writing, PARTIAL_SUFFIX,
+};
+use crate::solc::usage;
+use crate::util::cache::cache_root;
 use crate::util::log::log_to_file;
 
 #[cfg(unix)]
@@ -16,32 +23,133 @@ use std::os::unix::fs::PermissionsExt;
 
 pub struct SolcManager {
     pub cache_dir: PathBuf,
-    pub list: SolcList,
+    /// Where exact versions are downloaded, [`exact_cache_dir`] by default
+    pub exact_cache_dir: PathBuf,
+    /// Replaced when `list.json` is refreshed during the session
+    list: RwLock<SolcList>,
 }
 
 impl SolcManager {
     pub fn new(cache_dir: PathBuf, list: SolcList) -> Self {
         fs::create_dir_all(&cache_dir).ok(); // ensure exists
-        Self { cache_dir, list }
+        Self {
+            cache_dir,
+            exact_cache_dir: exact_cache_dir(),
+            list: RwLock::new(list),
+        }
+    }
+
+    /// The release list, as last loaded or refreshed
+    pub fn list(&self) -> RwLockReadGuard<'_, SolcList> {
+        self.list.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Swap in a refreshed release list
+    pub fn set_list(&self, list: SolcList) {
+        *self.list.write().unwrap_or_else(PoisonError::into_inner) = list;
+    }
+
+    /// Versions of every downloaded compiler, from both the minor-series and
+    /// the exact cache, oldest first and each listed once
+    pub fn list_cached_versions(&self) -> Vec<Version> {
+        let mut versions: Vec<Version> = [&self.cache_dir, &self.exact_cache_dir]
+            .into_iter()
+            .flat_map(|dir| scan_cache_dir(dir))
+            .map(|(version, _)| version)
+            .collect();
+        versions.sort();
+        versions.dedup();
+        versions
     }
 
-    pub fn ensure_latest_versions(&self) -> Result<()> {
-        let latest_versions = self.list.latest_per_minor();
+    /// [`Self::list_cached_versions`], each with whether it satisfies `req`
+    pub fn cached_versions_matching(&self, req: &VersionReq) -> Vec<(Version, bool)> {
+        self.list_cached_versions()
+            .into_iter()
+            .map(|version| {
+                let matches = req.matches(&version);
+                (version, matches)
+            })
+            .collect()
+    }
 
-        let releases: Vec<_> = latest_versions.values().cloned().collect();
+    /// Download the latest patch of every minor series, calling
+    /// `on_progress(done, total, version)` after each release is cached.
+    /// The sync stops early, between releases or between retries of one,
+    /// once `is_cancelled` returns `true`.
+    pub fn ensure_latest_versions(
+        &self,
+        is_cancelled: &dyn Fn() -> bool,
+        on_progress: impl Fn(usize, usize, &str),
+    ) -> Result<()> {
+        // copied, so a list refresh isn't held up while they download
+        let releases: Vec<SolcRelease> = self.list().latest_per_minor().into_values().cloned().collect();
 
-        for release in &releases {
-            self.ensure_release_cached(release)?;
+        for (i, release) in releases.iter().enumerate() {
+            if !self.ensure_release_cached(release, is_cancelled)? {
+                log_to_file("[solc-sync] Sync cancelled");
+                return Ok(());
+            }
+            on_progress(i + 1, releases.len(), &release.version);
         }
 
-        self.clean_old_versions(&latest_versions)?;
+        self.clean_old_versions(&releases)?;
         Ok(())
     }
 
+    /// Remove what an interrupted session may have left in either cache:
+    /// unfinished downloads, and binaries whose checksum doesn't match their
+    /// `list.json` entry. Binaries of versions not in the list are kept.
+    pub fn sweep_cache(&self) {
+        let list = self.list();
+        for dir in [self.cache_dir.clone(), self.exact_cache_dir.clone()] {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                let reason = if name.ends_with(PARTIAL_SUFFIX) || name.ends_with(".tmp") {
+                    // still being downloaded, e.g. by a background list refresh
+                    if is_being_written(&path) {
+                        continue;
+                    }
+                    "unfinished download".to_string()
+                } else if let Some(version) = name.strip_prefix("solc-") {
+                    let version = version.trim_end_matches(".exe");
+                    let Some(release) = list.builds.iter().find(|r| r.version == version) else {
+                        continue;
+                    };
+                    match verify_sha256(&path, &release.sha256) {
+                        Ok(()) => {
+                            record_cached(&release.sha256, &path);
+                            continue;
+                        }
+                        Err(e) => e.to_string(),
+                    }
+                } else {
+                    continue;
+                };
+
+                match fs::remove_file(&path) {
+                    Ok(()) => log_to_file(&format!(
+                        "[solc-prune] Removed {} ({})",
+                        path.display(),
+                        reason
+                    )),
+                    Err(e) => log_to_file(&format!(
+                        "[solc-prune] Failed to remove {}: {}",
+                        path.display(),
+                        e
+                    )),
+                }
+            }
+        }
+    }
+
     pub fn clean_unused_exact_versions(&self) -> Result<()> {
-        let exact_cache_dir = dirs::cache_dir()
-            .unwrap_or_else(|| PathBuf::from(".cache"))
-            .join("emacs-solidity-server/solc-exact");
+        let exact_cache_dir = &self.exact_cache_dir;
 
         if !exact_cache_dir.exists() {
             return Ok(()); // nothing to clean
@@ -50,7 +158,7 @@ impl SolcManager {
         let now = std::time::SystemTime::now();
         let retention_period = std::time::Duration::from_secs(30 * 24 * 60 * 60); // 30 days
 
-        for entry in fs::read_dir(&exact_cache_dir)? {
+        for entry in fs::read_dir(exact_cache_dir)? {
             let entry = entry?;
             let path = entry.path();
 
@@ -73,6 +181,68 @@ impl SolcManager {
         Ok(())
     }
 
+    /// Evict the least recently used compilers of both the minor-series and
+    /// exact caches until together they take at most `quota_bytes`.
+    /// Binaries in `keep` (resolved for open files) are never evicted.
+    pub fn enforce_quota(&self, quota_bytes: u64, keep: &HashSet<PathBuf>) -> Result<()> {
+        let mut binaries = Vec::new();
+        for dir in [self.cache_dir.clone(), self.exact_cache_dir.clone()] {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with("solc-") {
+                    continue; // e.g. list.json
+                }
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_file() {
+                    binaries.push((entry.path(), metadata.len()));
+                }
+            }
+        }
+
+        let mut total: u64 = binaries.iter().map(|(_, size)| size).sum();
+        if total <= quota_bytes {
+            return Ok(());
+        }
+
+        binaries.sort_by_key(|(path, _)| usage::last_used(path));
+
+        for (path, size) in binaries {
+            if total <= quota_bytes {
+                break;
+            }
+            if keep.contains(&path) {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    total -= size;
+                    log_to_file(&format!(
+                        "[solc-prune] Evicted {} to stay under the cache quota",
+                        path.display()
+                    ));
+                }
+                Err(e) => log_to_file(&format!(
+                    "[solc-prune] Failed to evict {}: {}",
+                    path.display(),
+                    e
+                )),
+            }
+        }
+
+        if total > quota_bytes {
+            log_to_file(&format!(
+                "[solc-prune] Cache still {} bytes over quota; remaining compilers are in use",
+                total - quota_bytes
+            ));
+        }
+        Ok(())
+    }
+
     pub fn get_binary_path(&self, version: &str) -> Option<PathBuf> {
         let path = self.cache_dir.join(format!("solc-{}", version));
         if path.exists() {
@@ -82,7 +252,13 @@ impl SolcManager {
         }
     }
 
-    pub fn ensure_release_cached(&self, release: &SolcRelease) -> Result<()> {
+    /// Download `release` unless it is cached, retrying until it verifies.
+    /// Returns `false` when `is_cancelled` stopped it first.
+    pub fn ensure_release_cached(
+        &self,
+        release: &SolcRelease,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<bool> {
         let mut filename = format!("solc-{}", release.version);
         if cfg!(windows) {
             filename.push_str(".exe");
@@ -91,30 +267,31 @@ impl SolcManager {
         let dest_path = self.cache_dir.join(&filename);
 
         if dest_path.exists() {
-            verify_sha256(&dest_path, &release.sha256)
-                .with_context(|| format!("Verifying {:?}", dest_path))?;
-            return Ok(()); // already downloaded and verified
+            return Ok(true); // already downloaded; verified by `sweep_cache` at startup
+        }
+        if link_cached_copy(&release.sha256, &dest_path) {
+            return Ok(true);
         }
 
-        let platform = get_platform_id();
-        let download_url = format!(
-            "https://binaries.soliditylang.org/{}/{}",
-            platform, release.path
-        );
-
-        log_to_file(&format!("Downloading {} → {}", release.version, download_url));
+        log_to_file(&format!("Downloading {} → {}", release.version, release.path));
 
+        let mut attempt = 0;
         loop {
-            match download_to_file(&download_url, &dest_path) {
+            if is_cancelled() {
+                log_to_file(&format!("[solc-sync] Download of {} cancelled", filename));
+                return Ok(false);
+            }
+            match download_release_file(&release.path, &dest_path) {
                 Ok(_) => {
                     match verify_sha256(&dest_path, &release.sha256) {
                         Ok(_) => {
                             make_executable(&dest_path)?;
+                            record_cached(&release.sha256, &dest_path);
                             log_to_file(&format!(
                                 "[solc-sync] Downloaded and verified {}",
                                 filename
                             ));
-                            return Ok(());
+                            return Ok(true);
                         }
                         Err(e) => {
                             log_to_file(&format!(
@@ -125,6 +302,7 @@ impl SolcManager {
                         }
                     }
                 }
+                Err(SolcError::ShuttingDown) => return Ok(false),
                 Err(e) => {
                     log_to_file(&format!(
                         "[solc-sync] Failed to download {}: {:?}",
@@ -133,17 +311,18 @@ impl SolcManager {
                 }
             }
 
-            thread::sleep(Duration::from_secs(5));
+            thread::sleep(retry_delay(attempt));
+            attempt += 1;
         }
     }
 
-    fn clean_old_versions(&self, latest: &HashMap<String, &SolcRelease>) -> Result<()> {
+    fn clean_old_versions(&self, latest: &[SolcRelease]) -> Result<()> {
         for entry in fs::read_dir(&self.cache_dir)? {
             let entry = entry?;
             let name = entry.file_name().into_string().unwrap_or_default();
 
             if let Some(ver) = name.strip_prefix("solc-") {
-                let keep = latest.values().any(|r| r.version == ver);
+                let keep = latest.iter().any(|r| r.version == ver);
                 if !keep {
                     let _ = fs::remove_file(entry.path());
                     log_to_file(&format!(
@@ -157,12 +336,107 @@ impl SolcManager {
     }
 }
 
+/// sha256 → a cached binary with that checksum, across both caches
+static CACHED_BY_SHA256: Lazy<Mutex<HashMap<String, PathBuf>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Remember that `path` is a verified binary with checksum `sha256`
+pub fn record_cached(sha256: &str, path: &Path) {
+    if let Ok(mut index) = CACHED_BY_SHA256.lock() {
+        index.insert(sha256.to_lowercase(), path.to_path_buf());
+    }
+}
+
+/// Some `list.json` entries share a binary. If one with checksum `sha256`
+/// is already cached, hardlink (or copy) it to `dest` instead of downloading
+/// it again. Returns whether `dest` now holds the binary.
+pub fn link_cached_copy(sha256: &str, dest: &Path) -> bool {
+    let Some(source) = CACHED_BY_SHA256
+        .lock()
+        .ok()
+        .and_then(|index| index.get(&sha256.to_lowercase()).cloned())
+    else {
+        return false;
+    };
+    if source == dest || verify_sha256(&source, sha256).is_err() {
+        return false; // evicted or replaced since it was recorded
+    }
+
+    let partial = PathBuf::from(format!("{}{}", dest.display(), PARTIAL_SUFFIX));
+    let _writing = Writing::start(&partial);
+    let _ = fs::remove_file(&partial);
+    let linked = fs::hard_link(&source, &partial)
+        .or_else(|_| fs::copy(&source, &partial).map(|_| ()))
+        .and_then(|()| fs::rename(&partial, dest))
+        .and_then(|()| make_executable(dest));
+    match linked {
+        Ok(()) => {
+            log_to_file(&format!(
+                "[solc-sync] Reused {} for {} (same sha256)",
+                source.display(),
+                dest.display()
+            ));
+            record_cached(sha256, dest);
+            true
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            log_to_file(&format!(
+                "[solc-sync] Could not reuse {} for {}: {}",
+                source.display(),
+                dest.display(),
+                e
+            ));
+            false
+        }
+    }
+}
+
+/// A cached compiler's file name, capturing its version
+static CACHED_BINARY_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^solc-(\d+\.\d+\.\d+)(?:\.exe)?$").unwrap());
+
+/// Compiler binaries in a cache directory, by version: `solc-0.8.25`, or
+/// `solc-0.8.25.exe` on Windows. Other files are ignored.
+pub fn scan_cache_dir(dir: &Path) -> Vec<(Version, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let fname = entry.file_name().to_string_lossy().to_string();
+            let cap = CACHED_BINARY_RE.captures(&fname)?;
+            Some((Version::parse(&cap[1]).ok()?, entry.path()))
+        })
+        .collect()
+}
+
+/// Latest release of each minor series, kept up to date by the background sync
+pub fn solc_cache_dir() -> PathBuf {
+    cache_root().join("solc")
+}
+
+/// Exact versions pinned by pragmas or overrides, downloaded on demand
+pub fn exact_cache_dir() -> PathBuf {
+    cache_root().join("solc-exact")
+}
+
 pub fn make_executable(path: &Path) -> std::io::Result<()> {
     #[cfg(unix)]
     {
         let mut perms = std::fs::metadata(path)?.permissions();
         perms.set_mode(0o755);
         std::fs::set_permissions(path, perms)?;
+
+        // noexec or read-only mounts can silently drop the bit
+        let mode = std::fs::metadata(path)?.permissions().mode();
+        if mode & 0o111 == 0 {
+            log_to_file(&format!(
+                "[solc] Execute permission did not stick on {} (mode {:o})",
+                path.display(),
+                mode
+            ));
+        }
     }
 
     #[cfg(windows)]
@@ -173,3 +447,103 @@ pub fn make_executable(path: &Path) -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solc::switcher::{unavailable_version_message, Pragma};
+
+    fn manager_with(series: &[&str], exact: &[&str]) -> (tempfile::TempDir, SolcManager) {
+        let root = tempfile::tempdir().unwrap();
+        let manager = SolcManager {
+            cache_dir: root.path().join("solc"),
+            exact_cache_dir: root.path().join("solc-exact"),
+            list: RwLock::new(SolcList {
+                builds: vec![],
+                releases: HashMap::new(),
+                latest_release: None,
+            }),
+        };
+        for (dir, names) in [(&manager.cache_dir, series), (&manager.exact_cache_dir, exact)] {
+            fs::create_dir_all(dir).unwrap();
+            for name in names {
+                fs::write(dir.join(name), "").unwrap();
+            }
+        }
+        (root, manager)
+    }
+
+    #[test]
+    fn refreshed_list_is_seen_by_later_lookups() {
+        let (_root, manager) = manager_with(&[], &[]);
+        let pragma = Pragma::Exact(Version::new(0, 8, 31));
+        assert!(unavailable_version_message(&pragma, &manager.list()).is_some());
+
+        let release = SolcRelease {
+            path: "solc-linux-amd64-v0.8.31+commit.fd3a2265".into(),
+            version: "0.8.31".into(),
+            build: "commit.fd3a2265".into(),
+            long_version: "0.8.31+commit.fd3a2265".into(),
+            keccak256: String::new(),
+            sha256: String::new(),
+            urls: vec![],
+        };
+        manager.set_list(SolcList {
+            builds: vec![release],
+            releases: HashMap::new(),
+            latest_release: Some("0.8.31".into()),
+        });
+
+        assert!(unavailable_version_message(&pragma, &manager.list()).is_none());
+        assert_eq!(manager.list().latest_release.as_deref(), Some("0.8.31"));
+    }
+
+    #[test]
+    fn sweep_keeps_files_still_being_downloaded() {
+        let (_root, manager) = manager_with(&["solc-0.8.24.part", "solc-0.8.25.part"], &[]);
+        let active = manager.cache_dir.join("solc-0.8.25.part");
+        let writing = Writing::start(&active);
+
+        manager.sweep_cache();
+        assert!(!manager.cache_dir.join("solc-0.8.24.part").exists());
+        assert!(active.exists());
+
+        drop(writing);
+        manager.sweep_cache();
+        assert!(!active.exists());
+    }
+
+    #[test]
+    fn lists_both_caches_sorted_and_deduplicated() {
+        let (_root, manager) = manager_with(
+            &["solc-0.8.25", "solc-0.7.6", "list.json", "solc-0.8.24.part"],
+            &["solc-0.8.19.exe", "solc-0.8.25", "solc-nightly"],
+        );
+
+        assert_eq!(
+            manager.list_cached_versions(),
+            [Version::new(0, 7, 6), Version::new(0, 8, 19), Version::new(0, 8, 25)]
+        );
+    }
+
+    #[test]
+    fn flags_versions_satisfying_a_requirement() {
+        let (_root, manager) = manager_with(&["solc-0.7.6", "solc-0.8.25"], &["solc-0.8.19"]);
+
+        let req = VersionReq::parse(">=0.8.0, <0.8.20").unwrap();
+        assert_eq!(
+            manager.cached_versions_matching(&req),
+            [
+                (Version::new(0, 7, 6), false),
+                (Version::new(0, 8, 19), true),
+                (Version::new(0, 8, 25), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_cache_directories_list_nothing() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(scan_cache_dir(&root.path().join("absent")).is_empty());
+    }
+}
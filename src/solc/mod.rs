@@ -4,3 +4,6 @@ pub mod platform;
 pub mod versions;
 pub mod switcher;
 pub mod global;
+pub mod usage;
+pub mod identity;
+pub mod error;
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::solc::error::SolcError;
+
 /// Represents the supported operating systems for solc binaries.
 #[derive(Debug, PartialEq, Eq)]
 pub enum OS {
@@ -79,8 +81,11 @@ impl Platform {
 }
 
 /// Helper to return current platform ID string like `linux-amd64`
-pub fn get_platform_id() -> String {
+pub fn get_platform_id() -> Result<String, SolcError> {
     Platform::detect()
-        .expect("Unsupported platform")
-        .id()
+        .map(|platform| platform.id())
+        .ok_or_else(|| SolcError::UnsupportedPlatform {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        })
 }
@@ -1,96 +1,259 @@
+use crate::config;
+use crate::lsp::handler::SOLC_MANAGER;
+use crate::lsp::progress::Progress;
+use crate::solc::error::SolcError;
+use crate::solc::identity::system_solc;
 use crate::solc::manager::SolcManager;
-use crate::solc::manager::make_executable;
+use crate::solc::manager::{
+    exact_cache_dir, link_cached_copy, make_executable, record_cached, scan_cache_dir,
+    solc_cache_dir,
+};
 use crate::util::log::log_to_file;
+use crate::util::text::strip_bom;
 
 use anyhow::{Context, Result};
-use regex::Regex;
+use once_cell::sync::Lazy;
 use semver::{Version, VersionReq};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::path::{Path, PathBuf};
-use which::which;
-use std::{thread, time::Duration};
+use std::thread;
 
-use crate::solc::fetch::{download_to_file, verify_sha256};
-use crate::solc::platform::get_platform_id;
-use crate::solc::versions::SolcList;
+use crate::solc::fetch::{
+    download_release_file, fetch_list_json, list_is_stale, retry_delay, verify_sha256,
+};
+use crate::solc::versions::{SolcList, SolcRelease};
 
+#[derive(Debug, Clone)]
 pub enum Pragma {
     Exact(Version),
     Range(VersionReq),
+    /// `^0.7.0 || ^0.8.0`: any of the alternatives will do
+    AnyOf(Vec<VersionReq>),
 }
 
-pub fn extract_pragma(source_path: &Path) -> Result<Pragma> {
-    let content = fs::read_to_string(source_path)
-        .with_context(|| format!("Reading source file: {:?}", source_path))?;
+impl Pragma {
+    pub fn matches(&self, version: &Version) -> bool {
+        match self {
+            Pragma::Exact(exact) => exact == version,
+            Pragma::Range(req) => req.matches(version),
+            Pragma::AnyOf(alternatives) => alternatives.iter().any(|req| req.matches(version)),
+        }
+    }
+}
 
-    for line in content.lines() {
-        if let Some(idx) = line.find("pragma solidity") {
-            let rest = line[idx + "pragma solidity".len()..]
-                .trim()
-                .trim_end_matches(';');
-
-            // If '=' is present anywhere, treat as exact — take the first version
-            if rest.contains('=') {
-                let first = rest
-                    .split_whitespace()
-                    .next()
-                    .and_then(|token| {
-                        token.trim_start_matches(|c: char| !c.is_ascii_digit()).parse().ok()
-                    });
-
-                if let Some(v) = first {
-                    return Ok(Pragma::Exact(v));
-                } else {
-                    return Err(anyhow::anyhow!("Could not parse exact version from: '{}'", rest));
-                }
+impl fmt::Display for Pragma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pragma::Exact(version) => write!(f, "={}", version),
+            Pragma::Range(req) => write!(f, "{}", req),
+            Pragma::AnyOf(alternatives) => {
+                let alternatives: Vec<String> = alternatives.iter().map(VersionReq::to_string).collect();
+                write!(f, "{}", alternatives.join(" || "))
+            }
+        }
+    }
+}
+
+/// Version clause of the first `pragma solidity` directive, e.g. `^0.8.0`.
+/// Other directives (`pragma abicoder v2;`, `pragma experimental ...;`) and
+/// commented-out lines are skipped. Each statement on a line is considered
+/// on its own, so `pragma abicoder v2; pragma solidity ^0.8.0;` works, and a
+/// directive missing its `;` is read up to the end of the line.
+pub fn find_solidity_pragma(content: &str) -> Option<&str> {
+    strip_bom(content)
+        .lines()
+        .filter_map(|line| line.split("//").next())
+        .flat_map(|code| code.split(';'))
+        .find_map(|statement| {
+            let rest = statement.trim_start().strip_prefix("pragma")?;
+            if !rest.starts_with(char::is_whitespace) {
+                return None;
             }
+            let rest = rest.trim_start().strip_prefix("solidity")?;
+            if rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+                return None; // e.g. `pragma solidityx`
+            }
+            Some(rest)
+        })
+}
 
-            // If the line starts with a version number (no operator), treat it as exact
-            if rest.chars().next().map_or(false, |c| c.is_ascii_digit()) {
-                let version = Version::parse(rest)
-                    .with_context(|| format!("Parsing version as exact: '{}'", rest))?;
-                return Ok(Pragma::Exact(version));
+/// Split a pragma version clause into comparators, e.g. `>=0.8.0<0.9.0` into
+/// `[">=0.8.0", "<0.9.0"]`. Operators may be glued to the previous version
+/// or separated from their own version by spaces (`>= 0.8.0`).
+pub fn tokenize_pragma(clause: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+    // whether `current` already holds (part of) a version
+    let mut has_version = false;
+
+    for c in clause.trim().trim_end_matches(';').chars() {
+        if c.is_whitespace() {
+            if has_version {
+                tokens.push(std::mem::take(&mut current));
+                has_version = false;
             }
+            continue;
+        }
 
-            // Otherwise, parse as a range
-            return Ok(Pragma::Range(VersionReq::parse(rest)?));
+        let is_operator = matches!(c, '^' | '~' | '>' | '<' | '=' | '|');
+        if is_operator && has_version {
+            tokens.push(std::mem::take(&mut current));
+            has_version = false;
         }
+        current.push(c);
+        has_version |= !is_operator;
+    }
+    if !current.is_empty() {
+        tokens.push(current);
     }
 
-    Err(anyhow::anyhow!("No valid pragma found"))
+    tokens
 }
 
-
-/// Extracts `pragma solidity ^0.8.0` or similar and parses it.
-pub fn extract_pragma_version(source_path: &Path) -> Result<VersionReq> {
+pub fn extract_pragma(source_path: &Path) -> Result<Pragma> {
     let content = fs::read_to_string(source_path)
         .with_context(|| format!("Reading source file: {:?}", source_path))?;
+    parse_pragma(&content)
+}
 
-    for line in content.lines() {
-        if let Some(idx) = line.find("pragma solidity") {
-            let rest = &line[idx + "pragma solidity".len()..];
-            let version_str = rest
-                .trim()
-                .trim_end_matches(';')
-                .trim_matches(|c: char| c == '^' || c == '=' || c == '>' || c == '<' || c == '~')
-                .split_whitespace()
-                .next()
-                .unwrap_or("");
-
-            if Version::parse(version_str).is_ok() {
-                let req_str = rest.trim().trim_end_matches(';');
-                return VersionReq::parse(req_str).context("Parsing version requirement");
+/// Parse the first `pragma solidity` directive of a source text
+///
+/// ```
+/// use emacs_solidity_server::{parse_pragma, Pragma};
+///
+/// let Ok(Pragma::Range(req)) = parse_pragma("pragma solidity >=0.8.0 <0.9.0;") else {
+///     panic!("expected a range");
+/// };
+/// assert!(req.matches(&semver::Version::new(0, 8, 25)));
+/// ```
+pub fn parse_pragma(content: &str) -> Result<Pragma> {
+    let clause = find_solidity_pragma(content)
+        .ok_or_else(|| anyhow::anyhow!("No valid pragma found"))?;
+    if !clause.contains("||") {
+        return parse_version_clause(clause);
+    }
+
+    let alternatives = clause
+        .split("||")
+        .map(|alternative| {
+            Ok(match parse_version_clause(alternative)? {
+                Pragma::Exact(version) => exact_requirement(&version),
+                Pragma::Range(req) => req,
+                Pragma::AnyOf(_) => unreachable!("alternatives contain no `||`"),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Pragma::AnyOf(alternatives))
+}
+
+/// One `||`-free version clause: a single version is exact, anything else a range
+fn parse_version_clause(clause: &str) -> Result<Pragma> {
+    let tokens = tokenize_pragma(clause);
+
+    // A single bare or `=` version is exact; solc reads a bare version as `=`
+    if let [only] = tokens.as_slice() {
+        let bare = only.strip_prefix('=').unwrap_or(only);
+        if bare.starts_with(|c: char| c.is_ascii_digit()) {
+            let version = Version::parse(bare)
+                .with_context(|| format!("Parsing version as exact: '{}'", bare))?;
+            return Ok(Pragma::Exact(version));
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err(anyhow::anyhow!("Empty pragma solidity directive"));
+    }
+
+    let comparators: Vec<String> = tokens
+        .iter()
+        .map(|t| {
+            if t.starts_with(|c: char| c.is_ascii_digit()) {
+                format!("={}", t)
+            } else {
+                t.clone()
             }
+        })
+        .collect();
+    let req = comparators.join(", ");
+    let req = VersionReq::parse(&req).with_context(|| format!("Parsing version range: '{}'", req))?;
+    Ok(Pragma::Range(req))
+}
+
+/// Up to `count` released versions closest to `version`: same minor series
+/// first, then neighbouring ones, nearest patch first
+pub fn nearest_versions(list: &SolcList, version: &Version, count: usize) -> Vec<String> {
+    let distance = |v: &Version| {
+        (
+            v.major.abs_diff(version.major),
+            v.minor.abs_diff(version.minor),
+            v.patch.abs_diff(version.patch),
+        )
+    };
+    let mut versions: Vec<Version> = list
+        .builds
+        .iter()
+        .filter_map(|r| Version::parse(&r.version).ok())
+        .filter(|v| v.pre.is_empty())
+        .collect();
+    versions.sort_by_key(|v| (distance(v), std::cmp::Reverse(v.clone())));
+    versions.dedup();
+    versions.into_iter().take(count).map(|v| v.to_string()).collect()
+}
+
+/// Why no released compiler can satisfy `pragma`, e.g. a typo'd `0.8.256`;
+/// `None` when some release in `list` does
+pub fn unavailable_version_message(pragma: &Pragma, list: &SolcList) -> Option<String> {
+    let released = || list.builds.iter().filter_map(|r| Version::parse(&r.version).ok());
+    let (problem, near) = match pragma {
+        Pragma::Exact(version) => {
+            if released().any(|v| v == *version) {
+                return None;
+            }
+            (format!("solc version {} does not exist", version), version.clone())
         }
+        Pragma::Range(_) | Pragma::AnyOf(_) => {
+            if released().any(|v| pragma.matches(&v)) {
+                return None;
+            }
+            // suggest around the first version the range names
+            let named = match pragma {
+                Pragma::AnyOf(alternatives) => alternatives.first()?.comparators.first()?,
+                Pragma::Range(req) => req.comparators.first()?,
+                Pragma::Exact(_) => return None,
+            };
+            let near = Version::new(named.major, named.minor.unwrap_or(0), named.patch.unwrap_or(0));
+            (format!("No released solc version satisfies {}", pragma), near)
+        }
+    };
+
+    let suggestions = nearest_versions(list, &near, 3);
+    if suggestions.is_empty() {
+        return Some(problem);
     }
+    Some(format!("{}. Nearest releases: {}", problem, suggestions.join(", ")))
+}
 
-    Err(anyhow::anyhow!("No pragma solidity directive found"))
+/// Extracts `pragma solidity ^0.8.0` or similar as a single requirement.
+/// A pragma with `||` alternatives has none; use [`extract_pragma`] for those.
+pub fn extract_pragma_version(source_path: &Path) -> Result<VersionReq> {
+    match extract_pragma(source_path)? {
+        Pragma::Exact(version) => Ok(exact_requirement(&version)),
+        Pragma::Range(req) => Ok(req),
+        Pragma::AnyOf(alternatives) => Err(anyhow::anyhow!(
+            "Pragma has {} alternatives, not a single requirement",
+            alternatives.len()
+        )),
+    }
 }
 
 /// Finds the best matching version from SolcList that has been downloaded
 pub fn match_cached_solc_version(manager: &SolcManager, req: &VersionReq) -> Option<String> {
     manager
-        .list
+        .list()
         .builds
         .iter()
         .filter_map(|release| {
@@ -101,143 +264,586 @@ pub fn match_cached_solc_version(manager: &SolcManager, req: &VersionReq) -> Opt
         .map(|(_, v)| v.to_string())
 }
 
-/// Resolve solc binary path for given source based on downloaded binaries
-/// Falls back to system solc if no match found
-pub fn get_solc_binary_from_cache(
-    source_path: &Path,
-    _project_root: &Path,
-) -> std::io::Result<PathBuf> {
-    let pragma = extract_pragma(source_path)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
-    match pragma {
-        Pragma::Exact(version) => {
-            let exact_cache_dir = dirs::cache_dir()
-                .unwrap_or_else(|| PathBuf::from(".cache"))
-                .join("emacs-solidity-server/solc-exact");
+fn exact_requirement(version: &Version) -> VersionReq {
+    VersionReq::parse(&format!("={}", version)).expect("a version is a valid requirement")
+}
 
-            let mut filename = format!("solc-{}", version);
-            if cfg!(windows) {
-                filename.push_str(".exe");
-            }
+/// Downloads in flight, by destination directory and release version
+static IN_FLIGHT: Lazy<Mutex<HashSet<(PathBuf, String)>>> = Lazy::new(|| Mutex::new(HashSet::new()));
 
-            let binary_path = exact_cache_dir.join(&filename);
+/// A release being downloaded into a directory; the claim is released on drop
+struct InFlight((PathBuf, String));
 
-            if binary_path.exists() {
-                log_to_file(&format!("[solc-switch] Using exact cached solc: {}", version));
-                return Ok(binary_path);
-            }
+impl InFlight {
+    /// `None` when the release is already being downloaded into `cache_dir`
+    fn claim(cache_dir: &Path, version: &str) -> Option<Self> {
+        let key = (cache_dir.to_path_buf(), version.to_string());
+        if let Ok(mut in_flight) = IN_FLIGHT.lock()
+            && !in_flight.insert(key.clone())
+        {
+            return None;
+        }
+        Some(InFlight(key))
+    }
+}
 
-            // Spawn thread to download
-            let version_clone = version.clone();
-            thread::spawn(move || {
-                std::fs::create_dir_all(&exact_cache_dir).ok();
-
-                let platform = get_platform_id();
-                let list_url = format!(
-                    "https://binaries.soliditylang.org/{}/list.json",
-                    platform
-                );
-                let list_path = exact_cache_dir.join("list.json");
-
-                loop {
-                    // Download list.json if missing
-                    if !list_path.exists() {
-                        if let Err(e) = download_to_file(&list_url, &list_path) {
-                            log_to_file(&format!("[solc-exact] Failed to download list.json: {:?}", e));
-                            thread::sleep(Duration::from_secs(5));
-                            continue;
-                        }
-                    }
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = IN_FLIGHT.lock() {
+            in_flight.remove(&self.0);
+        }
+    }
+}
 
-                    let list = match SolcList::from_file(&list_path) {
-                        Ok(l) => l,
-                        Err(e) => {
-                            log_to_file(&format!("[solc-exact] Failed to parse list.json: {:?}", e));
-                            break;
-                        }
-                    };
+/// Download the newest release matching `req` into `cache_dir` on a
+/// background thread, retrying until it succeeds or no release matches
+fn spawn_on_demand_download(req: Pragma, cache_dir: PathBuf) {
+    thread::spawn(move || download_matching_release(&req, &cache_dir));
+}
 
-                    let release_map = list.by_version();
-                    if let Some(release) = release_map.get(&version_clone.to_string()) {
-                        let binary_url = format!(
-                            "https://binaries.soliditylang.org/{}/{}",
-                            platform, release.path
-                        );
-
-                        log_to_file(&format!(
-                            "[solc-exact] Downloading solc {} from {}",
-                            version_clone, binary_url
-                        ));
-
-                        if let Err(e) = download_to_file(&binary_url, &binary_path) {
-                            log_to_file(&format!("[solc-exact] Download failed: {:?}", e));
-                            thread::sleep(Duration::from_secs(5));
-                            continue;
-                        }
+/// Set while a stale `list.json` is being refreshed
+static LIST_REFRESHING: AtomicBool = AtomicBool::new(false);
+
+/// Refresh `cache_dir`'s `list.json` in the background once it's older than
+/// `listTtlHours`, so releases made during a long session are picked up: the
+/// running manager's list is replaced, and with auto-update on the latest
+/// compiler of each series is fetched if it changed.
+fn refresh_stale_list(cache_dir: &Path) {
+    let list_path = cache_dir.join("list.json");
+    if !list_is_stale(&list_path) || LIST_REFRESHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
 
-                        if let Err(e) = verify_sha256(&binary_path, &release.sha256) {
-                            log_to_file(&format!("[solc-exact] Checksum mismatch: {:?}", e));
-                            let _ = std::fs::remove_file(&binary_path);
-                            thread::sleep(Duration::from_secs(5));
-                            continue;
+    let cache_dir = cache_dir.to_path_buf();
+    thread::spawn(move || {
+        log_to_file("[solc-sync] list.json is stale, refreshing it");
+        match fetch_list_json(&list_path) {
+            Ok(true) => match SolcList::from_file(&list_path) {
+                Ok(list) => {
+                    let manager = match SOLC_MANAGER.get() {
+                        Some(manager) if manager.cache_dir == cache_dir => {
+                            manager.set_list(list);
+                            Arc::clone(manager)
                         }
-
-                        let _ = make_executable(&binary_path);
-                        log_to_file(&format!("[solc-exact] Download complete: solc-{}", version_clone));
-                        break;
-                    } else {
-                        log_to_file(&format!(
-                            "[solc-exact] Version {} not found in list.json",
-                            version_clone
-                        ));
-                        break;
+                        _ => Arc::new(SolcManager::new(cache_dir, list)),
+                    };
+                    if config::get().auto_update
+                        && let Err(e) = manager.ensure_latest_versions(&|| false, |_, _, _| {})
+                    {
+                        log_to_file(&format!("[solc-sync] Error ensuring solc versions: {:?}", e));
                     }
                 }
-            });
+                Err(e) => log_to_file(&format!("[solc-sync] Failed to parse list.json: {:?}", e)),
+            },
+            Ok(_) => {}
+            Err(e) => log_to_file(&format!("[solc-sync] Failed to refresh list.json: {:?}", e)),
+        }
+        LIST_REFRESHING.store(false, Ordering::SeqCst);
+    });
+}
 
-            log_to_file(&format!(
-                "Exact version {} not cached — using system solc temporarily",
-                version
-            ));
-            which("solc").map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e))
+/// The newest release matching `req`, downloading `list.json` into
+/// `cache_dir` first if it's missing. `None` when no release matches or the
+/// server is shutting down.
+fn matching_release(req: &Pragma, cache_dir: &Path) -> Option<SolcRelease> {
+    let list_path = cache_dir.join("list.json");
+
+    let mut attempt = 0;
+    while !list_path.exists() {
+        let Err(e) = download_release_file("list.json", &list_path) else {
+            break;
+        };
+        log_to_file(&format!("[solc-download] Failed to download list.json: {:?}", e));
+        if matches!(e, SolcError::ShuttingDown) {
+            return None;
         }
+        thread::sleep(retry_delay(attempt));
+        attempt += 1;
+    }
 
-        Pragma::Range(req) => {
-            let cache_dir = dirs::cache_dir()
-                .unwrap_or_else(|| PathBuf::from(".cache"))
-                .join("emacs-solidity-server/solc");
+    let list = match SolcList::from_file(&list_path) {
+        Ok(l) => l,
+        Err(e) => {
+            log_to_file(&format!("[solc-download] Failed to parse list.json: {:?}", e));
+            return None;
+        }
+    };
 
-            let version_re = Regex::new(r"^solc-(\d+\.\d+\.\d+)$").unwrap();
-            let mut candidates = Vec::new();
+    let release = list
+        .builds
+        .iter()
+        .filter_map(|r| Version::parse(&r.version).ok().map(|v| (v, r)))
+        .filter(|(v, _)| req.matches(v))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, r)| r.clone());
+    if release.is_none() {
+        log_to_file(&format!("[solc-download] No release matching {} in list.json", req));
+    }
+    release
+}
 
-            for entry in fs::read_dir(&cache_dir)? {
-                let entry = entry?;
-                let fname = entry.file_name().to_string_lossy().to_string();
+/// Download the newest release matching `req` into `cache_dir`, retrying
+/// until it succeeds or the user cancels its progress. Requests resolving to
+/// a release already being downloaded there are no-ops, so two pragmas
+/// naming the same compiler never write the same file at once.
+fn download_matching_release(req: &Pragma, cache_dir: &Path) {
+    std::fs::create_dir_all(cache_dir).ok();
+
+    let Some(release) = matching_release(req, cache_dir) else {
+        return;
+    };
+    let Some(_claim) = InFlight::claim(cache_dir, &release.version) else {
+        log_to_file(&format!(
+            "[solc-download] solc {} is already being downloaded",
+            release.version
+        ));
+        return;
+    };
+
+    let mut filename = format!("solc-{}", release.version);
+    if cfg!(windows) {
+        filename.push_str(".exe");
+    }
+    let binary_path = cache_dir.join(filename);
+    // finished by another request since this one started
+    if binary_path.exists() || link_cached_copy(&release.sha256, &binary_path) {
+        return;
+    }
 
-                if let Some(cap) = version_re.captures(&fname) {
-                    if let Some(ver_str) = cap.get(1) {
-                        if let Ok(ver) = Version::parse(ver_str.as_str()) {
-                            if req.matches(&ver) {
-                                candidates.push((ver, entry.path()));
-                            }
-                        }
-                    }
-                }
+    let progress = Progress::begin(&format!("Downloading solc {}", release.version), 1);
+    let mut attempt = 0;
+    let outcome = loop {
+        if progress.as_ref().is_some_and(Progress::is_cancelled) {
+            log_to_file(&format!("[solc-download] Download of solc {} cancelled", release.version));
+            break "Download cancelled";
+        }
+        log_to_file(&format!(
+            "[solc-download] Downloading solc {} ({})",
+            release.version, release.path
+        ));
+
+        if let Err(e) = download_release_file(&release.path, &binary_path) {
+            log_to_file(&format!("[solc-download] Download failed: {:?}", e));
+            if matches!(e, SolcError::ShuttingDown) {
+                break "Download stopped";
             }
+            thread::sleep(retry_delay(attempt));
+            attempt += 1;
+            continue;
+        }
 
-            candidates.sort_by(|a, b| b.0.cmp(&a.0)); // latest first
+        if let Err(e) = verify_sha256(&binary_path, &release.sha256) {
+            log_to_file(&format!("[solc-download] Checksum mismatch: {:?}", e));
+            let _ = std::fs::remove_file(&binary_path);
+            thread::sleep(retry_delay(attempt));
+            attempt += 1;
+            continue;
+        }
 
-            if let Some((ver, path)) = candidates.first() {
+        let _ = make_executable(&binary_path);
+        record_cached(&release.sha256, &binary_path);
+        log_to_file(&format!("[solc-download] Download complete: solc-{}", release.version));
+        break "Download finished";
+    };
+    if let Some(p) = progress {
+        p.end(outcome);
+    }
+}
+
+/// Compiler forced by a `// ess-solc: 0.8.19` comment, which overrides the
+/// pragma for that one file
+pub fn find_compiler_override(content: &str) -> Option<Version> {
+    let value = strip_bom(content).lines().find_map(|line| {
+        line.trim_start()
+            .strip_prefix("//")?
+            .trim_start()
+            .strip_prefix("ess-solc:")
+    })?;
+    match Version::parse(value.trim().trim_start_matches('v')) {
+        Ok(version) => Some(version),
+        Err(e) => {
+            log_to_file(&format!("[solc-switch] Ignoring `ess-solc: {}`: {}", value.trim(), e));
+            None
+        }
+    }
+}
+
+/// Compilers picked with `solidity/setCompilerOverride`, by source file.
+/// They win over both `ess-solc` comments and the pragma.
+static COMPILER_OVERRIDES: Lazy<Mutex<HashMap<PathBuf, Version>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Compile `source_path` with `version` from now on, or go back to the
+/// pragma with `None`
+pub fn set_compiler_override(source_path: &Path, version: Option<Version>) {
+    let Ok(mut overrides) = COMPILER_OVERRIDES.lock() else {
+        return;
+    };
+    match version {
+        Some(version) => overrides.insert(source_path.to_path_buf(), version),
+        None => overrides.remove(source_path),
+    };
+}
+
+fn compiler_override(source_path: &Path) -> Option<Version> {
+    COMPILER_OVERRIDES.lock().ok()?.get(source_path).cloned()
+}
+
+/// Newest compiler in `dir` that `pragma` accepts, under any of its alternatives
+fn newest_cached(dir: &Path, pragma: &Pragma) -> Option<(Version, PathBuf)> {
+    scan_cache_dir(dir)
+        .into_iter()
+        .filter(|(version, _)| pragma.matches(version))
+        .max_by(|a, b| a.0.cmp(&b.0))
+}
+
+/// Every downloaded compiler, from both the minor-series and the exact
+/// cache, newest first
+pub fn cached_compilers() -> Vec<(Version, PathBuf)> {
+    let mut compilers: Vec<_> = [solc_cache_dir(), exact_cache_dir()]
+        .iter()
+        .flat_map(|dir| scan_cache_dir(dir))
+        .collect();
+    compilers.sort_by(|a, b| b.0.cmp(&a.0));
+    compilers
+}
+
+/// The system solc, standing in for a compiler matching `requirement`
+/// until `download_pending` brings one
+fn fallback_to_system(requirement: impl ToString, download_pending: bool) -> Result<PathBuf, SolcError> {
+    system_solc().ok_or_else(|| SolcError::NotFound {
+        requirement: requirement.to_string(),
+        download_pending,
+    })
+}
+
+/// Whether a download can provide a compiler matching `req`: one in the
+/// release list can, and so might one while the list isn't loaded yet
+fn downloadable(req: &Pragma) -> bool {
+    SOLC_MANAGER.get().is_none_or(|manager| {
+        manager
+            .list()
+            .builds
+            .iter()
+            .filter_map(|r| Version::parse(&r.version).ok())
+            .any(|v| req.matches(&v))
+    })
+}
+
+/// Exact-cache binary for `version`, downloading it in the background and
+/// using the system solc meanwhile if it isn't cached yet
+fn exact_binary(version: &Version) -> Result<PathBuf, SolcError> {
+    let exact_cache_dir = exact_cache_dir();
+
+    let mut filename = format!("solc-{}", version);
+    if cfg!(windows) {
+        filename.push_str(".exe");
+    }
+
+    let binary_path = exact_cache_dir.join(&filename);
+
+    if binary_path.exists() {
+        log_to_file(&format!("[solc-switch] Using exact cached solc: {}", version));
+        return Ok(binary_path);
+    }
+
+    let req = Pragma::Exact(version.clone());
+    let download_pending = downloadable(&req);
+    spawn_on_demand_download(req, exact_cache_dir);
+
+    log_to_file(&format!(
+        "Exact version {} not cached — using system solc temporarily",
+        version
+    ));
+    fallback_to_system(format!("={}", version), download_pending)
+}
+
+/// Resolve solc binary path for given source based on downloaded binaries
+/// Falls back to system solc if no match found. The pragma and `ess-solc`
+/// comment are read from `source_code`, the text being compiled, which for
+/// an unsaved buffer differs from the file on disk.
+pub fn get_solc_binary_from_cache(
+    source_path: &Path,
+    source_code: &str,
+    _project_root: &Path,
+) -> Result<PathBuf, SolcError> {
+    if let Some(version) = compiler_override(source_path) {
+        log_to_file(&format!(
+            "[solc-switch] {} overridden to solc {}",
+            source_path.display(),
+            version
+        ));
+        return exact_binary(&version);
+    }
+    if let Some(version) = find_compiler_override(source_code) {
+        log_to_file(&format!(
+            "[solc-switch] {} forces solc {} via ess-solc",
+            source_path.display(),
+            version
+        ));
+        return exact_binary(&version);
+    }
+    if find_solidity_pragma(source_code).is_none() {
+        log_to_file(&format!(
+            "[solc-switch] No pragma in {}; using system solc",
+            source_path.display()
+        ));
+        return fallback_to_system("any version (no pragma)", false);
+    }
+
+    let pragma = parse_pragma(source_code).map_err(|e| SolcError::InvalidPragma(e.to_string()))?;
+    refresh_stale_list(&solc_cache_dir());
+
+    match pragma {
+        Pragma::Exact(version) => exact_binary(&version),
+
+        req @ (Pragma::Range(_) | Pragma::AnyOf(_)) => {
+            let cache_dir = solc_cache_dir();
+
+            if let Some((ver, path)) = newest_cached(&cache_dir, &req) {
                 log_to_file(&format!("Using cached solc: {} → {:?}", ver, path));
-                Ok(path.clone())
+                Ok(path)
             } else {
                 log_to_file(&format!(
                     "No cached solc version matched {}; falling back to system solc",
                     req
                 ));
-                which("solc").map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e))
+                // without the background sync nothing else will fetch a match
+                if !config::get().auto_update {
+                    spawn_on_demand_download(req.clone(), cache_dir);
+                }
+                let download_pending = downloadable(&req);
+                fallback_to_system(req, download_pending)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alternatives(pragma: &Pragma) -> Vec<String> {
+        match pragma {
+            Pragma::AnyOf(alternatives) => alternatives.iter().map(|req| req.to_string()).collect(),
+            other => panic!("expected alternatives, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn glued_operators_are_split_into_comparators() {
+        assert_eq!(tokenize_pragma(">=0.8.0<0.9.0"), [">=0.8.0", "<0.9.0"]);
+        assert_eq!(tokenize_pragma(">= 0.8.0  < 0.9.0 ;"), [">=0.8.0", "<0.9.0"]);
+        assert_eq!(tokenize_pragma("^0.8.0"), ["^0.8.0"]);
+        assert_eq!(tokenize_pragma("0.8.19"), ["0.8.19"]);
+    }
+
+    #[test]
+    fn pragma_without_space_after_solidity() {
+        let Ok(Pragma::Range(req)) = parse_pragma("pragma solidity>=0.8.0<0.9.0;") else {
+            panic!("expected a range");
+        };
+        assert_eq!(req.to_string(), ">=0.8.0, <0.9.0");
+
+        let Ok(Pragma::Range(req)) = parse_pragma("pragma solidity^0.8.0;") else {
+            panic!("expected a range");
+        };
+        assert_eq!(req.to_string(), "^0.8.0");
+    }
+
+    #[test]
+    fn pragma_with_trailing_space_before_semicolon() {
+        let Ok(Pragma::Range(req)) = parse_pragma("pragma solidity ^0.8.0 ;") else {
+            panic!("expected a range");
+        };
+        assert_eq!(req.to_string(), "^0.8.0");
+
+        let Ok(Pragma::Exact(version)) = parse_pragma("pragma solidity =0.8.19 ;") else {
+            panic!("expected an exact version");
+        };
+        assert_eq!(version, Version::new(0, 8, 19));
+    }
+
+    #[test]
+    fn or_pragma_parses_into_alternatives() {
+        let pragma = parse_pragma("pragma solidity ^0.7.0 || ^0.8.0;").unwrap();
+
+        assert_eq!(alternatives(&pragma), ["^0.7.0", "^0.8.0"]);
+        assert!(pragma.matches(&Version::new(0, 7, 6)));
+        assert!(pragma.matches(&Version::new(0, 8, 25)));
+        assert!(!pragma.matches(&Version::new(0, 6, 12)));
+    }
+
+    #[test]
+    fn or_pragma_alternative_may_be_exact() {
+        let pragma = parse_pragma("pragma solidity 0.6.12 || ^0.8.0;").unwrap();
+
+        assert_eq!(alternatives(&pragma), ["=0.6.12", "^0.8.0"]);
+        assert!(pragma.matches(&Version::new(0, 6, 12)));
+        assert!(!pragma.matches(&Version::new(0, 6, 11)));
+    }
+
+    #[test]
+    fn invalid_or_alternative_is_an_error() {
+        assert!(parse_pragma("pragma solidity ^0.8.0 || ;").is_err());
+    }
+
+    #[test]
+    fn other_pragmas_before_the_solidity_one_are_skipped() {
+        let sources = [
+            "// SPDX-License-Identifier: MIT\npragma abicoder v2;\npragma solidity ^0.8.0;\n",
+            "pragma experimental ABIEncoderV2;\npragma solidity >=0.6.0 <0.8.0;\n",
+            "pragma experimental SMTChecker;\npragma abicoder v1;\npragma solidity 0.7.6;\n",
+        ];
+        let expected = ["^0.8.0", ">=0.6.0, <0.8.0", "=0.7.6"];
+
+        for (source, expected) in sources.iter().zip(expected) {
+            let req = match parse_pragma(source).unwrap() {
+                Pragma::Exact(version) => exact_requirement(&version),
+                Pragma::Range(req) => req,
+                other => panic!("unexpected {:?}", other),
+            };
+            assert_eq!(req.to_string(), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn file_with_only_other_pragmas_has_no_solidity_pragma() {
+        let source = "pragma abicoder v2;\npragma experimental ABIEncoderV2;\ncontract C {}\n";
+
+        assert!(find_solidity_pragma(source).is_none());
+        assert!(parse_pragma(source).is_err());
+    }
+
+    #[test]
+    fn pragma_of_bom_prefixed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("Token.sol");
+        fs::write(&file, "\u{feff}\n  pragma solidity ^0.8.0;\ncontract Token {}\n").unwrap();
+
+        let Ok(Pragma::Range(req)) = extract_pragma(&file) else {
+            panic!("expected a range");
+        };
+        assert_eq!(req.to_string(), "^0.8.0");
+        assert!(find_compiler_override("\u{feff}// ess-solc: 0.8.19\n").is_some());
+    }
+
+    #[test]
+    fn several_directives_on_one_line() {
+        let find = |source| find_solidity_pragma(source).map(str::trim);
+
+        assert_eq!(find("pragma abicoder v2; pragma solidity ^0.8.0;"), Some("^0.8.0"));
+        assert_eq!(find("pragma solidity ^0.8.0; pragma abicoder v2;"), Some("^0.8.0"));
+        assert_eq!(
+            find("pragma experimental SMTChecker;pragma solidity >=0.7.0 <0.9.0; contract C {}"),
+            Some(">=0.7.0 <0.9.0")
+        );
+        assert_eq!(find("pragma abicoder v2; // pragma solidity ^0.8.0;"), None);
+    }
+
+    #[test]
+    fn bom_before_pragma_on_the_first_line() {
+        assert_eq!(find_solidity_pragma("\u{feff}pragma solidity ^0.8.0;\n").map(str::trim), Some("^0.8.0"));
+        let Ok(Pragma::Range(req)) = parse_pragma("\u{feff}pragma solidity >=0.7.0 <0.9.0;") else {
+            panic!("expected a range");
+        };
+        assert_eq!(req.to_string(), ">=0.7.0, <0.9.0");
+    }
+
+    #[test]
+    fn compiler_is_chosen_from_the_editor_text_not_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("Token.sol");
+        fs::write(&file, "pragma solidity ^0.8.0;\n").unwrap();
+
+        // the unsaved buffer's pragma is the one that counts
+        let err = get_solc_binary_from_cache(&file, "pragma solidity ^0.8.0 || ;\n", dir.path())
+            .unwrap_err();
+        assert!(matches!(err, SolcError::InvalidPragma(_)), "{:?}", err);
+
+        // nothing is read from disk, so a file not saved yet is no I/O error
+        let unsaved = dir.path().join("Unsaved.sol");
+        let result = get_solc_binary_from_cache(&unsaved, "contract C {}\n", dir.path());
+        assert!(!matches!(result, Err(SolcError::Io(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn a_release_downloads_once_per_directory_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+
+        let claim = InFlight::claim(dir.path(), "0.8.25").unwrap();
+        assert!(InFlight::claim(dir.path(), "0.8.25").is_none());
+        assert!(InFlight::claim(other.path(), "0.8.25").is_some());
+        assert!(InFlight::claim(dir.path(), "0.8.24").is_some());
+
+        drop(claim);
+        assert!(InFlight::claim(dir.path(), "0.8.25").is_some());
+    }
+
+    #[test]
+    fn newest_cached_binary_matching_any_alternative_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        for version in ["0.6.12", "0.7.6", "0.8.19", "0.8.25", "0.9.0"] {
+            fs::write(dir.path().join(format!("solc-{}", version)), "").unwrap();
+        }
+
+        let pragma = parse_pragma("pragma solidity ^0.7.0 || ^0.8.0;").unwrap();
+        let (version, path) = newest_cached(dir.path(), &pragma).unwrap();
+        assert_eq!(version, Version::new(0, 8, 25));
+        assert_eq!(path, dir.path().join("solc-0.8.25"));
+
+        let pragma = parse_pragma("pragma solidity ^0.7.0 || 0.6.12;").unwrap();
+        let (version, _) = newest_cached(dir.path(), &pragma).unwrap();
+        assert_eq!(version, Version::new(0, 7, 6));
+    }
+
+    #[test]
+    fn or_of_bounded_ranges() {
+        let pragma = parse_pragma("pragma solidity >=0.7.0 <0.8.0 || >=0.8.1 <0.9.0;").unwrap();
+
+        assert_eq!(alternatives(&pragma), [">=0.7.0, <0.8.0", ">=0.8.1, <0.9.0"]);
+        assert!(pragma.matches(&Version::new(0, 7, 6)));
+        assert!(!pragma.matches(&Version::new(0, 8, 0)));
+        assert!(pragma.matches(&Version::new(0, 8, 1)));
+        assert!(!pragma.matches(&Version::new(0, 9, 0)));
+    }
+
+    #[test]
+    fn or_pragma_tolerates_missing_and_extra_whitespace() {
+        let tight = parse_pragma("pragma solidity >=0.7.0<0.8.0||>=0.8.1 <0.9.0;").unwrap();
+        let loose = parse_pragma("pragma  solidity  >= 0.7.0  < 0.8.0  ||  >= 0.8.1 < 0.9.0 ;");
+
+        assert_eq!(alternatives(&tight), [">=0.7.0, <0.8.0", ">=0.8.1, <0.9.0"]);
+        assert_eq!(alternatives(&loose.unwrap()), alternatives(&tight));
+    }
+
+    #[test]
+    fn openzeppelin_pragmas() {
+        // `access/Ownable.sol` of 3.4, `interfaces/IERC1363.sol` and
+        // `token/ERC20/ERC20.sol` of 5.x, and a file accepting both 3.x and 4.x
+        let cases = [
+            ("pragma solidity >=0.6.0 <0.8.0;", "0.7.6", "0.8.0"),
+            ("pragma solidity >=0.6.2;", "0.8.25", "0.6.1"),
+            ("pragma solidity ^0.8.20;", "0.8.24", "0.8.19"),
+            ("pragma solidity >=0.6.2 <0.8.0 || ^0.8.0;", "0.8.25", "0.6.1"),
+        ];
+
+        for (line, accepted, rejected) in cases {
+            let pragma = parse_pragma(line).unwrap();
+            assert!(pragma.matches(&Version::parse(accepted).unwrap()), "{} {}", line, accepted);
+            assert!(!pragma.matches(&Version::parse(rejected).unwrap()), "{} {}", line, rejected);
+        }
+    }
+
+    #[test]
+    fn single_requirement_of_or_pragma_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("Lib.sol");
+        fs::write(&file, "pragma solidity >=0.7.0 <0.8.0 || >=0.8.1 <0.9.0;\n").unwrap();
+        assert!(extract_pragma_version(&file).is_err());
+
+        fs::write(&file, "pragma solidity 0.8.19;\n").unwrap();
+        assert_eq!(extract_pragma_version(&file).unwrap().to_string(), "=0.8.19");
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use once_cell::sync::Lazy;
+
+#[derive(Default)]
+struct Usage {
+    /// Source file → compiler it was last compiled with
+    resolved: HashMap<PathBuf, PathBuf>,
+    /// Compiler → when it was last resolved in this session
+    last_used: HashMap<PathBuf, SystemTime>,
+}
+
+static USAGE: Lazy<Mutex<Usage>> = Lazy::new(|| Mutex::new(Usage::default()));
+
+/// Remember that `source` compiles with `binary`
+pub fn record(source: &Path, binary: &Path) {
+    if let Ok(mut usage) = USAGE.lock() {
+        usage.resolved.insert(source.to_path_buf(), binary.to_path_buf());
+        usage.last_used.insert(binary.to_path_buf(), SystemTime::now());
+    }
+}
+
+/// Compiler `source` was last compiled with
+pub fn resolved(source: &Path) -> Option<PathBuf> {
+    USAGE.lock().ok()?.resolved.get(source).cloned()
+}
+
+/// Compilers resolved for any of `sources`
+pub fn in_use<'a>(sources: impl IntoIterator<Item = &'a Path>) -> HashSet<PathBuf> {
+    let Ok(usage) = USAGE.lock() else {
+        return HashSet::new();
+    };
+    sources
+        .into_iter()
+        .filter_map(|source| usage.resolved.get(source).cloned())
+        .collect()
+}
+
+/// When `binary` was last used: resolved in this session, else its access or
+/// modification time on disk
+pub fn last_used(binary: &Path) -> SystemTime {
+    let session = USAGE.lock().ok().and_then(|u| u.last_used.get(binary).copied());
+    let on_disk = std::fs::metadata(binary)
+        .ok()
+        .and_then(|m| match (m.accessed().ok(), m.modified().ok()) {
+            (Some(a), Some(m)) => Some(a.max(m)),
+            (a, m) => a.or(m),
+        });
+    session.max(on_disk).unwrap_or(SystemTime::UNIX_EPOCH)
+}
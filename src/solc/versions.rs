@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use anyhow::{self, Result};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct SolcRelease {
     pub path: String,
     pub version: String,
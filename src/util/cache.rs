@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::util::log::log_to_file;
+
+const APP_DIR: &str = "emacs-solidity-server";
+
+static CACHE_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Directory holding everything the server caches (compilers, definition
+/// indices). `ESS_CACHE_DIR` wins; otherwise the platform cache directory,
+/// and when there is none (e.g. no `HOME` in a container) the temp
+/// directory. Resolved once; the choice is logged.
+pub fn cache_root() -> PathBuf {
+    CACHE_ROOT
+        .get_or_init(|| {
+            let (root, origin) = match std::env::var_os("ESS_CACHE_DIR").filter(|dir| !dir.is_empty()) {
+                Some(dir) => (PathBuf::from(dir), "ESS_CACHE_DIR"),
+                None => match dirs::cache_dir() {
+                    Some(dir) => (dir.join(APP_DIR), "platform cache directory"),
+                    None => (std::env::temp_dir().join(APP_DIR), "temp directory fallback"),
+                },
+            };
+            log_to_file(&format!("[cache] Using {} ({})", root.display(), origin));
+            root
+        })
+        .clone()
+}
@@ -1,54 +1,104 @@
-use std::collections::HashSet;
-use std::io::{Result, Write};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output, Stdio};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use serde_json::json;
+use serde_json::{json, Value};
 
+use crate::config;
 use crate::project::remappings::Remapping;
-use crate::util::imports::resolve_sources_recursive;
+use crate::project::workspace::foundry_model_checker;
+use crate::util::imports::{resolve_sources_recursive, source_unit_name};
 use crate::util::log::log_to_file;
+use crate::util::text::strip_bom;
 
-use crate::analysis::definitions::extract_definitions_from_solc_json;
+use crate::analysis::ast::{extract_source_units, AST_MAP};
+use crate::analysis::inheritance::{extract_contracts_from_solc_json, CONTRACT_MAP};
+use crate::analysis::references::{extract_references_from_solc_json, REFERENCE_MAP};
+use crate::analysis::definitions::{build_source_table, extract_definitions_from_solc_json};
 use crate::analysis::definitions::DEFINITION_MAP;
 
+use crate::solc::error::SolcError;
+use crate::solc::identity::long_version;
 use crate::solc::switcher::get_solc_binary_from_cache;
+use crate::solc::usage;
 
+/// Physical file behind each solc source unit name of a compile
+pub type SourcePaths = BTreeMap<String, PathBuf>;
+
+/// Settings the compile pipeline depends on; `solcSettings` can't change them
+const MANAGED_SETTINGS: [&str; 2] = ["remappings", "outputSelection"];
+
+/// Merge `extra` into `base`: objects key by key, anything else replaced
+fn merge_json(base: &mut Value, extra: &Value) {
+    match (base, extra) {
+        (Value::Object(base), Value::Object(extra)) => {
+            for (key, value) in extra {
+                merge_json(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (base, extra) => *base = extra.clone(),
+    }
+}
+
+/// Compile `source_path` with `source_code` overlaid on its file. Returns
+/// solc's output and the physical file of every source unit name solc was given.
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use emacs_solidity_server::{parse_remappings, run_solc};
+///
+/// let root = Path::new("/path/to/project");
+/// let file = root.join("src/Token.sol");
+/// let text = std::fs::read_to_string(&file)?;
+/// let (output, _) = run_solc(&file, &text, &parse_remappings(root, None), root)?;
+/// println!("{}", String::from_utf8_lossy(&output.stdout));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 pub fn run_solc(
     source_path: &Path,
     source_code: &str,
     remappings: &[Remapping],
     project_root: &Path,
-) -> Result<Output> {
+) -> Result<(Output, SourcePaths), SolcError> {
     log_to_file("=== run_solc ==================================================");
 
     let mut visited = HashSet::new();
-    let mut sources = resolve_sources_recursive(project_root, source_path, &mut visited);
+    let mut sources = resolve_sources_recursive(project_root, source_path, remappings, &mut visited);
+
+    // the walker named the entry the same way, however its imports reach it
+    // again, so the editor text replaces the disk copy under a single key
+    let entry_virtual = source_unit_name(source_path, project_root, remappings);
+    sources.insert(entry_virtual.clone(), strip_bom(source_code).to_string());
 
-    let entry_virtual = sources
+    // unit names are paths relative to the project root (or absolute), except the entry's
+    let source_paths: SourcePaths = sources
         .keys()
-        .find(|k| sources[*k].as_ptr() == source_path.to_string_lossy().as_ptr())
-        .cloned()
-        .unwrap_or_else(|| {
-            pathdiff::diff_paths(source_path, project_root)
-                .unwrap_or_else(|| PathBuf::from("input.sol"))
-                .to_string_lossy()
-                .replace('\\', "/")
-        });
-    sources.insert(entry_virtual.clone(), source_code.to_string());
+        .map(|name| {
+            let path = if *name == entry_virtual {
+                source_path.to_path_buf()
+            } else {
+                project_root.join(name)
+            };
+            (name.clone(), path)
+        })
+        .collect();
 
     let remap_strings: Vec<String> = remappings
         .iter()
-        .map(|r| format!("{}={}", r.prefix, r.target.display()))
+        .map(|r| r.to_solc(project_root))
         .collect();
     log_to_file(&format!("Remappings: {:?}", remap_strings));
 
     let sources_json = sources
-        .into_iter()
-        .map(|(k, v)| (k, json!({ "content": v })))
+        .iter()
+        .map(|(k, v)| (k.clone(), json!({ "content": v })))
         .collect::<serde_json::Map<_, _>>();
 
-    let input_json = json!({
+    let mut input_json = json!({
         "language": "Solidity",
         "sources": sources_json,
         "settings": {
@@ -56,33 +106,87 @@ pub fn run_solc(
             "outputSelection": { "*": { "*": [], "": ["ast"] } }
         }
     });
+    // SMTChecker findings come back as ordinary warnings
+    let model_checker = config::get()
+        .model_checker
+        .or_else(|| foundry_model_checker(project_root));
+    if let Some(model_checker) = model_checker {
+        input_json["settings"]["modelChecker"] = model_checker;
+    }
+    if let Some(Value::Object(extra)) = config::get().solc_settings {
+        for (key, value) in extra {
+            if MANAGED_SETTINGS.contains(&key.as_str()) {
+                log_to_file(&format!("[solc] Ignoring solcSettings.{}: set by the server", key));
+                continue;
+            }
+            merge_json(&mut input_json["settings"][key.as_str()], &value);
+        }
+        log_to_file(&format!("[solc] Effective settings: {}", input_json["settings"]));
+    }
 
-    log_to_file(&format!("Standard JSON input:\n{}", input_json.to_string()));
+    log_to_file(&format!("Standard JSON input:\n{}", input_json));
 
-    let solc_binary = get_solc_binary_from_cache(source_path, project_root)?;
+    let solc_binary = get_solc_binary_from_cache(source_path, source_code, project_root)?;
+    usage::record(source_path, &solc_binary);
 
-    log_to_file(&format!("Using solc binary: {}", solc_binary.to_string_lossy()));
+    log_to_file(&format!(
+        "Using solc binary: {} ({})",
+        solc_binary.to_string_lossy(),
+        long_version(&solc_binary).as_deref().unwrap_or("unknown build")
+    ));
 
-    let mut child = Command::new(solc_binary)
+    let mut child = Command::new(&solc_binary)
         .arg("--standard-json")
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()?;
+        .spawn()
+        .map_err(|e| {
+            if e.kind() == ErrorKind::PermissionDenied {
+                SolcError::NotExecutable {
+                    path: solc_binary.clone(),
+                    source: e,
+                }
+            } else {
+                SolcError::Io(e)
+            }
+        })?;
 
-    child
-        .stdin
-        .as_mut()
-        .unwrap()
-        .write_all(input_json.to_string().as_bytes())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input_json.to_string().as_bytes())?;
+    } // dropped here so solc sees EOF
 
-    let out = child.wait_with_output()?;
+    let timeout = Duration::from_secs(config::get().compile_timeout_secs);
+    let out = wait_with_timeout(child, timeout)?;
     log_to_file(&format!("solc exited with status {:?}", out.status));
     log_to_file(&format!("STDOUT bytes: {}", out.stdout.len()));
     log_to_file(&format!("STDERR bytes: {}", out.stderr.len()));
 
     if let Ok(parsed_json) = serde_json::from_slice::<serde_json::Value>(&out.stdout) {
-        let defs_per_file = extract_definitions_from_solc_json(&parsed_json, project_root);
+        // solc reports settings it doesn't accept as a JSONError without a location
+        let rejected = parsed_json["errors"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|e| e["type"] == "JSONError");
+        for error in rejected {
+            log_to_file(&format!(
+                "[solc] Input rejected, check solcSettings: {}",
+                error["message"].as_str().unwrap_or_default()
+            ));
+        }
+        let table = build_source_table(&parsed_json, project_root, &sources);
+        let defs_per_file = extract_definitions_from_solc_json(&parsed_json, &table, project_root);
+
+        if let Ok(mut units) = AST_MAP.lock() {
+            units.extend(extract_source_units(&parsed_json, &table));
+        }
+        if let Ok(mut refs) = REFERENCE_MAP.lock() {
+            refs.extend(extract_references_from_solc_json(&parsed_json, &table));
+        }
+        if let Ok(mut contracts) = CONTRACT_MAP.lock() {
+            contracts.extend(extract_contracts_from_solc_json(&parsed_json, &table));
+        }
 
 //        for (file, defs) in &defs_per_file {
 //            log_to_file(&format!("Definitions in {}:", file));
@@ -97,5 +201,75 @@ pub fn run_solc(
         log_to_file("⚠️  Could not parse solc stdout as JSON");
     }
 
-    Ok(out)
+    Ok((out, source_paths))
+}
+
+/// Wait for `child` to exit, killing it once `timeout` has elapsed. A zero
+/// timeout, or one too large to add to the current time, never elapses.
+/// stdout/stderr are drained on helper threads so a chatty solc can't block on a full pipe.
+fn wait_with_timeout(mut child: Child, timeout: Duration) -> Result<Output, SolcError> {
+    let stdout = drain_pipe(child.stdout.take());
+    let stderr = drain_pipe(child.stderr.take());
+
+    let deadline = Some(timeout)
+        .filter(|t| !t.is_zero())
+        .and_then(|t| Instant::now().checked_add(t));
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            let _ = child.kill();
+            let _ = child.wait();
+            log_to_file(&format!("solc killed after {}s timeout", timeout.as_secs()));
+            return Err(SolcError::TimedOut(timeout));
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn drain_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Child {
+        Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn zero_or_unrepresentable_timeout_waits_for_the_child() {
+        for timeout in [Duration::ZERO, Duration::from_secs(u64::MAX), Duration::MAX] {
+            let output = wait_with_timeout(sh("sleep 0.1; echo done"), timeout).unwrap();
+            assert_eq!(output.stdout, b"done\n", "{:?}", timeout);
+        }
+    }
+
+    #[test]
+    fn child_outliving_the_timeout_is_killed() {
+        let started = Instant::now();
+        let result = wait_with_timeout(sh("sleep 10"), Duration::from_millis(100));
+        assert!(matches!(result, Err(SolcError::TimedOut(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
 }
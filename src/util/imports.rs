@@ -1,59 +1,340 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use pathdiff::diff_paths;
 use regex::Regex;
+use serde::Serialize;
 
-/// Recursively resolves relative Solidity imports into a map of virtual path → source content.
+use crate::project::remappings::{remap_import, Remapping};
+use crate::util::text::strip_bom;
+use crate::util::uri::canonical_path;
+
+/// An import the walker could not follow, and why
+#[derive(Debug, Clone, Serialize)]
+pub struct UnresolvedImport {
+    /// Virtual path of the importing file
+    pub importer: String,
+    /// Import path as written in the source
+    pub import: String,
+    pub reason: String,
+}
+
+/// Matches an import statement, capturing the imported path:
+/// `import "./X.sol";`, `import {X} from "../Y/X.sol";`, `import {X as Y} from "../Z/X.sol";`
+pub fn import_regex() -> Regex {
+    Regex::new(r#"import\s+(?:\{[^}]*\}\s+from\s+)?["']([^"']+)["']"#).unwrap()
+}
+
+/// File an import path points to, as solc would look for it: relative to the
+/// importing file's directory `dir`, else through `remappings`, else under the
+/// project root. The returned file may not exist.
+pub fn resolve_import(
+    import: &str,
+    dir: &Path,
+    remappings: &[Remapping],
+    project_root: &Path,
+) -> Option<PathBuf> {
+    if import.starts_with('.') {
+        Some(dir.join(import))
+    } else if let Some(remapped) = remap_import(remappings, import, project_root) {
+        Some(remapped)
+    } else {
+        // like solc's --base-path: `import "src/Token.sol"` from the project root
+        Some(project_root.join(import)).filter(|path| path.is_file())
+    }
+}
+
+/// `path` with `.` and `..` components folded away without resolving
+/// symlinks, the way solc normalizes import paths
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// solc source unit name of a file: its path relative to the project root
+/// with `/` separators. Both sides are canonicalized, so every route to a
+/// file yields the same name, except that a file reached through a symlink
+/// inside the project but living outside it is named by that route (as solc
+/// names it) rather than with `../` components.
+pub fn virtual_path(physical_path: &Path, project_root: &Path) -> String {
+    let root = canonical_path(project_root);
+    let canonical = canonical_path(physical_path);
+    let relative = diff_paths(&canonical, &root)
+        .filter(|relative| !relative.starts_with(".."))
+        .or_else(|| {
+            let lexical = normalize_lexically(physical_path);
+            let relative = lexical
+                .strip_prefix(&root)
+                .or_else(|_| lexical.strip_prefix(project_root))
+                .ok()?;
+            Some(relative.to_path_buf())
+        })
+        .or_else(|| diff_paths(&canonical, &root))
+        .unwrap_or(canonical);
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// solc source unit name of a file as it is imported: a file under a
+/// remapping's target is named the way solc names it when the import goes
+/// through that remapping (the target as given to solc, then the rest of the
+/// path), so the name is the same whether the file is the entry or an import,
+/// even when the target is a symlink or lies outside the project root. Other
+/// files get their [`virtual_path`].
+pub fn source_unit_name(
+    physical_path: &Path,
+    project_root: &Path,
+    remappings: &[Remapping],
+) -> String {
+    let physical = canonical_path(physical_path);
+    let root = canonical_path(project_root);
+    let remapped = remappings
+        .iter()
+        .filter_map(|remapping| {
+            let target = canonical_path(&remapping.resolved_target(project_root));
+            // a target at the project root would rename every file
+            if target == root {
+                return None;
+            }
+            let rest = physical.strip_prefix(&target).ok()?;
+            Some((target.components().count(), remapping, rest))
+        })
+        .max_by_key(|(depth, _, _)| *depth);
+
+    let Some((_, remapping, rest)) = remapped else {
+        return virtual_path(physical_path, project_root);
+    };
+    let solc = remapping.to_solc(project_root);
+    let target = solc.split_once('=').map_or("", |(_, target)| target);
+    let rest = rest.to_string_lossy().replace('\\', "/");
+    if rest.is_empty() {
+        target.to_string()
+    } else {
+        format!("{}/{}", target.trim_end_matches('/'), rest)
+    }
+}
+
+/// Recursively resolves Solidity imports into a map of virtual path → source content.
+/// Relative imports are resolved against the importing file, everything else through
+/// `remappings`, falling back to the project root
+/// itself as solc's `--base-path` would.
+/// The map is ordered by virtual path so solc assigns stable source indices run-to-run.
 pub fn resolve_sources_recursive(
     project_root: &Path,
     physical_path: &Path,
+    remappings: &[Remapping],
     visited: &mut HashSet<PathBuf>,
-) -> HashMap<String, String> {
-    let mut sources = HashMap::new();
+) -> BTreeMap<String, String> {
+    let mut unresolved = Vec::new();
+    resolve_sources_with_report(project_root, physical_path, remappings, visited, &mut unresolved)
+}
 
-    // This handles:
-    // import "./X.sol";
-    // import {X} from "../Y/X.sol";
-    // import {X as Y} from "../Z/X.sol";
-    let import_re = Regex::new(r#"import\s+(?:\{[^}]*\}\s+from\s+)?["']([^"']+)["']"#).unwrap();
+/// Same as [`resolve_sources_recursive`], additionally collecting every import
+/// that could not be followed into `unresolved`.
+pub fn resolve_sources_with_report(
+    project_root: &Path,
+    physical_path: &Path,
+    remappings: &[Remapping],
+    visited: &mut HashSet<PathBuf>,
+    unresolved: &mut Vec<UnresolvedImport>,
+) -> BTreeMap<String, String> {
+    let mut sources = BTreeMap::new();
+    let import_re = import_regex();
 
     fn walk(
         project_root: &Path,
         phys: &Path,
+        remappings: &[Remapping],
         visited: &mut HashSet<PathBuf>,
-        acc: &mut HashMap<String, String>,
+        acc: &mut BTreeMap<String, String>,
+        unresolved: &mut Vec<UnresolvedImport>,
         re: &Regex,
     ) {
-        if !visited.insert(phys.to_path_buf()) {
+        // canonical paths are the dedup key, so `../A/X.sol` and `./X.sol` meet
+        // here; the name still follows the route taken, symlinks included
+        let phys = &normalize_lexically(phys);
+        if !visited.insert(canonical_path(phys)) {
             return; // already visited
         }
 
-        let Ok(code) = fs::read_to_string(phys) else {
-            return;
-        };
+        let virt = source_unit_name(phys, project_root, remappings);
 
-        let virt = diff_paths(phys, project_root)
-            .unwrap_or_else(|| phys.to_path_buf())
-            .to_string_lossy()
-            .replace('\\', "/");
+        let code = match fs::read_to_string(phys) {
+            Ok(code) => strip_bom(&code).to_string(),
+            Err(e) => {
+                unresolved.push(UnresolvedImport {
+                    importer: virt,
+                    import: phys.to_string_lossy().to_string(),
+                    reason: format!("unreadable: {}", e),
+                });
+                return;
+            }
+        };
 
         acc.insert(virt.clone(), code.clone());
 
         let dir = phys.parent().unwrap_or(Path::new("."));
         for cap in re.captures_iter(&code) {
             let imp = cap[1].trim();
-            if !imp.starts_with('.') {
-                continue; // skip non-relative imports
-            }
-            let child_phys = dir.join(imp);
-            if let Ok(abs_child) = child_phys.canonicalize() {
-                walk(project_root, &abs_child, visited, acc, re);
+            let Some(child_phys) = resolve_import(imp, dir, remappings, project_root) else {
+                unresolved.push(UnresolvedImport {
+                    importer: virt.clone(),
+                    import: imp.to_string(),
+                    reason: "no remapping matches and not found under the project root".into(),
+                });
+                continue;
+            };
+            if child_phys.exists() {
+                walk(project_root, &child_phys, remappings, visited, acc, unresolved, re);
+            } else {
+                unresolved.push(UnresolvedImport {
+                    importer: virt.clone(),
+                    import: imp.to_string(),
+                    reason: format!("{}: file not found", child_phys.display()),
+                });
             }
         }
     }
 
-    walk(project_root, physical_path, visited, &mut sources, &import_re);
+    let project_root = &canonical_path(project_root);
+    walk(project_root, physical_path, remappings, visited, &mut sources, unresolved, &import_re);
     sources
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::remappings::parse_remappings_txt;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn entry_imported_back_by_its_imports_is_one_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("remappings.txt"), "app/=src/\n");
+        write(&root.join("src/Main.sol"), "import \"./Lib.sol\";\n");
+        write(&root.join("src/Lib.sol"), "import \"app/Main.sol\";\nimport \"../src/./Main.sol\";\n");
+        let remappings = parse_remappings_txt(&root.join("remappings.txt"));
+
+        let entry = root.join("src/Main.sol");
+        let mut sources = resolve_sources_recursive(root, &entry, &remappings, &mut HashSet::new());
+        assert_eq!(sources.len(), 2, "{:?}", sources.keys());
+
+        // the editor text is overlaid under the name the walker used
+        let name = source_unit_name(&entry, root, &remappings);
+        assert!(sources.contains_key(&name), "{} not in {:?}", name, sources.keys());
+        sources.insert(name, "// unsaved\n".into());
+        assert_eq!(sources.len(), 2);
+    }
+
+    #[test]
+    fn entry_under_remapped_path_keeps_the_name_imports_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("remappings.txt"), "@oz/=lib/oz/\n");
+        write(&root.join("src/Main.sol"), "import \"@oz/Token.sol\";\n");
+        write(&root.join("lib/oz/Token.sol"), "import \"@oz/Base.sol\";\n");
+        write(&root.join("lib/oz/Base.sol"), "import \"./Token.sol\";\n");
+        let remappings = parse_remappings_txt(&root.join("remappings.txt"));
+
+        let entry = root.join("lib/oz/Token.sol");
+        let from_entry = resolve_sources_recursive(root, &entry, &remappings, &mut HashSet::new());
+        let main = root.join("src/Main.sol");
+        let from_main = resolve_sources_recursive(root, &main, &remappings, &mut HashSet::new());
+
+        let name = source_unit_name(&entry, root, &remappings);
+        assert_eq!(name, "lib/oz/Token.sol");
+        assert!(from_main.contains_key(&name));
+        assert_eq!(from_entry.keys().collect::<Vec<_>>(), ["lib/oz/Base.sol", "lib/oz/Token.sol"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn entry_behind_symlinked_remapping_target_is_named_as_imported() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        let shared = dir.path().join("shared/oz");
+        write(&root.join("remappings.txt"), "@oz/=lib/oz/\n");
+        write(&root.join("src/Main.sol"), "import \"@oz/Token.sol\";\n");
+        write(&shared.join("Token.sol"), "contract Token {}\n");
+        fs::create_dir_all(root.join("lib")).unwrap();
+        std::os::unix::fs::symlink(&shared, root.join("lib/oz")).unwrap();
+        let remappings = parse_remappings_txt(&root.join("remappings.txt"));
+
+        // the editor opened the file at its real location, outside the project
+        let name = source_unit_name(&shared.join("Token.sol"), &root, &remappings);
+        let main = root.join("src/Main.sol");
+        let sources = resolve_sources_recursive(&root, &main, &remappings, &mut HashSet::new());
+
+        assert_eq!(name, "lib/oz/Token.sol");
+        assert_eq!(sources.keys().collect::<Vec<_>>(), ["lib/oz/Token.sol", "src/Main.sol"]);
+    }
+
+    #[test]
+    fn file_reached_by_two_import_paths_is_one_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            &root.join("src/A/Main.sol"),
+            "import \"./X.sol\";\nimport \"../A/X.sol\";\nimport \"src/A/X.sol\";\n",
+        );
+        write(&root.join("src/A/X.sol"), "contract X {}\n");
+
+        let main = root.join("src/A/Main.sol");
+        let mut unresolved = Vec::new();
+        let sources =
+            resolve_sources_with_report(root, &main, &[], &mut HashSet::new(), &mut unresolved);
+
+        assert_eq!(sources.keys().collect::<Vec<_>>(), ["src/A/Main.sol", "src/A/X.sol"]);
+        assert!(unresolved.is_empty(), "{:?}", unresolved);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directory_outside_the_project_keeps_the_imported_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        let shared = dir.path().join("shared");
+        write(&root.join("src/Main.sol"), "import \"./shared/X.sol\";\n");
+        write(&shared.join("X.sol"), "import \"./Y.sol\";\nimport \"../shared/Y.sol\";\n");
+        write(&shared.join("Y.sol"), "contract Y {}\n");
+        std::os::unix::fs::symlink(&shared, root.join("src/shared")).unwrap();
+
+        let main = root.join("src/Main.sol");
+        let sources = resolve_sources_recursive(&root, &main, &[], &mut HashSet::new());
+
+        assert_eq!(
+            sources.keys().collect::<Vec<_>>(),
+            ["src/Main.sol", "src/shared/X.sol", "src/shared/Y.sol"]
+        );
+    }
+
+    #[test]
+    fn bom_is_stripped_from_sources_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("src/Main.sol"), "\u{feff}import \"./Lib.sol\";\n");
+        write(&root.join("src/Lib.sol"), "\u{feff}library Lib {}\n");
+
+        let main = root.join("src/Main.sol");
+        let sources = resolve_sources_recursive(root, &main, &[], &mut HashSet::new());
+
+        assert_eq!(sources["src/Main.sol"], "import \"./Lib.sol\";\n");
+        assert_eq!(sources["src/Lib.sol"], "library Lib {}\n");
+    }
+}
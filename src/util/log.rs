@@ -1,12 +1,36 @@
-// use std::fs::OpenOptions;
-// use std::io::Write;
-
-pub fn log_to_file(_msg: &str) {
-//    if let Ok(mut file) = OpenOptions::new()
-//        .create(true)
-//        .append(true)
-//        .open("/tmp/emacs-solidity-server.log")
-//    {
-//        let _ = writeln!(file, "{}", _msg);
-//    }
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Where `log_to_file` writes; nothing is logged until one is chosen
+enum LogTarget {
+    File(Mutex<File>),
+    Stderr,
+}
+
+static TARGET: OnceLock<LogTarget> = OnceLock::new();
+
+/// Append log lines to `path` (`--log-file`)
+pub fn log_to_path(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = TARGET.set(LogTarget::File(Mutex::new(file)));
+    Ok(())
+}
+
+/// Write log lines to stderr (`--log-stderr`), for editors that capture it
+pub fn log_to_stderr() {
+    let _ = TARGET.set(LogTarget::Stderr);
+}
+
+pub fn log_to_file(msg: &str) {
+    match TARGET.get() {
+        Some(LogTarget::File(file)) => {
+            if let Ok(mut file) = file.lock() {
+                let _ = writeln!(file, "{}", msg);
+            }
+        }
+        Some(LogTarget::Stderr) => eprintln!("{}", msg),
+        None => {}
+    }
 }
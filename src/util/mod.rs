@@ -3,3 +3,6 @@ pub mod position;
 pub mod log;
 pub mod imports;
 pub mod text;
+pub mod hash;
+pub mod uri;
+pub mod cache;
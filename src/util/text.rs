@@ -1,11 +1,83 @@
-pub fn extract_identifier_at(source: &str, offset: usize) -> Option<String> {
-    let bytes = source.as_bytes();
+/// UTF-8 byte order mark some Windows editors put at the start of a file
+const BOM: char = '\u{feff}';
 
-    if offset >= bytes.len() {
-        return None;
+/// `text` without a leading byte order mark. Sources are handed to solc
+/// without it, so solc's byte offsets line up with the text editors show.
+pub fn strip_bom(text: &str) -> &str {
+    text.strip_prefix(BOM).unwrap_or(text)
+}
+
+/// `source` with its `//` and `/* */` comments replaced by spaces (newlines
+/// are kept), so byte offsets still line up. String literals are left alone.
+pub fn blank_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            out.push(c);
+            if c == '\\' {
+                out.extend(chars.next());
+            } else if c == q || c == '\n' {
+                quote = None;
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"' | '\'', _) => {
+                quote = Some(c);
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                out.push(' ');
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    chars.next();
+                    out.extend(std::iter::repeat_n(' ', next.len_utf8()));
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                out.push_str("  ");
+                let mut prev = '\0';
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        out.push('\n');
+                    } else {
+                        out.extend(std::iter::repeat_n(' ', next.len_utf8()));
+                    }
+                    if prev == '*' && next == '/' {
+                        break;
+                    }
+                    prev = next;
+                }
+            }
+            _ => out.push(c),
+        }
     }
+    out
+}
 
-    let is_ident_char = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+fn is_ident_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Byte range `[start, end)` of the identifier under `offset`.
+/// A cursor resting just after a word (at end of file or before punctuation)
+/// still resolves that word, as editors place point after the last character.
+pub fn identifier_range_at(source: &str, offset: usize) -> Option<(usize, usize)> {
+    let bytes = source.as_bytes();
+
+    let offset = if offset < bytes.len() && is_ident_char(bytes[offset]) {
+        offset
+    } else if offset > 0 && offset <= bytes.len() && is_ident_char(bytes[offset - 1]) {
+        offset - 1
+    } else {
+        return None;
+    };
 
     let mut start = offset;
     while start > 0 && is_ident_char(bytes[start - 1]) {
@@ -18,8 +90,81 @@ pub fn extract_identifier_at(source: &str, offset: usize) -> Option<String> {
     }
 
     if start < end {
-        Some(source[start..end].to_string())
+        Some((start, end))
     } else {
         None
     }
 }
+
+pub fn extract_identifier_at(source: &str, offset: usize) -> Option<String> {
+    let (start, end) = identifier_range_at(source, offset)?;
+    Some(source[start..end].to_string())
+}
+
+/// For a member access `base.member`, where `member_start` is the offset of
+/// `member`, return the `base` identifier
+pub fn member_access_base(source: &str, member_start: usize) -> Option<String> {
+    let bytes = source.as_bytes();
+
+    let mut dot = member_start;
+    while dot > 0 && bytes[dot - 1].is_ascii_whitespace() {
+        dot -= 1;
+    }
+    if dot == 0 || bytes[dot - 1] != b'.' {
+        return None;
+    }
+
+    let mut base_end = dot - 1;
+    while base_end > 0 && bytes[base_end - 1].is_ascii_whitespace() {
+        base_end -= 1;
+    }
+    if base_end == 0 {
+        return None;
+    }
+
+    extract_identifier_at(source, base_end - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_at_end_of_file_resolves_the_last_word() {
+        let source = "contract Token";
+
+        assert_eq!(identifier_range_at(source, source.len()), Some((9, 14)));
+        assert_eq!(extract_identifier_at(source, source.len()).as_deref(), Some("Token"));
+        assert_eq!(identifier_range_at("", 0), None);
+        assert_eq!(identifier_range_at("x = 1;\n", 7), None);
+    }
+
+    #[test]
+    fn only_a_leading_bom_is_stripped() {
+        assert_eq!(strip_bom("\u{feff}pragma solidity ^0.8.0;"), "pragma solidity ^0.8.0;");
+        assert_eq!(strip_bom("pragma solidity ^0.8.0;"), "pragma solidity ^0.8.0;");
+        assert_eq!(strip_bom("a\u{feff}"), "a\u{feff}");
+        assert_eq!(strip_bom("\u{feff}\u{feff}a"), "\u{feff}a");
+    }
+
+    #[test]
+    fn comments_are_blanked_in_place() {
+        let source = "a // \"x\"\nb /* c\n*/ d \"// e\" '/*'\n";
+        let blanked = blank_comments(source);
+
+        assert_eq!(blanked, "a       \nb     \n   d \"// e\" '/*'\n");
+        assert_eq!(blanked.len(), source.len());
+    }
+
+    #[test]
+    fn cursor_just_past_a_word_resolves_it() {
+        let source = "balances[owner] = amount;";
+
+        assert_eq!(extract_identifier_at(source, 8).as_deref(), Some("balances"));
+        assert_eq!(extract_identifier_at(source, 14).as_deref(), Some("owner"));
+        assert_eq!(extract_identifier_at(source, 24).as_deref(), Some("amount"));
+        // a word under the cursor wins over the one before it
+        assert_eq!(extract_identifier_at("a.b", 2).as_deref(), Some("b"));
+        assert_eq!(identifier_range_at(source, 16), None);
+    }
+}
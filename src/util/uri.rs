@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use lsp_types::Url;
+
+/// Drop the `\\?\` prefix `canonicalize` adds on Windows and upper-case the
+/// drive letter, so a file always maps to the same path and URI however it
+/// was reached. Paths are returned unchanged elsewhere.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+
+    let text = path.to_string_lossy();
+    let text = text.strip_prefix(r"\\?\").filter(|rest| rest.get(1..2) == Some(":")).unwrap_or(&text);
+    match text.get(1..2) {
+        Some(":") => PathBuf::from(format!("{}{}", text[..1].to_ascii_uppercase(), &text[1..])),
+        _ => PathBuf::from(text),
+    }
+}
+
+/// `path` with symlinks resolved when it exists, normalized
+pub fn canonical_path(path: &Path) -> PathBuf {
+    normalize_path(&path.canonicalize().unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// `file://` URI of an absolute path, e.g. `file:///C:/project/Foo.sol` on Windows
+pub fn path_to_uri(path: &Path) -> Option<String> {
+    Url::from_file_path(normalize_path(path)).ok().map(|uri| uri.to_string())
+}
+
+/// Re-encode a client `file://` URI the way the server builds them, so
+/// `file:///c%3A/x.sol` and `file:///C:/x.sol` name the same document.
+/// Other URIs are returned unchanged.
+pub fn normalize_uri(uri: &str) -> String {
+    Url::parse(uri)
+        .ok()
+        .filter(|u| u.scheme() == "file")
+        .and_then(|u| u.to_file_path().ok())
+        .and_then(|path| path_to_uri(&path))
+        .unwrap_or_else(|| uri.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn path_with_space_round_trips_through_its_uri() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = canonical_path(dir.path()).join("my project").join("Foo.sol");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "contract Foo {}\n").unwrap();
+
+        let uri = path_to_uri(&path).unwrap();
+        assert!(uri.contains("my%20project"), "{}", uri);
+
+        let decoded = Url::parse(&uri).unwrap().to_file_path().unwrap();
+        assert_eq!(decoded, path);
+        assert_eq!(fs::read_to_string(decoded).unwrap(), "contract Foo {}\n");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_paths_map_to_one_uri() {
+        let uri = path_to_uri(Path::new(r"C:\project\Foo.sol")).unwrap();
+        assert_eq!(uri, "file:///C:/project/Foo.sol");
+        assert_eq!(path_to_uri(Path::new(r"\\?\c:\project\Foo.sol")).unwrap(), uri);
+        assert_eq!(normalize_uri("file:///c%3A/project/Foo.sol"), uri);
+    }
+
+    #[test]
+    fn percent_encoded_client_uri_is_kept_equivalent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = canonical_path(dir.path()).join("a b").join("Foo.sol");
+        let uri = path_to_uri(&path).unwrap();
+
+        assert_eq!(normalize_uri(&uri), uri);
+        assert_eq!(normalize_uri("untitled:Foo.sol"), "untitled:Foo.sol");
+    }
+}